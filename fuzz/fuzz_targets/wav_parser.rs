@@ -0,0 +1,23 @@
+//! Feeds arbitrary bytes to the WAV parsers, which see untrusted network
+//! data. Run with `cargo fuzz run wav_parser` from the repository root.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The player is a binary crate, so the module is compiled in directly.
+#[allow(dead_code)]
+#[path = "../../src/wav.rs"]
+mod wav;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = wav::parse_wav_info(data);
+    let _ = wav::declared_sizes(data);
+    let _ = wav::validate(data);
+    let _ = wav::reconstruct_wav_file(data, &[1, 2, 3]);
+    if let Some((header, body)) = wav::extract_wav_header(data) {
+        let mut header = header.to_vec();
+        wav::patch_wav_sizes(&mut header, body.len() as u32);
+        let _ = wav::reconstruct_wav_file(&header, body);
+    }
+    wav::patch_wav_sizes(&mut data.to_vec(), 0);
+});
@@ -0,0 +1,87 @@
+use crate::pcm::Pcm;
+
+/// Most the gain is raised, so pauses and background noise aren't pulled
+/// up to the target.
+const MAX_GAIN: f64 = 10.0;
+
+/// `--agc`: evens out the loudness of a live stream by tracking a running
+/// RMS level across chunks and scaling each frame toward `--agc-target`.
+/// The level follows the signal with a time constant of `--agc-speed`, so
+/// short peaks pass through while a quiet or loud passage is brought
+/// toward the target within a few multiples of it. Chunks are processed
+/// as they're decoded, which keeps the state in one place however they
+/// end up queued.
+#[derive(Debug, Clone)]
+pub struct Agc {
+    /// Target RMS level, as a fraction of full scale.
+    target: f64,
+    /// Time constant of the level tracking, in seconds.
+    speed: f64,
+    /// Running mean square of the samples, as a fraction of full scale.
+    level: f64,
+}
+
+impl Agc {
+    /// `target_dbfs` is the RMS level aimed for, `speed` the time constant
+    /// in seconds. Starts at unity gain.
+    pub fn new(target_dbfs: f64, speed: f64) -> Self {
+        let target = 10f64.powf(target_dbfs / 20.0);
+        Self {
+            target,
+            speed,
+            level: target * target,
+        }
+    }
+
+    pub fn apply(&mut self, mut pcm: Pcm) -> Pcm {
+        let channels = usize::from(pcm.channels.max(1));
+        let rate = f64::from(pcm.sample_rate.max(1));
+        // One-pole smoothing coefficient per frame.
+        let alpha = 1.0 - (-1.0 / (self.speed * rate)).exp();
+        for frame in pcm.samples.chunks_mut(channels) {
+            let square = frame.iter().map(|&s| (f64::from(s) / 32768.0).powi(2)).sum::<f64>() / frame.len() as f64;
+            self.level += alpha * (square - self.level);
+            let gain = (self.target / self.level.sqrt().max(1e-9)).min(MAX_GAIN);
+            for sample in frame {
+                *sample = (f64::from(*sample) * gain).round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16;
+            }
+        }
+        pcm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square wave of `amplitude`, 0.5s of mono 8 kHz.
+    fn square(amplitude: i16) -> Pcm {
+        Pcm {
+            channels: 1,
+            sample_rate: 8000,
+            samples: (0..4000).map(|i| if i % 2 == 0 { amplitude } else { -amplitude }).collect(),
+        }
+    }
+
+    fn variance(levels: &[f32]) -> f32 {
+        let mean = levels.iter().sum::<f32>() / levels.len() as f32;
+        levels.iter().map(|level| (level - mean).powi(2)).sum::<f32>() / levels.len() as f32
+    }
+
+    #[test]
+    fn evens_out_loud_and_quiet_chunks() {
+        let mut agc = Agc::new(-20.0, 0.05);
+        let (mut before, mut after, mut settled) = (Vec::new(), Vec::new(), Vec::new());
+        for amplitude in [16000, 1000].repeat(4) {
+            let pcm = square(amplitude);
+            before.push(pcm.rms());
+            let mut pcm = agc.apply(pcm);
+            after.push(pcm.rms());
+            pcm.samples.drain(..2000);
+            settled.push(pcm.rms());
+        }
+        assert!(variance(&after) * 10.0 < variance(&before), "{:?}", after);
+        // Both settle near -20 dBFS by the second half of each chunk.
+        assert!(settled.iter().all(|&rms| (rms - 0.1).abs() < 0.015), "{:?}", settled);
+    }
+}
@@ -0,0 +1,97 @@
+/// Decodes Ascii85 (btoa / Adobe flavour) text. The `<~` and `~>`
+/// delimiters are optional, whitespace is ignored and `z` stands for four
+/// zero bytes.
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    let text = text.strip_prefix("<~").unwrap_or(text);
+    let text = text.strip_suffix("~>").unwrap_or(text);
+
+    let mut out = Vec::with_capacity(text.len() / 5 * 4);
+    let mut group = [0u8; 5];
+    let mut len = 0;
+    for (pos, c) in text.char_indices() {
+        match c {
+            c if c.is_ascii_whitespace() => {}
+            'z' if len == 0 => out.extend_from_slice(&[0; 4]),
+            'z' => return Err(format!("`z` inside a group at offset {}", pos)),
+            '!'..='u' => {
+                group[len] = c as u8 - b'!';
+                len += 1;
+                if len == 5 {
+                    out.extend_from_slice(&group_value(&group, pos)?.to_be_bytes());
+                    len = 0;
+                }
+            }
+            _ => return Err(format!("invalid character {:?} at offset {}", c, pos)),
+        }
+    }
+
+    match len {
+        0 => {}
+        1 => return Err("a final group needs at least two characters".to_string()),
+        _ => {
+            // A partial group is padded with the highest digit and the
+            // padding's share of the bytes dropped.
+            group[len..].fill(84);
+            let bytes = group_value(&group, text.len())?.to_be_bytes();
+            out.extend_from_slice(&bytes[..len - 1]);
+        }
+    }
+    Ok(out)
+}
+
+fn group_value(group: &[u8; 5], pos: usize) -> Result<u32, String> {
+    let value = group.iter().fold(0u64, |value, &digit| value * 85 + u64::from(digit));
+    u32::try_from(value).map_err(|_| format!("group ending at offset {} overflows 32 bits", pos))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes `data` with delimiters, using `z` for zero groups.
+    pub(crate) fn encode(data: &[u8]) -> String {
+        let mut out = String::from("<~");
+        for chunk in data.chunks(4) {
+            let mut bytes = [0u8; 4];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            let mut value = u32::from_be_bytes(bytes);
+            if value == 0 && chunk.len() == 4 {
+                out.push('z');
+                continue;
+            }
+            let mut digits = [0u8; 5];
+            for digit in digits.iter_mut().rev() {
+                *digit = (value % 85) as u8 + b'!';
+                value /= 85;
+            }
+            out.extend(digits[..chunk.len() + 1].iter().map(|&d| d as char));
+        }
+        out.push_str("~>");
+        out
+    }
+
+    #[test]
+    fn decodes_known_text() {
+        assert_eq!(decode("9jqo^").unwrap(), b"Man ");
+        assert_eq!(decode("<~87cURD]i,\"Ebo80~>").unwrap(), b"Hello World!");
+        assert_eq!(decode("z 9jqo\n^").unwrap(), b"\0\0\0\0Man ");
+        assert_eq!(decode("").unwrap(), b"");
+    }
+
+    #[test]
+    fn round_trips_every_tail_length() {
+        let data: Vec<u8> = (0..=255).chain([0, 0, 0, 0]).collect();
+        for len in 0..data.len() {
+            assert_eq!(decode(&encode(&data[..len])).unwrap(), &data[..len]);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        assert!(decode("9jqo^v").unwrap_err().starts_with("invalid character 'v'"));
+        assert!(decode("9jzqo").unwrap_err().starts_with("`z` inside a group"));
+        assert!(decode("9jqo^9").is_err());
+        assert!(decode("uuuuu").unwrap_err().contains("overflows"));
+    }
+}
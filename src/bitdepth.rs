@@ -0,0 +1,102 @@
+/// Sample widths `--out-bits` converts integer PCM between.
+pub const BIT_DEPTHS: [&str; 3] = ["16", "24", "32"];
+
+/// Whether `bits` is a width [`convert`] can read and write.
+pub fn supported(bits: u16) -> bool {
+    matches!(bits, 16 | 24 | 32)
+}
+
+/// Triangular (TPDF) dither noise for `--dither`, from a xorshift
+/// generator; it only has to be noise, not random.
+#[derive(Debug, Clone)]
+pub struct Dither {
+    state: u32,
+}
+
+impl Dither {
+    pub fn new() -> Self {
+        Self { state: 0x9E37_79B9 }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Noise spanning ±`lsb`, peaking at zero.
+    fn noise(&mut self, lsb: i64) -> i64 {
+        let uniform = |value: u32| ((i64::from(value) * lsb) >> 32) - lsb / 2;
+        uniform(self.next_u32()) + uniform(self.next_u32())
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Converts little-endian integer PCM from `from` to `to` bits per sample,
+/// rounding to the nearest step when narrowing. A trailing partial sample
+/// is dropped. Dither only applies when narrowing.
+pub fn convert(body: &[u8], from: u16, to: u16, mut dither: Option<&mut Dither>) -> Vec<u8> {
+    let (from_bytes, to_bytes) = (usize::from(from / 8), usize::from(to / 8));
+    // Samples are scaled to 32 bits, so one step of the output is `lsb`.
+    let lsb = 1i64 << (32 - to);
+    let (min, max) = (-(1i64 << (to - 1)), (1i64 << (to - 1)) - 1);
+
+    let mut out = Vec::with_capacity(body.len() / from_bytes * to_bytes);
+    for sample in body.chunks_exact(from_bytes) {
+        let mut value = i64::from(read_sample(sample)) << (32 - from);
+        if to < from {
+            if let Some(dither) = dither.as_deref_mut() {
+                value += dither.noise(lsb);
+            }
+        }
+        let scaled = (value + lsb / 2).div_euclid(lsb).clamp(min, max) as i32;
+        out.extend_from_slice(&scaled.to_le_bytes()[..to_bytes]);
+    }
+    out
+}
+
+/// Sign-extends one 2, 3 or 4-byte little-endian sample.
+fn read_sample(bytes: &[u8]) -> i32 {
+    let mut word = [0; 4];
+    word[4 - bytes.len()..].copy_from_slice(bytes);
+    i32::from_le_bytes(word) >> (8 * (4 - bytes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples_24(samples: &[i32]) -> Vec<u8> {
+        samples.iter().flat_map(|sample| sample.to_le_bytes()[..3].to_vec()).collect()
+    }
+
+    #[test]
+    fn narrows_24_bit_to_16() {
+        let body = samples_24(&[0x12_3456, 0x12_3480, 0x7F_FFFF, -0x80_0000, -1]);
+        let narrowed: Vec<i16> = convert(&body, 24, 16, None)
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(narrowed, vec![0x1234, 0x1235, i16::MAX, i16::MIN, 0]);
+
+        // Widening is exact and round-trips.
+        assert_eq!(convert(&convert(&body[..6], 24, 32, None), 32, 24, None), body[..6]);
+    }
+
+    #[test]
+    fn dither_stays_within_a_step() {
+        let body = samples_24(&[0x12_3456; 64]);
+        let mut dither = Dither::new();
+        let narrowed = convert(&body, 24, 16, Some(&mut dither));
+        for sample in narrowed.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]);
+            assert!((0x1233..=0x1235).contains(&sample), "{:#x}", sample);
+        }
+    }
+}
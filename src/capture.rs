@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory `--capture-ring` dumps are written under.
+pub const DUMP_DIR: &str = "crash_dump";
+
+/// The last few raw chunks, kept so a decode failure can be dumped together
+/// with the input that led up to it.
+pub struct CaptureRing {
+    capacity: usize,
+    chunks: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl CaptureRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            chunks: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Remembers chunk `index`, evicting the oldest once full.
+    pub fn push(&mut self, index: usize, data: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.chunks.len() == self.capacity {
+            self.chunks.pop_front();
+        }
+        self.chunks.push_back((index, data.to_vec()));
+    }
+
+    /// Writes the buffered chunks to `<root>/chunk-<failed>/`, naming the
+    /// failing one `chunk-<n>-FAILED.bin`. Returns the dump directory.
+    pub fn dump(&self, root: &Path, failed: usize) -> io::Result<PathBuf> {
+        let dir = root.join(format!("chunk-{:06}", failed));
+        fs::create_dir_all(&dir)?;
+        for (index, data) in &self.chunks {
+            let name = if *index == failed {
+                format!("chunk-{:06}-FAILED.bin", index)
+            } else {
+                format!("chunk-{:06}.bin", index)
+            };
+            fs::write(dir.join(name), data)?;
+        }
+        Ok(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+
+    #[test]
+    fn dumps_context_of_failed_decode() {
+        let mut ring = CaptureRing::new(3);
+        let chunks: Vec<Vec<u8>> = (1..=5).map(|i| vec![i; 4]).collect();
+        let mut failed = None;
+        for (i, chunk) in chunks.iter().enumerate() {
+            ring.push(i + 1, chunk);
+            if i == 4 {
+                // Not a WAV file, so this one fails.
+                assert!(Format::Wav.decoder(chunk.clone()).is_err());
+                failed = Some(i + 1);
+            }
+        }
+
+        let root = std::env::temp_dir().join(format!("capture-ring-{}", std::process::id()));
+        let dir = ring.dump(&root, failed.unwrap()).unwrap();
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["chunk-000003.bin", "chunk-000004.bin", "chunk-000005-FAILED.bin"]
+        );
+        assert_eq!(fs::read(dir.join("chunk-000005-FAILED.bin")).unwrap(), vec![5; 4]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use serde::Deserialize;
+use std::fs;
+
+/// Settings read by `--config` from a JSON file. Keys are the long option
+/// names; precedence is command line, then config file, then built-in
+/// defaults.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub playback: Option<String>,
+    pub verbose: Option<bool>,
+    pub timestamps: Option<String>,
+    pub limit_duration: Option<f64>,
+    pub format: Option<String>,
+    pub encoding: Option<String>,
+    pub json_pointer: Option<String>,
+    pub lenient_json: Option<bool>,
+    pub allow_comments: Option<bool>,
+    pub allow_multichannel: Option<bool>,
+    pub replace_on_error: Option<bool>,
+    pub error_silence_ms: Option<u64>,
+    pub warmup_ms: Option<u64>,
+    pub device: Vec<String>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| format!("invalid config {}: {}", path, e))
+    }
+
+    /// The settings as command-line arguments, leaving out the options for
+    /// which `on_command_line` is true so those keep their given values.
+    pub fn to_args(&self, on_command_line: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut value = |id: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|_| !on_command_line(id)) {
+                args.push(format!("--{}", id));
+                args.push(value);
+            }
+        };
+        value("playback", self.playback.clone());
+        value("timestamps", self.timestamps.clone());
+        value("limit-duration", self.limit_duration.map(|s| s.to_string()));
+        value("format", self.format.clone());
+        value("encoding", self.encoding.clone());
+        value("json-pointer", self.json_pointer.clone());
+        value("error-silence-ms", self.error_silence_ms.map(|ms| ms.to_string()));
+        value("warmup-ms", self.warmup_ms.map(|ms| ms.to_string()));
+
+        let flags = [
+            ("verbose", self.verbose),
+            ("lenient-json", self.lenient_json),
+            ("allow-comments", self.allow_comments),
+            ("allow-multichannel", self.allow_multichannel),
+            ("replace-on-error", self.replace_on_error),
+        ];
+        for (id, set) in flags {
+            if set == Some(true) && !on_command_line(id) {
+                args.push(format!("--{}", id));
+            }
+        }
+
+        if !on_command_line("device") {
+            for device in &self.device {
+                args.push("--device".to_string());
+                args.push(device.clone());
+            }
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::parser::ValueSource;
+    use std::time::Duration;
+
+    #[test]
+    fn command_line_overrides_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"playback": "wav", "warmup-ms": 50, "allow-comments": true, "device": ["a", "b"]}"#,
+        )
+        .unwrap();
+
+        let cli_args = ["jsonl_player", "--playback", "flac", "--device", "c"];
+        let given = crate::cli().get_matches_from(cli_args);
+        let mut args: Vec<String> = cli_args.iter().map(|arg| arg.to_string()).collect();
+        let from_config = config.to_args(|id| given.value_source(id) == Some(ValueSource::CommandLine));
+        args.splice(1..1, from_config);
+        let matches = crate::cli().get_matches_from(args);
+
+        assert_eq!(matches.get_one::<String>("playback").unwrap(), "flac");
+        assert_eq!(*matches.get_one::<u64>("warmup-ms").unwrap(), 50);
+        assert!(matches.get_flag("allow-comments"));
+        let devices: Vec<_> = matches.get_many::<String>("device").unwrap().collect();
+        assert_eq!(devices, vec!["c"]);
+        // Untouched by either: built-in default.
+        assert_eq!(matches.get_one::<String>("encoding").unwrap(), "base64");
+        assert_eq!(matches.get_one::<Duration>("limit-duration"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(serde_json::from_str::<Config>(r#"{"volume": 2}"#).is_err());
+    }
+}
@@ -0,0 +1,1625 @@
+use rodio::source::UniformSourceIterator;
+use rodio::Source;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::agc::Agc;
+use crate::capture::{self, CaptureRing};
+use crate::control::Control;
+use crate::cues::Cues;
+use crate::format::Format;
+use crate::framelog::FrameLog;
+use crate::hook::{AsrHook, ChunkHook};
+use crate::inject::Injector;
+use crate::input::Chunk;
+use crate::invert::{Invert, InvertChannel};
+use crate::limit::{Budget, DurationLimit};
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::marker::Marker;
+use crate::metadata::Metadata;
+use crate::pacing::Pacer;
+use crate::pcm::Pcm;
+use crate::playback::{AudioOutput, Tracked};
+use crate::reconstruct::{FrameAligner, Reconstructor};
+use crate::remap::{ChannelRemap, Remap};
+use crate::resample::Resampler;
+use crate::segment::Destination;
+use crate::seqgap::GapConcealer;
+use crate::spill::{Spill, WholeStream};
+use crate::stream::ChunkStream;
+use crate::wav::SampleLoop;
+use crate::{downmix, merge, mp3, silence, wav, BoxedSource};
+
+/// Turns received chunks into audio on an [`AudioOutput`], or into the
+/// `--out` file when there is a writer.
+pub struct Consumer {
+    pub playback_format: Format,
+    pub allow_multichannel: bool,
+    /// `--replace-on-error` fallback duration.
+    pub error_silence: Option<Duration>,
+    pub warmup: Option<Duration>,
+    /// `--start-delay`: wait this long before queueing anything. Chunks
+    /// arriving meanwhile stay buffered in the channel.
+    pub start_delay: Option<Duration>,
+    /// `--chunk-gap`: silence queued between consecutive chunks.
+    pub chunk_gap: Option<Duration>,
+    /// `--marker-every`: a beep queued ahead of every Nth chunk.
+    pub marker: Option<Marker>,
+    pub exit_on_empty: bool,
+    /// `--shutdown-timeout`: longest to wait for queued audio to drain.
+    pub shutdown_timeout: Option<Duration>,
+    pub limit: Option<DurationLimit>,
+    pub capture: Option<CaptureRing>,
+    pub writer: Option<Destination>,
+    pub manifest: Option<Manifest<BufWriter<File>>>,
+    /// `--frame-log`: the manifest entries as CSV, with levels and timings.
+    pub frame_log: Option<FrameLog<BufWriter<File>>>,
+    /// `--cues`: a WebVTT cue per decoded chunk.
+    pub cues: Option<Cues<BufWriter<File>>>,
+    pub hook: Option<ChunkHook>,
+    /// `--asr-cmd`, sent the PCM of every WAV chunk before it's played.
+    pub asr: Option<AsrHook>,
+    /// `--invert-channel`, applied to WAV chunks only.
+    pub invert: Option<InvertChannel>,
+    /// `--remap`, applied to WAV chunks only, before any downmix.
+    pub remap: Option<ChannelRemap>,
+    /// `--wav-validate`: refuse WAV chunks that aren't spec compliant.
+    pub wav_validate: bool,
+    /// `--strict`: stop at the first chunk `--wav-validate` refuses.
+    pub strict: bool,
+    /// `--trace-reconstruction`: log the start of each WAV handed to the decoder.
+    pub trace_wav: bool,
+    /// `--mp3-stream`: decode all chunks with one mp3 decoder.
+    pub mp3_stream: bool,
+    /// Chunks from `--right-input`, merged with the primary input as the
+    /// right channel.
+    pub right: Option<Receiver<Chunk>>,
+    /// `--interactive` key presses.
+    pub controls: Option<Receiver<Control>>,
+    /// `--repeat-on-underrun`: replay the last chunk at most this many times
+    /// in a row while the output has run dry waiting for input.
+    pub max_conceal: Option<usize>,
+    /// `--use-timestamps`: append chunks as far apart as their `ts` fields.
+    pub pacer: Option<Pacer>,
+    /// `--sink-queue-limit`: most chunks left queued on the output before
+    /// the consumer stops taking more from the channel.
+    pub sink_queue_limit: Option<usize>,
+    /// `--max-queued-duration`: most audio left queued on the output before
+    /// the consumer stops taking more from the channel.
+    pub max_queued: Option<Duration>,
+    /// `--reverse`: buffer the whole stream and play it backwards once
+    /// input ends.
+    pub reverse: bool,
+    /// `--loop`: buffer the whole stream and, once input ends, play it on
+    /// repeat, or just the loop region of the stream's first `smpl` chunk.
+    pub looping: bool,
+    /// `--spill-to-disk`: keep the `--reverse` or `--loop` buffer in a temp
+    /// file in this directory rather than in memory.
+    pub spill: Option<PathBuf>,
+    /// `--conceal-gaps`: silence standing in for chunks a jump in the
+    /// records' `seq` fields shows were lost.
+    pub conceal_gaps: Option<GapConcealer>,
+    /// Completes header-once WAV streams; `None` with `--no-reconstruct`.
+    pub reconstruct: Option<Reconstructor>,
+    /// `--align-frames`, applied to WAV chunks after reconstruction.
+    pub align: Option<FrameAligner>,
+    /// `--prepend-file` and `--append-file`, played before the first chunk
+    /// and after the last in the stream's layout.
+    pub intro: Option<Pcm>,
+    pub outro: Option<Pcm>,
+    /// Converts chunks to the output device's rate, per
+    /// `--resample-quality`; `None` without a device.
+    pub resample: Option<Resampler>,
+    /// `--agc`, applied to each decoded chunk in turn.
+    pub agc: Option<Agc>,
+    /// `--inject-file`: audio files spliced in between chunks.
+    pub inject: Option<Injector>,
+}
+
+/// Peak level, as a fraction of full scale, a chunk has to exceed to count
+/// as audible: about -60 dBFS.
+pub const SILENCE_PEAK: f32 = 0.001;
+
+/// Audio decodes attempted by [`Consumer::run`] and how many failed, for
+/// `--max-decode-error-rate`. Chunks written with `--out` or decoded as one
+/// `--mp3-stream` aren't counted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DecodeStats {
+    pub attempted: usize,
+    pub failed: usize,
+    /// Decoded chunks peaking above [`SILENCE_PEAK`].
+    pub audible: usize,
+    /// With `--wav-validate --strict`, the chunk that failed validation and
+    /// stopped the stream.
+    pub invalid_wav: Option<usize>,
+}
+
+impl DecodeStats {
+    /// Chunks decoded, yet none of them louder than [`SILENCE_PEAK`].
+    pub fn all_silent(&self) -> bool {
+        self.attempted > self.failed && self.audible == 0
+    }
+
+    /// Failed decodes as a percentage of those attempted.
+    pub fn error_rate(&self) -> f64 {
+        if self.attempted == 0 {
+            return 0.0;
+        }
+        self.failed as f64 * 100.0 / self.attempted as f64
+    }
+}
+
+impl Consumer {
+    /// A consumer with the same settings, for one `--serve` request. This
+    /// one is kept as a template and never run, so its duration limit and
+    /// pacing haven't started. Outputs and hooks that belong to a single
+    /// run, such as `--out` or `--manifest`, aren't carried over.
+    pub fn for_next_run(&self) -> Consumer {
+        Consumer {
+            playback_format: self.playback_format,
+            allow_multichannel: self.allow_multichannel,
+            error_silence: self.error_silence,
+            warmup: self.warmup,
+            start_delay: self.start_delay,
+            chunk_gap: self.chunk_gap,
+            marker: self.marker,
+            exit_on_empty: self.exit_on_empty,
+            shutdown_timeout: self.shutdown_timeout,
+            limit: self.limit.clone(),
+            capture: None,
+            writer: None,
+            manifest: None,
+            frame_log: None,
+            cues: None,
+            hook: None,
+            asr: None,
+            invert: self.invert,
+            remap: self.remap.clone(),
+            wav_validate: self.wav_validate,
+            strict: self.strict,
+            trace_wav: self.trace_wav,
+            mp3_stream: self.mp3_stream,
+            right: None,
+            controls: None,
+            max_conceal: self.max_conceal,
+            pacer: self.pacer.as_ref().map(|_| Pacer::new()),
+            sink_queue_limit: self.sink_queue_limit,
+            max_queued: self.max_queued,
+            reverse: self.reverse,
+            looping: self.looping,
+            spill: self.spill.clone(),
+            conceal_gaps: self.conceal_gaps.as_ref().map(|_| GapConcealer::new()),
+            reconstruct: self.reconstruct.clone(),
+            align: self.align.clone(),
+            intro: self.intro.clone(),
+            outro: self.outro.clone(),
+            resample: self.resample.as_ref().map(|resampler| Resampler::new(resampler.rate, resampler.quality)),
+            agc: self.agc.clone(),
+            inject: None,
+        }
+    }
+
+    /// Processes chunks until the sender hangs up or the duration limit is
+    /// reached, then waits for `output` to finish playing.
+    pub fn run<O: AudioOutput>(self, rx: Receiver<Chunk>, output: &mut O) -> DecodeStats {
+        let Consumer {
+            playback_format,
+            allow_multichannel,
+            error_silence,
+            mut warmup,
+            start_delay,
+            chunk_gap,
+            marker,
+            exit_on_empty,
+            shutdown_timeout,
+            mut limit,
+            mut capture,
+            mut writer,
+            mut manifest,
+            mut frame_log,
+            mut cues,
+            mut hook,
+            mut asr,
+            invert,
+            remap,
+            wav_validate,
+            strict,
+            trace_wav,
+            mp3_stream,
+            right,
+            controls,
+            max_conceal,
+            mut pacer,
+            sink_queue_limit,
+            max_queued,
+            reverse,
+            looping,
+            spill,
+            mut conceal_gaps,
+            mut reconstruct,
+            mut align,
+            mut intro,
+            outro,
+            mut resample,
+            mut agc,
+            mut inject,
+        } = self;
+
+        if let Some(delay) = start_delay {
+            info!("Delaying playback by {:?}", delay);
+            thread::sleep(delay);
+        }
+
+        if mp3_stream {
+            // One decoder over every chunk keeps the bit reservoir intact
+            // across chunk seams. Its length isn't known up front, so the
+            // duration limit trims it as a whole.
+            match Format::Mp3.stream_decoder(ChunkStream::new(rx)) {
+                Ok(decoder) => {
+                    info!("Decoding mp3 chunks as one stream");
+                    play(output, &mut limit, Box::new(decoder), Duration::MAX);
+                }
+                Err(e) => error!("Failed to start the mp3 stream decoder: {}", e),
+            }
+            wait_for(output, exit_on_empty, shutdown_timeout);
+            info!("Audio playback finished");
+            return DecodeStats::default();
+        }
+
+        // Keeps the length of what's queued, for --max-queued-duration.
+        let mut tracked = Tracked::new(output, max_queued);
+        let output = &mut tracked;
+        let mut chunk_count = 0;
+        let mut successful_chunks = 0;
+        let mut decodes = DecodeStats::default();
+        let mut last_layout = None;
+        let mut vbr_duration = None;
+        // With --interactive or --repeat-on-underrun, the samples last
+        // queued, for replaying.
+        let mut last_played: Option<Pcm> = None;
+        let mut conceal = max_conceal.map(Concealment::new);
+        let keep_last = controls.is_some() || conceal.is_some();
+        let mut right_ended = false;
+        // A chunk has been queued, so the next one gets a --chunk-gap.
+        let mut gap_due = false;
+        // With --reverse or --loop, everything decoded so far.
+        let mut buffered: Option<WholeStream> = None;
+        // With --loop, the first smpl loop, the rate it counts frames at and
+        // the frames buffered before the chunk it came with.
+        let mut sample_loop = None;
+        // The format and gain of the last chunk decoded, for the end of the
+        // stream the resampler holds back.
+        let mut last_chunk = None;
+
+        while let Some(Chunk {
+            data: decoded_data,
+            format,
+            gain,
+            ts,
+            seq,
+            encoded_len,
+        }) =
+            next_chunk(&rx, controls.as_ref(), conceal.as_mut(), output, &last_played)
+        {
+            chunk_count += 1;
+            
+            debug!("Processing audio chunk {}, size: {} bytes", chunk_count, decoded_data.len());
+
+            if let Some(pacer) = pacer.as_mut() {
+                let wait = pacer.wait(chunk_count, ts);
+                if !wait.is_zero() {
+                    debug!("Waiting {:?} for audio chunk {}'s timestamp", wait, chunk_count);
+                    thread::sleep(wait);
+                }
+            }
+            if let Some(limit) = sink_queue_limit {
+                if output.wait_for_room(limit, QUEUE_POLL_INTERVAL) {
+                    debug!("Waited for the output queue to drop below {} before audio chunk {}", limit, chunk_count);
+                }
+            }
+            if let Some(cap) = max_queued {
+                if output.wait_for_duration(QUEUE_POLL_INTERVAL) {
+                    debug!("Waited for the queued audio to drop below {:?} before audio chunk {}", cap, chunk_count);
+                }
+            }
+            
+            let format = format.unwrap_or(playback_format);
+            let decoded_data = match reconstruct.as_mut().filter(|_| format == Format::Wav) {
+                Some(reconstruct) => reconstruct.complete(chunk_count, decoded_data),
+                None => decoded_data,
+            };
+            let decoded_data = match align.as_mut().filter(|_| format == Format::Wav) {
+                Some(align) => align.align(chunk_count, decoded_data),
+                None => decoded_data,
+            };
+
+            if let Some(hook) = hook.as_mut() {
+                hook.write(chunk_count, &decoded_data);
+            }
+            if let Some(asr) = asr.as_mut() {
+                asr.write(chunk_count, &decoded_data);
+            }
+
+            if chunk_count == 1 && playback_format == Format::Mp3 && format == Format::Mp3 {
+                vbr_duration = mp3::xing_duration(&decoded_data);
+                match vbr_duration {
+                    Some(duration) => debug!("Xing/Info header announces {:?}", duration),
+                    None => info!("No Xing/Info header in the first mp3 chunk; total duration unknown"),
+                }
+            }
+            if chunk_count == 1 {
+                if let Some(metadata) = Metadata::read(format, &decoded_data) {
+                    metadata.log();
+                }
+            }
+            let mut entry = ManifestEntry {
+                index: chunk_count,
+                bytes: decoded_data.len(),
+                format: Format::detect(&decoded_data).map(Format::name),
+                peak: None,
+                decoded: false,
+                encoded_bytes: encoded_len,
+                rms: None,
+                decode_latency: None,
+                played: false,
+            };
+
+            if wav_validate && format == Format::Wav {
+                let problems = wav::validate(&decoded_data);
+                if !problems.is_empty() {
+                    for problem in &problems {
+                        warn!("Audio chunk {} fails WAV validation: {}", chunk_count, problem);
+                    }
+                    record(&mut manifest, &mut frame_log, &entry);
+                    if strict {
+                        decodes.invalid_wav = Some(chunk_count);
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(writer) = writer.as_mut() {
+                match writer.write_chunk(format, decoded_data) {
+                    Ok(()) => {
+                        successful_chunks += 1;
+                        entry.decoded = true;
+                        entry.played = true;
+                    }
+                    Err(e) => error!("Failed to write audio chunk {}: {}", chunk_count, e),
+                }
+                record(&mut manifest, &mut frame_log, &entry);
+                continue;
+            }
+
+            let wav_info = if format == Format::Wav {
+                if trace_wav {
+                    trace!("WAV bytes of chunk {}: {}", chunk_count, wav::hex_prefix(&decoded_data));
+                }
+                wav::parse_wav_info(&decoded_data)
+            } else {
+                None
+            };
+            if looping && sample_loop.is_none() {
+                if let (Some(info), Some(region)) = (wav_info, wav::sample_loop(&decoded_data)) {
+                    debug!("Audio chunk {} loops frames {} to {}", chunk_count, region.start, region.end);
+                    let offset = buffered.as_ref().map_or(0, WholeStream::frames);
+                    sample_loop = Some((region, info.sample_rate, offset));
+                }
+            }
+            if let Some(capture) = capture.as_mut() {
+                capture.push(chunk_count, &decoded_data);
+            }
+            // Take the right chunk even if the left one fails, so the two
+            // inputs stay in step.
+            let right_chunk = right.as_ref().map(|rx| rx.recv().ok());
+            if right_chunk == Some(None) && !right_ended {
+                warn!("Right input ended at chunk {}; the right channel is silent from here", chunk_count);
+                right_ended = true;
+            }
+            decodes.attempted += 1;
+            let decode_started = Instant::now();
+            let source = Pcm::decode(format, decoded_data)
+                .map_err(|e| e.to_string())
+                .and_then(|pcm| match right_chunk {
+                    Some(chunk) => {
+                        let right = chunk
+                            .map(|chunk| Pcm::decode(chunk.format.unwrap_or(playback_format), chunk.data))
+                            .transpose()
+                            .map_err(|e| format!("right input: {}", e))?;
+                        merge::stereo(chunk_count, pcm, right)
+                    }
+                    None => Ok(pcm),
+                })
+                .and_then(|pcm| {
+                    let pcm = match resample.as_mut() {
+                        Some(resampler) => resampler.apply(pcm),
+                        None => pcm,
+                    };
+                    let pcm = match agc.as_mut() {
+                        Some(agc) => agc.apply(pcm),
+                        None => pcm,
+                    };
+                    entry.peak = Some(pcm.peak());
+                    entry.rms = Some(pcm.rms());
+                    shape(pcm, format == Format::Wav, remap.as_ref(), invert, allow_multichannel, gain)
+                });
+            entry.decode_latency = Some(decode_started.elapsed());
+            entry.decoded = source.is_ok();
+            if entry.peak.is_some_and(|peak| peak > SILENCE_PEAK) {
+                decodes.audible += 1;
+            }
+
+            if let Some(warmup) = warmup.take() {
+                debug!("Queueing {:?} of warm-up silence", warmup);
+                output.append(Box::new(silence::warmup(wav_info, warmup).source()));
+            }
+
+            if let Some(gaps) = conceal_gaps.as_mut() {
+                let missing = gaps.missing(chunk_count, seq);
+                if let Some(duration) = gaps.silence_for(missing) {
+                    info!(
+                        "{} audio chunks missing before chunk {}, queueing {:?} of silence in their place",
+                        missing, chunk_count, duration
+                    );
+                    let layout = match &source {
+                        Ok((source, _)) => Some((source.channels(), source.sample_rate())),
+                        Err(_) => last_layout,
+                    };
+                    if !queue_gap(output, &mut limit, Some(duration), layout) {
+                        record(&mut manifest, &mut frame_log, &entry);
+                        info!("Reached playback duration limit, stopping");
+                        break;
+                    }
+                }
+                if let Ok((_, duration)) = &source {
+                    gaps.record(*duration);
+                }
+            }
+
+            if let (Some(cues), Ok((_, duration))) = (cues.as_mut(), &source) {
+                // WAV chunks are timed by their fmt byte rate, like the stream.
+                let duration = wav_info.map_or(*duration, |info| info.duration());
+                if let Err(e) = cues.record(chunk_count, duration) {
+                    error!("Failed to write the cue for chunk {}: {}", chunk_count, e);
+                }
+            }
+
+            let keep_going = match source {
+                Ok((source, duration)) => {
+                    successful_chunks += 1;
+                    last_chunk = Some((format, gain));
+                    debug!("Successfully decoded audio chunk {}", chunk_count);
+                    last_layout = Some((source.channels(), source.sample_rate()));
+                    if let Some(intro) = intro.take() {
+                        queue_file_audio(output, &mut limit, "intro", intro, last_layout);
+                    }
+                    if duration.is_zero() {
+                        debug!("Audio chunk {} holds no samples, not queueing it", chunk_count);
+                        true
+                    } else if reverse || looping {
+                        buffer(
+                            &mut buffered,
+                            spill.as_deref(),
+                            chunk_count,
+                            source,
+                            if reverse { "--reverse" } else { "--loop" },
+                        );
+                        true
+                    } else if !queue_gap(output, &mut limit, chunk_gap.filter(|_| gap_due), last_layout)
+                        || !queue_marker(output, &mut limit, marker.filter(|marker| marker.due(chunk_count)))
+                    {
+                        false
+                    } else if keep_last {
+                        gap_due = true;
+                        entry.played = true;
+                        let pcm = Pcm {
+                            channels: source.channels(),
+                            sample_rate: source.sample_rate(),
+                            samples: source.collect(),
+                        };
+                        let keep_going = play(output, &mut limit, Box::new(pcm.clone().into_source()), duration);
+                        last_played = Some(pcm);
+                        if let Some(controls) = &controls {
+                            handle_controls(controls, output, &last_played);
+                        }
+                        keep_going
+                    } else {
+                        gap_due = true;
+                        entry.played = true;
+                        play(output, &mut limit, source, duration)
+                    }
+                }
+                Err(e) => {
+                    decodes.failed += 1;
+                    error!("Failed to decode audio chunk {}: {}", chunk_count, e);
+                    if let Some(capture) = &capture {
+                        match capture.dump(Path::new(capture::DUMP_DIR), chunk_count) {
+                            Ok(dir) => info!("Dumped recent chunks to {}", dir.display()),
+                            Err(e) => error!("Failed to dump recent chunks: {}", e),
+                        }
+                    }
+                    match error_silence {
+                        Some(fallback) => {
+                            let silence = silence::replacement_for(wav_info, last_layout, fallback);
+                            debug!("Replacing audio chunk {} with {:?} of silence", chunk_count, silence.duration);
+                            play(output, &mut limit, Box::new(silence.source()), silence.duration)
+                        }
+                        None => true,
+                    }
+                }
+            };
+
+            record(&mut manifest, &mut frame_log, &entry);
+
+            if let Some(pcm) = inject.as_mut().and_then(Injector::poll) {
+                queue_file_audio(output, &mut limit, "injected file", pcm, last_layout);
+            }
+
+            if !keep_going {
+                info!("Reached playback duration limit, stopping");
+                break;
+            }
+        }
+
+        let tail = resample.as_mut().and_then(Resampler::flush).zip(last_chunk);
+        if let Some((pcm, (format, gain))) = tail {
+            let pcm = match agc.as_mut() {
+                Some(agc) => agc.apply(pcm),
+                None => pcm,
+            };
+            match shape(pcm, format == Format::Wav, remap.as_ref(), invert, allow_multichannel, gain) {
+                Ok((source, _)) if reverse || looping => {
+                    buffer(&mut buffered, spill.as_deref(), chunk_count, source, if reverse { "--reverse" } else { "--loop" });
+                }
+                Ok((source, duration)) => {
+                    play(output, &mut limit, source, duration);
+                }
+                Err(e) => error!("Failed to queue the end of the resampled stream: {}", e),
+            }
+        }
+
+        match buffered {
+            Some(stream) if reverse => {
+                let duration = stream.duration();
+                info!("Playing {:.1}s of buffered audio in reverse", duration.as_secs_f64());
+                match stream.into_reversed() {
+                    Ok(source) => {
+                        play(output, &mut limit, source, duration);
+                    }
+                    Err(e) => error!("Failed to read back the buffered audio: {}", e),
+                }
+            }
+            Some(stream) => play_looped(output, &mut limit, stream, sample_loop),
+            None => {}
+        }
+        // Nothing decoded: the intro hasn't played yet either.
+        for (name, pcm) in [("intro", intro), ("outro", outro)] {
+            if let Some(pcm) = pcm {
+                queue_file_audio(output, &mut limit, name, pcm, last_layout);
+            }
+        }
+
+        if let Some(hook) = hook {
+            hook.finish();
+        }
+        if let Some(asr) = asr {
+            asr.finish();
+        }
+
+        if let Some(writer) = writer {
+            if let Err(e) = writer.finish() {
+                error!("Failed to finish output file: {}", e);
+            }
+        }
+
+        info!("Processed {} audio chunks total ({} successful)", chunk_count, successful_chunks);
+        if playback_format == Format::Mp3 {
+            match vbr_duration {
+                Some(duration) => info!("Stream duration (from Xing/Info header): {:.1}s", duration.as_secs_f64()),
+                None => info!("Stream duration: unknown"),
+            }
+        }
+        
+        // Wait for the last sound to finish playing.
+        wait_for(output, exit_on_empty, shutdown_timeout);
+        
+        info!("Audio playback finished");
+        decodes
+    }
+}
+
+/// Adds chunk `index` to the `--reverse` or `--loop` buffer, which is
+/// spilled to a file in `spill` if given. Its layout is set by the first
+/// chunk; chunks in another layout are dropped.
+fn buffer(buffer: &mut Option<WholeStream>, spill: Option<&Path>, index: usize, source: BoxedSource, flag: &str) {
+    let (channels, sample_rate) = (source.channels(), source.sample_rate());
+    let stream = buffer.get_or_insert_with(|| {
+        let memory = || {
+            WholeStream::Memory(Pcm {
+                channels,
+                sample_rate,
+                samples: Vec::new(),
+            })
+        };
+        match spill.map(|dir| Spill::create(dir, channels, sample_rate)) {
+            Some(Ok(spill)) => WholeStream::Disk(spill),
+            Some(Err(e)) => {
+                error!("Failed to create a spill file, buffering in memory instead: {}", e);
+                memory()
+            }
+            None => memory(),
+        }
+    });
+    if (stream.channels(), stream.sample_rate()) != (channels, sample_rate) {
+        warn!(
+            "Audio chunk {} is {} channel {} Hz, not {} channel {} Hz like the first; leaving it out of {}",
+            index,
+            channels,
+            sample_rate,
+            stream.channels(),
+            stream.sample_rate(),
+            flag
+        );
+        return;
+    }
+    if let Err(e) = stream.extend(source) {
+        error!("Failed to spill audio chunk {} to disk: {}", index, e);
+    }
+}
+
+/// Plays the `--loop` buffer until the duration limit, or forever. With a
+/// `smpl` loop, counted in frames at `rate` from `offset` frames into the
+/// buffer, the audio up to the loop plays once and only the loop region
+/// repeats; one outside the audio falls back to looping all of it.
+fn play_looped<O: AudioOutput>(
+    output: &mut O,
+    limit: &mut Option<DurationLimit>,
+    stream: WholeStream,
+    sample_loop: Option<(SampleLoop, u32, usize)>,
+) {
+    let frames = stream.frames();
+    // Resampled chunks count frames at the output rate.
+    let to_frame =
+        |frame: u32, rate: u32| (u64::from(frame) * u64::from(stream.sample_rate()) / u64::from(rate.max(1))) as usize;
+    let region = sample_loop
+        .map(|(region, rate, offset)| (offset + to_frame(region.start, rate), offset + to_frame(region.end, rate)))
+        .filter(|&(start, end)| {
+            let fits = start <= end && end < frames;
+            if !fits {
+                warn!("The smpl loop doesn't fit the {} frames of audio; looping all of it", frames);
+            }
+            fits
+        });
+    let total = stream.duration().as_secs_f64();
+    let (start, end) = match region {
+        Some((start, end)) => {
+            info!("Looping frames {} to {} of {:.1}s of buffered audio", start, end, total);
+            (start, end)
+        }
+        None if frames == 0 => return,
+        None => {
+            info!("Looping {:.1}s of buffered audio", total);
+            (0, frames - 1)
+        }
+    };
+    let lead_in_duration = Duration::from_secs_f64(start as f64 / f64::from(stream.sample_rate().max(1)));
+    let (lead_in, looped) = match stream.into_loop(start, end) {
+        Ok(sources) => sources,
+        Err(e) => {
+            error!("Failed to read back the buffered audio: {}", e);
+            return;
+        }
+    };
+    if !lead_in_duration.is_zero() && !play(output, limit, lead_in, lead_in_duration) {
+        return;
+    }
+    play(output, limit, looped, Duration::MAX);
+}
+
+/// Queues `gap` of silence in `layout`, that of the chunk about to be
+/// queued. Returns `false` once the duration limit has been reached.
+fn queue_gap<O: AudioOutput>(
+    output: &mut O,
+    limit: &mut Option<DurationLimit>,
+    gap: Option<Duration>,
+    layout: Option<(u16, u32)>,
+) -> bool {
+    let (Some(duration), Some((channels, sample_rate))) = (gap, layout) else {
+        return true;
+    };
+    let gap = silence::Silence {
+        channels,
+        sample_rate,
+        duration,
+    };
+    debug!("Queueing a {:?} gap between chunks", duration);
+    play(output, limit, Box::new(gap.source()), duration)
+}
+
+/// Queues `--prepend-file` or `--append-file` audio, converted to the
+/// stream's channels and sample rate when they differ.
+fn queue_file_audio<O: AudioOutput>(
+    output: &mut O,
+    limit: &mut Option<DurationLimit>,
+    name: &str,
+    pcm: Pcm,
+    layout: Option<(u16, u32)>,
+) {
+    let duration = pcm.duration();
+    let source: BoxedSource = match layout {
+        Some((channels, sample_rate)) if (channels, sample_rate) != (pcm.channels, pcm.sample_rate) => {
+            debug!(
+                "Converting the {} from {} channels at {} Hz to {} channels at {} Hz",
+                name, pcm.channels, pcm.sample_rate, channels, sample_rate
+            );
+            Box::new(UniformSourceIterator::<_, i16>::new(pcm.into_source(), channels, sample_rate))
+        }
+        _ => Box::new(pcm.into_source()),
+    };
+    info!("Queueing the {} ({:.1}s)", name, duration.as_secs_f64());
+    play(output, limit, source, duration);
+}
+
+/// Queues the `--marker-every` beep, if any. Returns `false` once the
+/// duration limit has been reached.
+fn queue_marker<O: AudioOutput>(output: &mut O, limit: &mut Option<DurationLimit>, marker: Option<Marker>) -> bool {
+    let Some(marker) = marker else {
+        return true;
+    };
+    debug!("Queueing a marker beep");
+    play(output, limit, marker.source(), marker.duration)
+}
+
+/// Queues `source` while honouring `--limit-duration`, trimming the chunk
+/// that crosses the limit. Returns `false` once the limit has been reached.
+fn play<O: AudioOutput>(
+    output: &mut O,
+    limit: &mut Option<DurationLimit>,
+    source: BoxedSource,
+    duration: Duration,
+) -> bool {
+    let Some(limit) = limit.as_mut() else {
+        output.append(source);
+        return true;
+    };
+
+    match limit.admit(duration) {
+        Budget::Whole => output.append(source),
+        Budget::Partial(rest) => output.append(Box::new(source.take_duration(rest))),
+        Budget::Exhausted => {}
+    }
+
+    !limit.is_exhausted()
+}
+
+/// How often pending controls, and with `--repeat-on-underrun` whether
+/// the output has run dry, are checked while waiting for a chunk.
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Receives the next chunk, handling controls and underruns while waiting.
+fn next_chunk<O: AudioOutput>(
+    rx: &Receiver<Chunk>,
+    controls: Option<&Receiver<Control>>,
+    mut conceal: Option<&mut Concealment>,
+    output: &mut O,
+    last_played: &Option<Pcm>,
+) -> Option<Chunk> {
+    if controls.is_none() && conceal.is_none() {
+        return rx.recv().ok();
+    }
+    loop {
+        match rx.recv_timeout(CONTROL_POLL_INTERVAL) {
+            Ok(chunk) => {
+                if let Some(conceal) = conceal {
+                    conceal.repeats = 0;
+                }
+                return Some(chunk);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(controls) = controls {
+                    handle_controls(controls, output, last_played);
+                }
+                if let Some(conceal) = conceal.as_deref_mut() {
+                    conceal.fill(output, last_played);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Bounds how often `--repeat-on-underrun` replays the last chunk before
+/// the input catches up.
+struct Concealment {
+    max_repeats: usize,
+    repeats: usize,
+}
+
+impl Concealment {
+    fn new(max_repeats: usize) -> Self {
+        Self { max_repeats, repeats: 0 }
+    }
+
+    /// Queues the last chunk again if the output has run dry and the
+    /// repeat budget isn't used up.
+    fn fill<O: AudioOutput>(&mut self, output: &mut O, last_played: &Option<Pcm>) {
+        let Some(pcm) = last_played else {
+            return;
+        };
+        if self.repeats >= self.max_repeats || !output.empty() {
+            return;
+        }
+        self.repeats += 1;
+        info!("Input underrun, repeating the last chunk ({}/{})", self.repeats, self.max_repeats);
+        output.append(Box::new(pcm.clone().into_source()));
+    }
+}
+
+fn handle_controls<O: AudioOutput>(controls: &Receiver<Control>, output: &mut O, last_played: &Option<Pcm>) {
+    for control in controls.try_iter() {
+        match (control, last_played) {
+            (Control::Replay, Some(pcm)) => {
+                info!("Replaying the last chunk ({:.2}s)", pcm.duration().as_secs_f64());
+                output.append(Box::new(pcm.clone().into_source()));
+            }
+            (Control::Replay, None) => info!("Nothing has played yet to replay"),
+        }
+    }
+}
+
+fn wait_for<O: AudioOutput>(output: &O, exit_on_empty: bool, shutdown_timeout: Option<Duration>) {
+    if let Some(timeout) = shutdown_timeout {
+        if !output.wait_until_empty_within(EMPTY_POLL_INTERVAL, timeout) {
+            warn!(
+                "Playback didn't finish within {:?} (--shutdown-timeout); queued audio may not have played",
+                timeout
+            );
+        }
+    } else if exit_on_empty {
+        output.wait_until_empty(EMPTY_POLL_INTERVAL);
+    } else {
+        output.sleep_until_end();
+    }
+}
+
+/// How often `--exit-on-empty` checks whether the sink has drained.
+const EMPTY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often a full output queue is checked for room.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Turns a decoded chunk into the source queued for it: `--remap` and
+/// `--invert-channel` for WAV chunks, the downmix, then the record's gain.
+fn shape(
+    pcm: Pcm,
+    wav: bool,
+    remap: Option<&ChannelRemap>,
+    invert: Option<InvertChannel>,
+    allow_multichannel: bool,
+    gain: Option<f32>,
+) -> Result<(BoxedSource, Duration), String> {
+    let duration = pcm.duration();
+    let source: BoxedSource = match remap.filter(|_| wav) {
+        Some(remap) => Box::new(Remap::new(pcm.into_source(), remap)?),
+        None => Box::new(pcm.into_source()),
+    };
+    downmix::fit_channels(source, allow_multichannel).map(|source| {
+        let source = match invert.filter(|_| wav) {
+            Some(channel) => Box::new(Invert::new(source, channel)),
+            None => source,
+        };
+        (apply_gain(source, gain), duration)
+    })
+}
+
+/// Scales a chunk by its record's `gain`, if it had one.
+fn apply_gain(source: BoxedSource, gain: Option<f32>) -> BoxedSource {
+    match gain {
+        Some(gain) => Box::new(source.amplify(gain)),
+        None => source,
+    }
+}
+
+fn record<W: io::Write>(
+    manifest: &mut Option<Manifest<W>>,
+    frame_log: &mut Option<FrameLog<W>>,
+    entry: &ManifestEntry,
+) {
+    if let Some(manifest) = manifest {
+        if let Err(e) = manifest.record(entry) {
+            error!("Failed to write manifest entry for chunk {}: {}", entry.index, e);
+        }
+    }
+    if let Some(log) = frame_log {
+        if let Err(e) = log.record(entry) {
+            error!("Failed to write frame log row for chunk {}: {}", entry.index, e);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::playback::tests::RecordingOutput;
+    use crate::resample::Quality;
+    use crate::wav::tests::wav_file;
+    use std::sync::mpsc;
+
+    pub(crate) fn consumer() -> Consumer {
+        Consumer {
+            playback_format: Format::Wav,
+            allow_multichannel: false,
+            error_silence: None,
+            warmup: None,
+            start_delay: None,
+            chunk_gap: None,
+            marker: None,
+            exit_on_empty: false,
+            shutdown_timeout: None,
+            limit: None,
+            capture: None,
+            writer: None,
+            manifest: None,
+            frame_log: None,
+            cues: None,
+            hook: None,
+            asr: None,
+            invert: None,
+            remap: None,
+            wav_validate: false,
+            strict: false,
+            trace_wav: false,
+            mp3_stream: false,
+            right: None,
+            controls: None,
+            max_conceal: None,
+            pacer: None,
+            sink_queue_limit: None,
+            max_queued: None,
+            reverse: false,
+            looping: false,
+            spill: None,
+            conceal_gaps: None,
+            reconstruct: Some(Reconstructor::new(None)),
+            align: None,
+            intro: None,
+            outro: None,
+            resample: None,
+            agc: None,
+            inject: None,
+        }
+    }
+
+    fn chunk(data: Vec<u8>) -> Chunk {
+        Chunk {
+            encoded_len: data.len(),
+            data,
+            format: None,
+            gain: None,
+            ts: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn plays_multi_chunk_wav_stream() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(vec![0; 3])).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        consumer().run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn resampled_chunks_end_with_the_held_back_frames() {
+        let bytes = |samples: &[i16]| samples.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<u8>>();
+        let samples: Vec<i16> = (0..40).map(|i| i * 100).collect();
+        let resampler = Resampler::new(16000, Quality::Medium);
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &bytes(&samples[..25])))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &bytes(&samples[25..])))).unwrap();
+        drop(tx);
+        let mut output = RecordingOutput::default();
+        Consumer {
+            resample: Some(resampler.clone()),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &bytes(&samples)))).unwrap();
+        drop(tx);
+        let mut whole = RecordingOutput::default();
+        Consumer {
+            resample: Some(resampler),
+            ..consumer()
+        }
+        .run(rx, &mut whole);
+
+        // Each chunk, then the frames its last one held back.
+        assert_eq!(output.chunks.len(), 3);
+        assert_eq!(output.chunks.concat(), whole.chunks.concat());
+        assert_eq!(whole.chunks.concat().len(), 80);
+    }
+
+    #[test]
+    fn chunk_gap_separates_chunks() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            chunk_gap: Some(Duration::from_millis(1)),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![0; 8], vec![3]]);
+    }
+
+    #[test]
+    fn conceal_gaps_fills_skipped_sequence_numbers() {
+        let (tx, rx) = mpsc::channel();
+        for (seq, body) in [(1, [1, 0, 2, 0]), (2, [3, 0, 4, 0]), (5, [5, 0, 6, 0])] {
+            tx.send(Chunk {
+                seq: Some(seq),
+                ..chunk(wav_file(1, 8000, &body))
+            })
+            .unwrap();
+        }
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            conceal_gaps: Some(GapConcealer::new()),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        // Seqs 3 and 4 are missing: two chunks of the average two frames.
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![3, 4], vec![0; 4], vec![5, 6]]);
+    }
+
+    #[test]
+    fn plays_header_once_wav_stream() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(vec![3, 0])).unwrap();
+        tx.send(chunk(vec![4, 0, 5, 0])).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        consumer().run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn intro_and_outro_play_around_the_stream() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[2, 0]))).unwrap();
+        drop(tx);
+
+        let file = |samples: Vec<i16>| Pcm {
+            channels: 1,
+            sample_rate: 8000,
+            samples,
+        };
+        let mut output = RecordingOutput::default();
+        Consumer {
+            intro: Some(file(vec![7, 7])),
+            // Stereo at another rate, converted to the stream's mono 8 kHz.
+            outro: Some(Pcm {
+                channels: 2,
+                sample_rate: 16000,
+                samples: vec![9, 9, 9, 9],
+            }),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks.len(), 4);
+        assert_eq!(output.chunks[..3], [vec![7, 7], vec![1], vec![2]]);
+        assert!(!output.chunks[3].is_empty() && output.chunks[3].iter().all(|&sample| sample == 9));
+    }
+
+    #[test]
+    fn reverse_plays_the_whole_stream_backwards() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(2, 8000, &[1, 0, 2, 0, 3, 0, 4, 0]))).unwrap();
+        tx.send(chunk(wav_file(2, 8000, &[5, 0, 6, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            reverse: true,
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        // One source, frames reversed, left and right still in place.
+        assert_eq!(output.chunks, vec![vec![5, 6, 3, 4, 1, 2]]);
+
+        // The same, read back from a spill file.
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(2, 8000, &[1, 0, 2, 0, 3, 0, 4, 0]))).unwrap();
+        tx.send(chunk(wav_file(2, 8000, &[5, 0, 6, 0]))).unwrap();
+        drop(tx);
+        let mut output = RecordingOutput::default();
+        Consumer {
+            reverse: true,
+            spill: Some(std::env::temp_dir()),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![5, 6, 3, 4, 1, 2]]);
+    }
+
+    #[test]
+    fn loop_repeats_the_smpl_region() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(crate::wav::tests::looped_file(&[1, 0, 2, 0, 3, 0, 4, 0], 1, 2))).unwrap();
+        drop(tx);
+
+        // Eight frames at 8 kHz, with room for rounding.
+        let mut output = RecordingOutput::default();
+        Consumer {
+            looping: true,
+            limit: Some(DurationLimit::new(Duration::from_micros(1060))),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        // Frame 0 once, then frames 1 and 2 over and over; frame 3 never plays.
+        assert_eq!(output.chunks, vec![vec![1], vec![2, 3, 2, 3, 2, 3, 2]]);
+
+        // A smpl chunk arriving with the second chunk counts from its start.
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(crate::wav::tests::looped_file(&[3, 0, 4, 0, 5, 0], 1, 1))).unwrap();
+        drop(tx);
+        let mut output = RecordingOutput::default();
+        Consumer {
+            looping: true,
+            limit: Some(DurationLimit::new(Duration::from_micros(1060))),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2, 3], vec![4, 4, 4, 4, 4]]);
+    }
+
+    #[test]
+    fn writes_a_cue_per_decoded_chunk() {
+        let (tx, rx) = mpsc::channel();
+        // 0.25s, then 0.5s of 16-bit mono at 8 kHz.
+        tx.send(chunk(wav_file(1, 8000, &[0; 4000]))).unwrap();
+        tx.send(chunk(b"not audio".to_vec())).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[0; 8000]))).unwrap();
+        drop(tx);
+
+        let path = std::env::temp_dir().join(format!("cues-{}.vtt", std::process::id()));
+        Consumer {
+            cues: Some(Cues::new(BufWriter::new(File::create(&path).unwrap())).unwrap()),
+            ..consumer()
+        }
+        .run(rx, &mut RecordingOutput::default());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            text,
+            "WEBVTT\n\n1\n00:00:00.000 --> 00:00:00.250\nchunk 1\n\n3\n00:00:00.250 --> 00:00:00.750\nchunk 3\n"
+        );
+    }
+
+    #[test]
+    fn plays_injected_files_between_chunks() {
+        let path = std::env::temp_dir().join(format!("inject-{}.wav", std::process::id()));
+        let inject = Injector::new(&path);
+        std::fs::write(&path, wav_file(1, 8000, &[9, 0, 9, 0])).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[2, 0]))).unwrap();
+        drop(tx);
+        let mut output = RecordingOutput::default();
+        Consumer {
+            inject: Some(inject),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        std::fs::remove_file(&path).unwrap();
+        // Played once, after the chunk queued when it showed up.
+        assert_eq!(output.chunks, vec![vec![1], vec![9, 9], vec![2]]);
+    }
+
+    #[test]
+    fn no_reconstruct_plays_complete_files_unchanged() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(wav_file(2, 8000, &[3, 0, 4, 0]))).unwrap();
+        // Bare PCM isn't given the first chunk's header.
+        tx.send(chunk(vec![5, 0])).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[6, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        let decodes = Consumer {
+            reconstruct: None,
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![3, 4], vec![6]]);
+        assert_eq!(decodes.failed, 1);
+    }
+
+    #[test]
+    fn counts_failed_decodes() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        tx.send(chunk(b"not audio".to_vec())).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[2, 0]))).unwrap();
+        drop(tx);
+
+        let decodes = consumer().run(rx, &mut RecordingOutput::default());
+        assert_eq!(decodes, DecodeStats { attempted: 3, failed: 1, audible: 0, invalid_wav: None });
+        assert!((decodes.error_rate() - 100.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn all_zero_stream_is_silent() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[0; 16]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[0; 16]))).unwrap();
+        drop(tx);
+        assert!(consumer().run(rx, &mut RecordingOutput::default()).all_silent());
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[0; 16]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[0, 0x40]))).unwrap();
+        drop(tx);
+        let decodes = consumer().run(rx, &mut RecordingOutput::default());
+        assert_eq!(decodes.audible, 1);
+        assert!(!decodes.all_silent());
+    }
+
+    #[test]
+    fn marker_precedes_every_nth_chunk() {
+        let (tx, rx) = mpsc::channel();
+        for sample in 1..=4 {
+            tx.send(chunk(wav_file(1, 8000, &[sample, 0]))).unwrap();
+        }
+        drop(tx);
+
+        let marker = Marker {
+            every: 2,
+            frequency: 1000.0,
+            duration: Duration::from_millis(1),
+        };
+        let mut output = RecordingOutput::default();
+        Consumer {
+            marker: Some(marker),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        let beep: Vec<i16> = marker.source().collect();
+        assert_eq!(output.chunks, vec![vec![1], beep.clone(), vec![2], vec![3], beep, vec![4]]);
+    }
+
+    #[test]
+    fn sink_queue_limit_throttles_appends() {
+        /// Plays one queued chunk each time its queue is checked.
+        #[derive(Default)]
+        struct Draining {
+            inner: RecordingOutput,
+            queue: std::cell::Cell<usize>,
+            longest: usize,
+        }
+
+        impl AudioOutput for Draining {
+            fn append(&mut self, source: BoxedSource) {
+                self.inner.append(source);
+                self.queue.set(self.queue.get() + 1);
+                self.longest = self.longest.max(self.queue.get());
+            }
+
+            fn sleep_until_end(&self) {}
+
+            fn empty(&self) -> bool {
+                true
+            }
+
+            fn queued(&self) -> usize {
+                let queued = self.queue.get();
+                self.queue.set(queued.saturating_sub(1));
+                queued
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        for sample in 1..=4 {
+            tx.send(chunk(wav_file(1, 8000, &[sample, 0]))).unwrap();
+        }
+        drop(tx);
+
+        let mut output = Draining::default();
+        Consumer {
+            sink_queue_limit: Some(1),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.inner.chunks, vec![vec![1], vec![2], vec![3], vec![4]]);
+        // Without the limit the queue would have grown to all four chunks.
+        assert_eq!(output.longest, 1);
+    }
+
+    #[test]
+    fn max_queued_duration_waits_for_the_queue_to_play() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Plays nothing until the test says a number of sources have played.
+        #[derive(Default)]
+        struct Stalled {
+            appended: AtomicUsize,
+            played: AtomicUsize,
+        }
+
+        impl AudioOutput for Arc<Stalled> {
+            fn append(&mut self, _: BoxedSource) {
+                self.appended.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn sleep_until_end(&self) {}
+
+            fn empty(&self) -> bool {
+                self.queued() == 0
+            }
+
+            fn queued(&self) -> usize {
+                self.appended.load(Ordering::SeqCst).saturating_sub(self.played.load(Ordering::SeqCst))
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        for sample in 1..=4 {
+            tx.send(chunk(wav_file(1, 8000, &[sample, 0, sample, 0]))).unwrap();
+        }
+        drop(tx);
+        let shared = Arc::new(Stalled::default());
+        let mut output = Arc::clone(&shared);
+        let playing = std::thread::spawn(move || {
+            // 250 us chunks under a 400 us cap.
+            Consumer {
+                max_queued: Some(Duration::from_micros(400)),
+                ..consumer()
+            }
+            .run(rx, &mut output)
+        });
+
+        let wait_for = |appended: usize| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while shared.appended.load(Ordering::SeqCst) < appended && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+        wait_for(2);
+        std::thread::sleep(Duration::from_millis(50));
+        // Two chunks fill the cap; the third waits for one to play.
+        assert_eq!(shared.appended.load(Ordering::SeqCst), 2);
+        shared.played.store(4, Ordering::SeqCst);
+        wait_for(4);
+        assert_eq!(playing.join().unwrap().attempted, 4);
+    }
+
+    #[test]
+    fn start_delay_holds_back_first_chunk() {
+        struct FirstAppend(Option<std::time::Instant>);
+
+        impl AudioOutput for FirstAppend {
+            fn append(&mut self, _: BoxedSource) {
+                self.0.get_or_insert_with(std::time::Instant::now);
+            }
+
+            fn sleep_until_end(&self) {}
+
+            fn empty(&self) -> bool {
+                true
+            }
+
+            fn queued(&self) -> usize {
+                0
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        drop(tx);
+
+        let start = std::time::Instant::now();
+        let mut output = FirstAppend(None);
+        Consumer {
+            start_delay: Some(Duration::from_millis(50)),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert!(output.0.unwrap() - start >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn shutdown_timeout_stops_waiting_on_stuck_output() {
+        struct Stuck;
+
+        impl AudioOutput for Stuck {
+            fn append(&mut self, _: BoxedSource) {}
+
+            fn sleep_until_end(&self) {
+                panic!("blocking wait used despite --shutdown-timeout");
+            }
+
+            fn empty(&self) -> bool {
+                false
+            }
+
+            fn queued(&self) -> usize {
+                1
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        drop(tx);
+
+        let start = std::time::Instant::now();
+        Consumer {
+            shutdown_timeout: Some(Duration::from_millis(50)),
+            ..consumer()
+        }
+        .run(rx, &mut Stuck);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn replay_key_requeues_last_chunk() {
+        let (tx, rx) = mpsc::channel();
+        let (controls, control_rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        drop(tx);
+        controls.send(Control::Replay).unwrap();
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            controls: Some(control_rx),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn underrun_repeats_last_chunk() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        let sender = thread::spawn(move || {
+            // Starve the consumer for several polls.
+            thread::sleep(CONTROL_POLL_INTERVAL * 6);
+            tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        });
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            max_conceal: Some(2),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        sender.join().unwrap();
+        assert_eq!(output.chunks, vec![vec![1, 2], vec![1, 2], vec![1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn wav_validate_refuses_malformed_chunks() {
+        let mut bad = wav_file(1, 8000, &[2, 0]);
+        wav::write_u32_le(&mut bad, 4, 1000);
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(bad.clone())).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            wav_validate: true,
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1]]);
+        // Best effort plays it.
+        assert!(Format::Wav.decoder(bad.clone()).is_ok());
+
+        // Under --strict the stream stops at the bad chunk.
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        tx.send(chunk(bad)).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        drop(tx);
+        let mut output = RecordingOutput::default();
+        let decodes = Consumer {
+            wav_validate: true,
+            strict: true,
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1]]);
+        assert_eq!(decodes.invalid_wav, Some(2));
+    }
+
+    #[test]
+    fn right_input_becomes_right_channel() {
+        let (tx, rx) = mpsc::channel();
+        let (right_tx, right_rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0, 2, 0]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[3, 0]))).unwrap();
+        right_tx.send(chunk(wav_file(1, 8000, &[9, 0, 8, 0]))).unwrap();
+        drop((tx, right_tx));
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            right: Some(right_rx),
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1, 9, 2, 8], vec![3, 0]]);
+    }
+
+    #[test]
+    fn header_only_chunk_is_not_queued() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(chunk(wav_file(1, 8000, &[]))).unwrap();
+        tx.send(chunk(wav_file(1, 8000, &[1, 0]))).unwrap();
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        consumer().run(rx, &mut output);
+        assert_eq!(output.chunks, vec![vec![1]]);
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn mp3_stream_decodes_across_chunk_seams() {
+        let mp3 = &include_bytes!("../sample.mp3")[..32 * 1024];
+        let whole: Vec<i16> = Format::Mp3.decoder(mp3.to_vec()).unwrap().collect();
+
+        // Seams land mid-frame, where per-chunk decoders would lose audio.
+        let (tx, rx) = mpsc::channel();
+        for piece in mp3.chunks(3001) {
+            tx.send(chunk(piece.to_vec())).unwrap();
+        }
+        drop(tx);
+
+        let mut output = RecordingOutput::default();
+        Consumer {
+            playback_format: Format::Mp3,
+            mp3_stream: true,
+            ..consumer()
+        }
+        .run(rx, &mut output);
+        assert_eq!(output.chunks.len(), 1);
+        assert!(!whole.is_empty());
+        assert_eq!(output.chunks[0], whole);
+    }
+
+    #[test]
+    fn zero_gain_silences_chunk() {
+        let source: BoxedSource = Box::new(rodio::buffer::SamplesBuffer::new(1, 8000, vec![1000i16, -32768, 5]));
+        let samples: Vec<i16> = apply_gain(source, Some(0.0)).collect();
+        assert_eq!(samples, vec![0, 0, 0]);
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::mpsc::Sender;
+use std::thread;
+use tracing::{debug, warn};
+
+/// Terminal the `--interactive` keys are read from. Stdin carries the
+/// audio stream, so the controls can't come from there.
+#[cfg(unix)]
+const TTY: &str = "/dev/tty";
+#[cfg(windows)]
+const TTY: &str = "CONIN$";
+
+/// A request typed at the terminal while playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// `r`: play the most recent chunk again.
+    Replay,
+}
+
+impl Control {
+    /// Maps one line typed at the terminal to a control.
+    pub fn from_key(line: &str) -> Option<Control> {
+        match line.trim() {
+            "r" | "R" => Some(Control::Replay),
+            _ => None,
+        }
+    }
+}
+
+/// Reads controls from the terminal, one per line, and sends them to `tx`
+/// until the terminal closes or the receiver hangs up.
+pub fn spawn_tty(tx: Sender<Control>) -> io::Result<()> {
+    let tty = BufReader::new(File::open(TTY)?);
+    thread::spawn(move || {
+        for line in tty.lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match Control::from_key(&line) {
+                Some(control) => {
+                    debug!("Control: {:?}", control);
+                    if tx.send(control).is_err() {
+                        break;
+                    }
+                }
+                None => warn!("Unknown key {:?}; press r and Enter to replay the last chunk", line.trim()),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_keys() {
+        assert_eq!(Control::from_key("r"), Some(Control::Replay));
+        assert_eq!(Control::from_key(" R \r"), Some(Control::Replay));
+        assert_eq!(Control::from_key("x"), None);
+        assert_eq!(Control::from_key(""), None);
+    }
+}
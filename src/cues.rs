@@ -0,0 +1,67 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Writes a WebVTT cue per decoded chunk for `--cues`, timed by the
+/// stream's cumulative duration and captioned with the chunk index, so a
+/// transcript made elsewhere can be lined up with chunk positions. Each
+/// cue is flushed as it's written.
+pub struct Cues<W: Write> {
+    out: W,
+    /// Where the next cue starts.
+    elapsed: Duration,
+}
+
+impl<W: Write> Cues<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        writeln!(out, "WEBVTT")?;
+        out.flush()?;
+        Ok(Self {
+            out,
+            elapsed: Duration::ZERO,
+        })
+    }
+
+    pub fn record(&mut self, index: usize, duration: Duration) -> io::Result<()> {
+        let start = self.elapsed;
+        self.elapsed += duration;
+        write!(
+            self.out,
+            "\n{}\n{} --> {}\nchunk {}\n",
+            index,
+            timestamp(start),
+            timestamp(self.elapsed),
+            index
+        )?;
+        self.out.flush()
+    }
+}
+
+/// `HH:MM:SS.mmm`, as WebVTT wants it.
+fn timestamp(at: Duration) -> String {
+    let millis = at.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_cues_by_cumulative_duration() {
+        let mut cues = Cues::new(Vec::new()).unwrap();
+        cues.record(1, Duration::from_millis(1500)).unwrap();
+        cues.record(2, Duration::from_secs(3599)).unwrap();
+
+        let text = String::from_utf8(cues.out).unwrap();
+        assert_eq!(
+            text,
+            "WEBVTT\n\n1\n00:00:00.000 --> 00:00:01.500\nchunk 1\n\n2\n00:00:01.500 --> 01:00:00.500\nchunk 2\n"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use rodio::Source;
+use std::time::Duration;
+
+use crate::BoxedSource;
+
+/// Level applied to the centre and surround channels when folding them into
+/// the front pair (-3 dB, as in ITU-R BS.775).
+const SIDE_LEVEL: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Overall gain keeping the sum of three full-scale channels from clipping.
+const NORMALIZE: f32 = 1.0 / (1.0 + 2.0 * SIDE_LEVEL);
+
+/// Folds 5.1 audio (WAV channel order FL, FR, FC, LFE, BL, BR) down to
+/// stereo:
+///
+/// ```text
+/// L = (FL + 0.707 * FC + 0.707 * BL) * 0.414
+/// R = (FR + 0.707 * FC + 0.707 * BR) * 0.414
+/// ```
+///
+/// The LFE channel is dropped.
+pub struct Downmix<S> {
+    input: S,
+    right: Option<i16>,
+}
+
+impl<S> Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    /// Channel count this adapter knows how to fold down.
+    pub const INPUT_CHANNELS: u16 = 6;
+
+    pub fn new(input: S) -> Self {
+        debug_assert_eq!(input.channels(), Self::INPUT_CHANNELS);
+        Self { input, right: None }
+    }
+}
+
+impl<S> Iterator for Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+
+        let mut frame = [0f32; 6];
+        for sample in frame.iter_mut() {
+            *sample = self.input.next()? as f32;
+        }
+        let [fl, fr, fc, _lfe, bl, br] = frame;
+
+        let mix = |front: f32, back: f32| {
+            let value = (front + SIDE_LEVEL * (fc + back)) * NORMALIZE;
+            value.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        };
+        self.right = Some(mix(fr, br));
+        Some(mix(fl, bl))
+    }
+}
+
+impl<S> Source for Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len().map(|len| len / 3)
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// Makes the channel layout of a decoded chunk safe for a stereo device:
+/// 5.1 is folded down, other layouts above stereo are rejected. With
+/// `allow_multichannel` the source is passed through untouched.
+pub fn fit_channels<S>(
+    source: S,
+    allow_multichannel: bool,
+) -> Result<BoxedSource, String>
+where
+    S: Source<Item = i16> + Send + 'static,
+{
+    let channels = source.channels();
+    if channels <= 2 || allow_multichannel {
+        Ok(Box::new(source))
+    } else if channels == Downmix::<S>::INPUT_CHANNELS {
+        Ok(Box::new(Downmix::new(source)))
+    } else {
+        Err(format!(
+            "{} channels can't be downmixed to stereo, pass --allow-multichannel to play as is",
+            channels
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn folds_six_channel_frame_to_stereo() {
+        let frame = vec![10000, 2000, 4000, 30000, -6000, 8000];
+        let source = Downmix::new(SamplesBuffer::new(6, 48000, frame));
+        assert_eq!(source.channels(), 2);
+        assert_eq!(source.sample_rate(), 48000);
+
+        let left = (10000.0 + SIDE_LEVEL * (4000.0 - 6000.0)) * NORMALIZE;
+        let right = (2000.0 + SIDE_LEVEL * (4000.0 + 8000.0)) * NORMALIZE;
+        let out: Vec<i16> = source.collect();
+        assert_eq!(out, vec![left.round() as i16, right.round() as i16]);
+    }
+
+    #[test]
+    fn full_scale_does_not_wrap() {
+        let frame = vec![i16::MAX; 6];
+        let out: Vec<i16> = Downmix::new(SamplesBuffer::new(6, 48000, frame)).collect();
+        assert_eq!(out, vec![i16::MAX, i16::MAX]);
+    }
+
+    #[test]
+    fn fits_channel_layouts() {
+        let stereo = SamplesBuffer::new(2, 48000, vec![1i16, 2]);
+        assert_eq!(fit_channels(stereo, false).unwrap().channels(), 2);
+
+        let surround = SamplesBuffer::new(6, 48000, vec![0i16; 6]);
+        assert_eq!(fit_channels(surround, false).unwrap().channels(), 2);
+
+        let quad = SamplesBuffer::new(4, 48000, vec![0i16; 4]);
+        assert!(fit_channels(quad, false).is_err());
+
+        let quad = SamplesBuffer::new(4, 48000, vec![0i16; 4]);
+        assert_eq!(fit_channels(quad, true).unwrap().channels(), 4);
+    }
+
+    #[test]
+    fn drops_trailing_partial_frame() {
+        let samples = vec![0, 0, 0, 0, 0, 0, 1, 2, 3];
+        let out: Vec<i16> = Downmix::new(SamplesBuffer::new(6, 48000, samples)).collect();
+        assert_eq!(out.len(), 2);
+    }
+}
@@ -0,0 +1,88 @@
+use thiserror::Error;
+
+use crate::format::Format;
+
+/// Why a record or chunk couldn't be turned into audio. Kept typed so
+/// callers can tell a bad payload from a bad stream; the binary only
+/// formats them.
+#[derive(Debug, Error)]
+pub enum ChunkerError {
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    #[error(transparent)]
+    Hex(#[from] hex::FromHexError),
+    #[error("{0}")]
+    Ascii85(String),
+    /// A `--encoding json-bytes` payload that isn't an array of bytes.
+    #[error("{0}")]
+    JsonBytes(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A `--json-lines-strict` violation in an otherwise valid line.
+    #[error("strict: {0}")]
+    Strict(String),
+    /// A `--unwrap-json` field that's missing or doesn't hold a JSON record.
+    #[error("{0}")]
+    Unwrap(String),
+    /// Nothing where the payload should be: the `data` field or the
+    /// `--json-pointer` target, as named.
+    #[error("nothing at {0}")]
+    MissingPayload(String),
+    /// The payload field holds something else than a string.
+    #[error("{0} resolves to {1}, not a string")]
+    PayloadNotString(String, &'static str),
+    /// A chunk played as WAV that doesn't start with a RIFF/WAVE header.
+    #[error("not a RIFF/WAVE chunk")]
+    InvalidRiff,
+    /// The chunk's format isn't one this build can decode.
+    #[error("unsupported {0} chunk")]
+    UnsupportedFormat(Format),
+    /// Malformed data, as reported by symphonia, which only mp3 pulls in.
+    #[cfg(feature = "mp3")]
+    #[error(transparent)]
+    Decode(rodio::decoder::DecoderError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{decode_json_bytes, extract_record, parse_value, Encoding, InputOptions};
+    use crate::pcm::Pcm;
+
+    #[test]
+    fn payload_errors_keep_their_kind() {
+        let decode = |encoding: Encoding, data| encoding.decode_into(data, &mut Vec::new());
+        assert!(matches!(decode(Encoding::Base64, "not base64!"), Err(ChunkerError::Base64(_))));
+        assert!(matches!(decode(Encoding::Hex, "zz"), Err(ChunkerError::Hex(_))));
+        assert!(matches!(decode(Encoding::Ascii85, "<~\u{7f}~>"), Err(ChunkerError::Ascii85(_))));
+        assert!(matches!(decode(Encoding::JsonBytes, "[1]"), Err(ChunkerError::JsonBytes(_))));
+        let bytes = [serde_json::json!(300)];
+        assert!(matches!(decode_json_bytes(&bytes, &mut Vec::new()), Err(ChunkerError::JsonBytes(_))));
+    }
+
+    #[test]
+    fn json_errors_keep_their_kind() {
+        assert!(matches!(parse_value("{", false), Err(ChunkerError::Json(_))));
+        assert!(matches!(parse_value("{} {}", true), Err(ChunkerError::Strict(_))));
+    }
+
+    #[test]
+    fn payload_field_errors_keep_their_kind() {
+        let extract = |value| extract_record(value, &InputOptions::default());
+        assert!(matches!(extract(serde_json::json!({})), Err(ChunkerError::MissingPayload(_))));
+        assert!(matches!(
+            extract(serde_json::json!({ "data": 1 })),
+            Err(ChunkerError::PayloadNotString(_, "a number"))
+        ));
+    }
+
+    #[test]
+    fn decode_errors_keep_their_kind() {
+        assert!(matches!(Pcm::decode(Format::Wav, b"OggS\0\0\0\0".to_vec()), Err(ChunkerError::InvalidRiff)));
+        // Compiled-out formats and data no decoder recognises look the same.
+        assert!(matches!(
+            Pcm::decode(Format::Mp3, vec![0; 64]),
+            Err(ChunkerError::UnsupportedFormat(Format::Mp3))
+        ));
+    }
+}
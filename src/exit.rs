@@ -0,0 +1,77 @@
+use std::fmt;
+use std::process::ExitCode;
+
+/// Exit codes: failures are tagged by attaching one of these as context, so
+/// the top line of the error names the category. Untagged errors exit with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Failure,
+    /// Records that failed to parse under `--json-lines-strict`, or a
+    /// silent stream or malformed WAV chunk under `--strict`.
+    Input,
+    /// No audio output could be opened.
+    Device,
+    /// The input stream stalled past `--input-timeout`.
+    Network,
+    /// `--config` couldn't be read or parsed.
+    Config,
+}
+
+/// Shown by `--help`.
+pub const TABLE: &str = "\
+Exit codes:
+  0  success
+  1  any other failure
+  2  malformed input under --json-lines-strict, a silent stream or malformed WAV under --strict, or invalid command-line usage
+  3  the audio device couldn't be opened
+  4  the input stream stalled (--input-timeout)
+  5  the --config file couldn't be read or parsed";
+
+impl Exit {
+    pub fn code(self) -> u8 {
+        match self {
+            Exit::Failure => 1,
+            Exit::Input => 2,
+            Exit::Device => 3,
+            Exit::Network => 4,
+            Exit::Config => 5,
+        }
+    }
+
+    /// The category `error` was tagged with.
+    pub fn of(error: &anyhow::Error) -> Exit {
+        error.downcast_ref::<Exit>().copied().unwrap_or(Exit::Failure)
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Exit::Failure => "playback failed",
+            Exit::Input => "malformed input",
+            Exit::Device => "audio device initialization failed",
+            Exit::Network => "input stream stalled",
+            Exit::Config => "invalid configuration",
+        })
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn tagged_errors_map_to_their_code() {
+        let err = Err::<(), _>(anyhow!("no such file")).context(Exit::Config).unwrap_err();
+        assert_eq!(Exit::of(&err).code(), 5);
+        assert_eq!(err.to_string(), "invalid configuration");
+        assert_eq!(Exit::of(&anyhow!("something else")).code(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, Read};
+use std::thread;
+use std::time::Duration;
+
+/// How often `--watch` checks a file that has run out for more data.
+pub const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Reads a file that's still being written, like `tail -f`: at its end,
+/// reads wait for more instead of returning 0. It never ends on its own.
+pub struct Follow<R> {
+    inner: R,
+    poll: Duration,
+}
+
+impl<R: Read> Follow<R> {
+    pub fn new(inner: R, poll: Duration) -> Self {
+        Self { inner, poll }
+    }
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.inner.read(buf) {
+                Ok(0) => thread::sleep(self.poll),
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, OpenOptions};
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::mpsc;
+
+    #[test]
+    fn picks_up_lines_appended_after_the_end() {
+        let path = std::env::temp_dir().join(format!("watch-{}", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let file = File::open(&path).unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(Follow::new(file, Duration::from_millis(5))).lines() {
+                if tx.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), "first");
+
+        // The reader has caught up and is waiting at the end of the file.
+        thread::sleep(Duration::from_millis(20));
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"sec").unwrap();
+        file.flush().unwrap();
+        thread::sleep(Duration::from_millis(20));
+        file.write_all(b"ond\n").unwrap();
+        let second = rx.recv_timeout(Duration::from_secs(5));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(second.unwrap(), "second");
+    }
+}
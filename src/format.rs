@@ -0,0 +1,258 @@
+use rodio::decoder::DecoderError;
+use rodio::Decoder;
+use std::fmt;
+use std::io::{Cursor, Read, Seek};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Audio container/codec of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mp3,
+    Wav,
+    Ogg,
+    Flac,
+}
+
+impl Format {
+    /// Names accepted by `--playback`.
+    pub const NAMES: [&'static str; 4] = ["mp3", "wav", "ogg", "flac"];
+
+    pub const ALL: [Format; 4] = [Format::Mp3, Format::Wav, Format::Ogg, Format::Flac];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Wav => "wav",
+            Format::Ogg => "ogg",
+            Format::Flac => "flac",
+        }
+    }
+
+    /// Cargo feature that compiles in the decoder for this format.
+    pub fn feature(self) -> &'static str {
+        match self {
+            Format::Mp3 => "mp3",
+            Format::Wav => "wav",
+            Format::Ogg => "vorbis",
+            Format::Flac => "flac",
+        }
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            Format::Mp3 => cfg!(feature = "mp3"),
+            Format::Wav => cfg!(feature = "wav"),
+            Format::Ogg => cfg!(feature = "vorbis"),
+            Format::Flac => cfg!(feature = "flac"),
+        }
+    }
+
+    /// Fails with rebuild instructions when this build can't decode the format.
+    pub fn check_available(self) -> Result<(), String> {
+        if self.is_available() {
+            return Ok(());
+        }
+        let built: Vec<_> = Format::ALL
+            .into_iter()
+            .filter(|format| format.is_available())
+            .map(Format::name)
+            .collect();
+        Err(format!(
+            "{} playback isn't compiled into this build (available: {}); rebuild with the `{}` feature: cargo build --release --features {}",
+            self,
+            if built.is_empty() { "none".to_string() } else { built.join(", ") },
+            self.feature(),
+            self.feature()
+        ))
+    }
+
+    /// The leading bytes [`Format::detect`] knows the format by.
+    pub fn magic(self) -> &'static str {
+        match self {
+            Format::Mp3 => "ID3, or an MPEG frame sync (FF Ex)",
+            Format::Wav => "RIFF....WAVE, or RIFX....WAVE",
+            Format::Ogg => "OggS",
+            Format::Flac => "fLaC",
+        }
+    }
+
+    /// `--list-formats`: a line per format with whether this build can
+    /// decode it and how it's detected.
+    pub fn listing() -> String {
+        Format::ALL
+            .into_iter()
+            .map(|format| {
+                let backend = if format.is_available() {
+                    "compiled in".to_string()
+                } else {
+                    format!("not compiled in (feature `{}`)", format.feature())
+                };
+                format!("{:<5} {:<34} magic: {}\n", format.name(), backend, format.magic())
+            })
+            .collect()
+    }
+
+    /// Maps an HTTP-style `content_type` to a format. Parameters such as
+    /// `; codecs=...` are ignored.
+    pub fn from_mime(mime: &str) -> Option<Format> {
+        let essence = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "audio/mpeg" | "audio/mp3" => Some(Format::Mp3),
+            "audio/wav" | "audio/x-wav" | "audio/wave" => Some(Format::Wav),
+            "audio/ogg" => Some(Format::Ogg),
+            "audio/flac" | "audio/x-flac" => Some(Format::Flac),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from a chunk's leading bytes.
+    pub fn detect(data: &[u8]) -> Option<Format> {
+        match data {
+            [b'R', b'I', b'F', b'F' | b'X', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Format::Wav),
+            [b'O', b'g', b'g', b'S', ..] => Some(Format::Ogg),
+            [b'f', b'L', b'a', b'C', ..] => Some(Format::Flac),
+            [b'I', b'D', b'3', ..] => Some(Format::Mp3),
+            // MPEG audio frame sync: 11 set bits.
+            [0xff, second, ..] if second & 0xe0 == 0xe0 => Some(Format::Mp3),
+            _ => None,
+        }
+    }
+
+    /// Builds the rodio decoder for a chunk in this format. Formats compiled
+    /// out of this build are reported as unrecognized.
+    pub fn decoder(self, data: Vec<u8>) -> Result<Decoder<Cursor<Vec<u8>>>, DecoderError> {
+        self.stream_decoder(Cursor::new(data))
+    }
+
+    /// Like [`Format::decoder`], but over any reader, such as a stream
+    /// spanning several chunks.
+    pub fn stream_decoder<R>(self, data: R) -> Result<Decoder<R>, DecoderError>
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        #[allow(unreachable_patterns)]
+        match self {
+            #[cfg(feature = "mp3")]
+            Format::Mp3 => Decoder::new_mp3(data),
+            #[cfg(feature = "wav")]
+            Format::Wav => Decoder::new_wav(data),
+            #[cfg(feature = "vorbis")]
+            Format::Ogg => Decoder::new_vorbis(data),
+            #[cfg(feature = "flac")]
+            Format::Flac => Decoder::new_flac(data),
+            _ => {
+                drop(data);
+                Err(DecoderError::UnrecognizedFormat)
+            }
+        }
+    }
+}
+
+/// Start-up tuning used when it isn't given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buffering {
+    /// Silence queued ahead of the first chunk, as `--warmup-ms`.
+    pub warmup: Duration,
+}
+
+/// Per-format defaults: compressed formats get more lead-in, since their
+/// first chunk takes longer to decode than PCM copied straight through.
+pub fn default_buffering(format: Format) -> Buffering {
+    let warmup_ms = match format {
+        Format::Mp3 | Format::Ogg => 150,
+        Format::Flac => 50,
+        Format::Wav => 0,
+    };
+    Buffering {
+        warmup: Duration::from_millis(warmup_ms),
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp3" => Ok(Format::Mp3),
+            "wav" => Ok(Format::Wav),
+            "ogg" => Ok(Format::Ogg),
+            "flac" => Ok(Format::Flac),
+            _ => Err(format!("unknown audio format `{}`", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_mime_types() {
+        assert_eq!(Format::from_mime("audio/mpeg"), Some(Format::Mp3));
+        assert_eq!(Format::from_mime("audio/x-wav"), Some(Format::Wav));
+        assert_eq!(Format::from_mime("Audio/WAV"), Some(Format::Wav));
+        assert_eq!(Format::from_mime("audio/ogg; codecs=vorbis"), Some(Format::Ogg));
+        assert_eq!(Format::from_mime("audio/flac"), Some(Format::Flac));
+        assert_eq!(Format::from_mime("video/mp4"), None);
+    }
+
+    #[test]
+    fn detects_magic_bytes() {
+        assert_eq!(Format::detect(b"RIFF\0\0\0\0WAVEfmt "), Some(Format::Wav));
+        assert_eq!(Format::detect(b"RIFF\0\0\0\0AVI "), None);
+        assert_eq!(Format::detect(b"RIFX\0\0\0\0WAVEfmt "), Some(Format::Wav));
+        assert_eq!(Format::detect(b"OggS\0"), Some(Format::Ogg));
+        assert_eq!(Format::detect(b"fLaC"), Some(Format::Flac));
+        assert_eq!(Format::detect(b"ID3\x04"), Some(Format::Mp3));
+        assert_eq!(Format::detect(&[0xff, 0xfb, 0x90]), Some(Format::Mp3));
+        assert_eq!(Format::detect(&[0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn lists_every_format() {
+        let listing = Format::listing();
+        assert_eq!(listing.lines().count(), Format::ALL.len());
+        let line = |name| listing.lines().find(|line| line.starts_with(name)).unwrap();
+        assert!(line("wav ").contains("compiled in") && line("wav ").contains("magic: RIFF....WAVE"));
+        assert!(line("mp3 ").contains("compiled in") && line("mp3 ").contains("magic: ID3"));
+        assert!(!line("wav ").contains("not compiled in"), "{}", listing);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for name in Format::NAMES {
+            assert_eq!(name.parse::<Format>().unwrap().name(), name);
+        }
+    }
+
+    #[test]
+    fn buffering_defaults_per_format() {
+        assert_eq!(default_buffering(Format::Mp3).warmup, Duration::from_millis(150));
+        assert_eq!(default_buffering(Format::Ogg).warmup, Duration::from_millis(150));
+        assert_eq!(default_buffering(Format::Flac).warmup, Duration::from_millis(50));
+        assert_eq!(default_buffering(Format::Wav).warmup, Duration::ZERO);
+    }
+
+    #[test]
+    fn check_matches_compiled_backends() {
+        for format in Format::ALL {
+            assert_eq!(format.check_available().is_ok(), format.is_available());
+        }
+    }
+
+    #[cfg(not(feature = "flac"))]
+    #[test]
+    fn missing_backend_explains_rebuild() {
+        let err = Format::Flac.check_available().unwrap_err();
+        assert!(err.starts_with("flac playback isn't compiled into this build"), "{}", err);
+        assert!(err.ends_with("cargo build --release --features flac"), "{}", err);
+        assert!(Format::Flac.decoder(b"fLaC".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+
+use crate::manifest::ManifestEntry;
+
+/// Column names of the `--frame-log` CSV.
+pub const HEADER: &str = "index,input_b64_len,decoded_len,detected_format,peak,rms,decode_latency_ms,played";
+
+/// Writes one CSV row per processed chunk, flushing each one so an
+/// interrupted run still leaves a readable file. Unknown values are left
+/// empty.
+pub struct FrameLog<W: Write> {
+    out: W,
+}
+
+impl<W: Write> FrameLog<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        writeln!(out, "{}", HEADER)?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+
+    pub fn record(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        let level = |level: Option<f32>| level.map(|level| format!("{:.6}", level)).unwrap_or_default();
+        writeln!(
+            self.out,
+            "{},{},{},{},{},{},{},{}",
+            entry.index,
+            entry.encoded_bytes,
+            entry.bytes,
+            entry.format.unwrap_or_default(),
+            level(entry.peak),
+            level(entry.rms),
+            entry
+                .decode_latency
+                .map(|latency| format!("{:.3}", latency.as_secs_f64() * 1000.0))
+                .unwrap_or_default(),
+            entry.played
+        )?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_header_then_one_row_per_chunk() {
+        let mut log = FrameLog::new(Vec::new()).unwrap();
+        log.record(&ManifestEntry {
+            index: 1,
+            bytes: 8192,
+            format: Some("wav"),
+            peak: Some(0.5),
+            decoded: true,
+            encoded_bytes: 10924,
+            rms: Some(0.25),
+            decode_latency: Some(Duration::from_micros(1250)),
+            played: true,
+        })
+        .unwrap();
+        log.record(&ManifestEntry {
+            index: 2,
+            bytes: 3,
+            format: None,
+            peak: None,
+            decoded: false,
+            encoded_bytes: 4,
+            rms: None,
+            decode_latency: None,
+            played: false,
+        })
+        .unwrap();
+
+        let text = String::from_utf8(log.out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                HEADER,
+                "1,10924,8192,wav,0.500000,0.250000,1.250,true",
+                "2,4,3,,,,,false",
+            ]
+        );
+    }
+}
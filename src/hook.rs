@@ -0,0 +1,214 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread::{self, JoinHandle};
+use tracing::{info, warn};
+
+use crate::wav::{self, WavInfo};
+
+/// `--on-chunk-cmd`: one long-running child that receives every decoded
+/// chunk on stdin, in arrival order and with no framing between chunks.
+/// Each chunk is written in full before it's played, so a child that stops
+/// reading stalls playback once the pipe buffer fills.
+pub struct ChunkHook {
+    command: String,
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl ChunkHook {
+    /// Starts `command` through the shell.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        Self::spawn_with_stdout(command, Stdio::inherit())
+    }
+
+    fn spawn_with_stdout(command: &str, stdout: Stdio) -> io::Result<Self> {
+        let mut child = shell(command).stdin(Stdio::piped()).stdout(stdout).spawn()?;
+        let stdin = child.stdin.take();
+        Ok(Self {
+            command: command.to_string(),
+            child,
+            stdin,
+        })
+    }
+
+    /// Writes chunk `index` to the child. Once a write fails, typically
+    /// because the child exited, further chunks are no longer sent.
+    pub fn write(&mut self, index: usize, data: &[u8]) {
+        let Some(stdin) = self.stdin.as_mut() else {
+            return;
+        };
+        if let Err(e) = stdin.write_all(data) {
+            warn!(
+                "`{}` stopped accepting input at chunk {} ({}); no longer sending chunks to it",
+                self.command, index, e
+            );
+            self.stdin = None;
+        }
+    }
+
+    /// Closes the child's stdin and waits for it to exit.
+    pub fn finish(mut self) {
+        drop(self.stdin.take());
+        match self.child.wait() {
+            Ok(status) if status.success() => info!("`{}` exited", self.command),
+            Ok(status) => warn!("`{}` exited with {}", self.command, status),
+            Err(e) => warn!("Failed to wait for `{}`: {}", self.command, e),
+        }
+    }
+}
+
+/// `--asr-cmd`: a [`ChunkHook`] fed the PCM bodies of WAV chunks, headers
+/// stripped, whose stdout is read line by line on its own thread so a slow
+/// transcriber never holds up playback on that side.
+pub struct AsrHook {
+    hook: ChunkHook,
+    reader: Option<JoinHandle<()>>,
+    /// Format of the PCM sent, fixed by the first WAV chunk.
+    layout: Option<WavInfo>,
+}
+
+impl AsrHook {
+    /// Starts `command` through the shell, handing each line it prints to
+    /// `on_line`.
+    pub fn spawn(command: &str, mut on_line: impl FnMut(String) + Send + 'static) -> io::Result<Self> {
+        let mut hook = ChunkHook::spawn_with_stdout(command, Stdio::piped())?;
+        let stdout = hook.child.stdout.take().unwrap();
+        let reader = thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                on_line(line);
+            }
+        });
+        Ok(Self {
+            hook,
+            reader: Some(reader),
+            layout: None,
+        })
+    }
+
+    /// Sends the PCM of chunk `index`, always little-endian: RIFX samples
+    /// are byte-swapped. The format is logged with the first chunk; chunks
+    /// in another format, or that aren't WAV, are skipped.
+    pub fn write(&mut self, index: usize, data: &[u8]) {
+        let (Some(info), Some((_, body))) = (wav::parse_wav_info(data), wav::extract_wav_header(data)) else {
+            warn!("Audio chunk {} isn't WAV, not sending it to `{}`", index, self.hook.command);
+            return;
+        };
+        match self.layout {
+            None => {
+                info!(
+                    "Sending `{}` little-endian {}-bit {} PCM at {} Hz, {} interleaved channel(s)",
+                    self.hook.command,
+                    info.bits_per_sample,
+                    if info.format_tag == wav::FORMAT_IEEE_FLOAT { "float" } else { "integer" },
+                    info.sample_rate,
+                    info.channels
+                );
+                self.layout = Some(info);
+            }
+            Some(layout) if (layout.format_tag, layout.bits_per_sample, layout.channels, layout.sample_rate)
+                != (info.format_tag, info.bits_per_sample, info.channels, info.sample_rate) =>
+            {
+                warn!("Audio chunk {} changes the PCM format, not sending it to `{}`", index, self.hook.command);
+                return;
+            }
+            Some(_) => {}
+        }
+        let body = if info.rifx {
+            Cow::Owned(little_endian(body, usize::from(info.bits_per_sample).div_ceil(8)))
+        } else {
+            Cow::Borrowed(body)
+        };
+        self.hook.write(index, &body);
+    }
+
+    /// Closes the child's stdin, waits for it to exit and for the last of
+    /// its output to be handed over.
+    pub fn finish(mut self) {
+        self.hook.finish();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Big-endian samples of `width` bytes, byte-swapped. A trailing partial
+/// sample is dropped.
+fn little_endian(body: &[u8], width: usize) -> Vec<u8> {
+    if width <= 1 {
+        return body.to_vec();
+    }
+    body.chunks_exact(width).flat_map(|sample| sample.iter().rev().copied()).collect()
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::wav::tests::{rifx_file, wav_file};
+    use std::fs;
+
+    #[test]
+    fn child_receives_chunks_in_order() {
+        let path = std::env::temp_dir().join(format!("chunk-hook-{}", std::process::id()));
+        let mut hook = ChunkHook::spawn(&format!("cat > '{}'", path.display())).unwrap();
+        hook.write(1, &[1, 2, 3]);
+        hook.write(2, &[4]);
+        hook.finish();
+
+        assert_eq!(fs::read(&path).unwrap(), vec![1, 2, 3, 4]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn asr_reads_back_lines() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        // Stands in for a transcriber: one line with the bytes it was sent.
+        let mut asr = AsrHook::spawn("wc -c | tr -d ' '", move |line| tx.send(line).unwrap()).unwrap();
+        asr.write(1, &wav_file(1, 16000, &[1, 0, 2, 0]));
+        asr.write(2, &[0xff, 0xfb]);
+        asr.write(3, &wav_file(1, 16000, &[3, 0]));
+        asr.finish();
+
+        // Only the PCM bodies are sent, and the mp3 chunk is skipped.
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec!["6"]);
+    }
+
+    #[test]
+    fn asr_swaps_rifx_samples_to_little_endian() {
+        let path = std::env::temp_dir().join(format!("asr-rifx-{}", std::process::id()));
+        let mut asr = AsrHook::spawn(&format!("cat > '{}'", path.display()), |_| {}).unwrap();
+        asr.write(1, &rifx_file(1, 16000, &[0, 1, 0xff, 0xfe]));
+        asr.write(2, &wav_file(1, 16000, &[3, 0]));
+        asr.finish();
+
+        assert_eq!(fs::read(&path).unwrap(), vec![1, 0, 0xfe, 0xff, 3, 0]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn exited_child_disables_writes() {
+        let mut hook = ChunkHook::spawn("exit 0").unwrap();
+        hook.child.wait().unwrap();
+        // Large enough not to fit in a pipe buffer nobody reads.
+        hook.write(1, &vec![0; 1 << 20]);
+        assert!(hook.stdin.is_none());
+        hook.finish();
+    }
+}
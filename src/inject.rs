@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+use crate::pcm::Pcm;
+
+/// Watches `--inject-file` for complete audio files to splice into the
+/// stream, such as a notification sound. Whenever the file is written
+/// anew it's decoded on its own and played after the chunk being queued.
+/// Whatever is at the path when watching starts isn't played.
+#[derive(Debug)]
+pub struct Injector {
+    path: PathBuf,
+    /// Modification time and length of the last version seen.
+    seen: Option<(SystemTime, u64)>,
+    /// A version that failed to decode once.
+    pending: Option<(SystemTime, u64)>,
+}
+
+impl Injector {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            seen: version(path),
+            pending: None,
+        }
+    }
+
+    /// The audio of a version of the file not played yet. One that doesn't
+    /// decode is retried on the next call, since it may still be being
+    /// written; once it stops changing it's given up on.
+    pub fn poll(&mut self) -> Option<Pcm> {
+        let current = version(&self.path)?;
+        if self.seen == Some(current) {
+            return None;
+        }
+        let decoded = fs::read(&self.path).map_err(|e| e.to_string()).and_then(Pcm::decode_file);
+        match decoded {
+            Ok(pcm) => {
+                self.seen = Some(current);
+                Some(pcm)
+            }
+            Err(e) if self.pending == Some(current) => {
+                warn!("Not injecting {}: {}", self.path.display(), e);
+                self.seen = Some(current);
+                None
+            }
+            Err(e) => {
+                debug!("{} doesn't decode yet: {}", self.path.display(), e);
+                self.pending = Some(current);
+                None
+            }
+        }
+    }
+}
+
+fn version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
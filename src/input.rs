@@ -0,0 +1,1426 @@
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use std::io::{self, BufRead, Read};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+use crate::ascii85;
+use crate::error::ChunkerError;
+use crate::format::Format;
+#[cfg(feature = "parquet")]
+use crate::parquet;
+use crate::pool::Pool;
+use crate::throughput::Throughput;
+use crate::wav::read_u32_le;
+
+pub struct JsonData {
+    data: Payload,
+    content_type: Option<String>,
+    gain: Option<f32>,
+    ts: Option<u64>,
+    seq: Option<u64>,
+}
+
+/// A record's payload, as it appears in the record.
+enum Payload {
+    /// Text in one of the string encodings.
+    Text(String),
+    /// The elements of a `--encoding json-bytes` array.
+    Bytes(Vec<serde_json::Value>),
+}
+
+impl Payload {
+    /// Encoded size: the text's length, or the number of array elements,
+    /// since the array's text isn't kept once parsed.
+    fn len(&self) -> usize {
+        match self {
+            Payload::Text(text) => text.len(),
+            Payload::Bytes(elements) => elements.len(),
+        }
+    }
+}
+
+/// A decoded audio payload handed to the consumer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub data: Vec<u8>,
+    /// Format announced by the record's `content_type`, overriding `--playback`.
+    pub format: Option<Format>,
+    /// Per-chunk volume factor from the record's `gain` field.
+    pub gain: Option<f32>,
+    /// Presentation timestamp in ms from the record's `ts` field, for
+    /// `--use-timestamps`.
+    pub ts: Option<u64>,
+    /// Sequence number from the record's `seq` field, for `--conceal-gaps`.
+    pub seq: Option<u64>,
+    /// Length of the payload as it appeared in the input, before decoding.
+    pub encoded_len: usize,
+}
+
+/// Range `gain` values are clamped to.
+pub const GAIN_RANGE: (f32, f32) = (0.0, 4.0);
+
+/// Framing of the records on the input stream.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// One JSON object per line.
+    #[default]
+    Jsonl,
+    /// A single JSON array of objects. The whole input is read before the
+    /// first chunk is sent, so this can't be used with unbounded streams.
+    JsonArray,
+    /// Raw audio frames, each preceded by its length as a little-endian u32.
+    /// There is no JSON or text encoding involved.
+    Framed,
+    /// RFC 7464 JSON text sequences: records prefixed with 0x1E.
+    JsonSeq,
+    /// A Parquet file, one chunk per row of a binary column. Read whole,
+    /// like `JsonArray`.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// The ASCII record separator that starts each `--format json-seq` record.
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// Text encoding of the `data` field.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Base64,
+    Hex,
+    Ascii85,
+    /// A JSON array of byte values instead of a string.
+    JsonBytes,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Base64 => "base64",
+            Encoding::Hex => "hex",
+            Encoding::Ascii85 => "ascii85",
+            Encoding::JsonBytes => "json-bytes",
+        }
+    }
+
+    /// Decodes `data` into the empty buffer `out`, reusing its allocation.
+    pub fn decode_into(self, data: &str, out: &mut Vec<u8>) -> Result<(), ChunkerError> {
+        match self {
+            Encoding::Base64 => general_purpose::STANDARD.decode_vec(data, out)?,
+            Encoding::Hex => {
+                out.resize(data.len() / 2, 0);
+                hex::decode_to_slice(data, out)?;
+            }
+            Encoding::Ascii85 => out.extend(ascii85::decode(data).map_err(ChunkerError::Ascii85)?),
+            Encoding::JsonBytes => return Err(ChunkerError::JsonBytes("payload is a string, not an array of bytes".to_string())),
+        }
+        Ok(())
+    }
+}
+
+/// How input lines are interpreted.
+#[derive(Debug, Default, Clone)]
+pub struct InputOptions {
+    pub format: InputFormat,
+    pub encoding: Encoding,
+    /// Skip lines whose first non-whitespace character is `#`.
+    pub allow_comments: bool,
+    /// Only JSONL lines starting with this, after leading whitespace, are
+    /// records; the prefix is stripped before parsing.
+    pub line_prefix: Option<String>,
+    /// RFC 6901 pointer to the payload string, instead of the top-level
+    /// `data` field.
+    pub json_pointer: Option<String>,
+    /// Top-level string field holding the actual record as JSON text, for
+    /// double-encoded input.
+    pub unwrap_json: Option<String>,
+    /// Retry lines that fail strict parsing with trailing commas removed.
+    pub lenient_json: bool,
+    /// Require exactly one JSON object per line or record.
+    pub strict_lines: bool,
+    /// Stop before sending a chunk that would take the decoded bytes sent
+    /// past this many.
+    pub limit_bytes: Option<u64>,
+    /// Drop decoded chunks shorter than this, except WAV headers.
+    pub min_chunk_size: usize,
+    /// Skip JSONL lines and JSON text sequence records longer than this,
+    /// without buffering more of them than the limit.
+    pub max_line_bytes: Option<usize>,
+    /// `--force-format`: every chunk gets this format and `content_type`
+    /// isn't looked at.
+    pub force_format: Option<Format>,
+    /// `--parquet-column`, or [`parquet::DEFAULT_COLUMN`] when unset.
+    #[cfg(feature = "parquet")]
+    pub parquet_column: Option<String>,
+    /// Rolling counter fed with every chunk sent, for `--stats-interval`.
+    pub throughput: Option<Arc<Mutex<Throughput>>>,
+    /// Buffers the consumer gives back, to decode payloads into.
+    pub pool: Option<Arc<Pool>>,
+    /// `--jsonl-output`: receives a JSON description of each decoded record.
+    pub echo: Option<Sender<String>>,
+}
+
+impl InputOptions {
+    /// An empty buffer for the next payload, from the pool if there is one.
+    fn buffer(&self) -> Vec<u8> {
+        self.pool.as_ref().map(|pool| pool.take()).unwrap_or_default()
+    }
+
+    /// Accounts for a chunk about to be sent: checks it against
+    /// `--limit-bytes` and feeds the throughput counter.
+    fn admit(&self, stats: &mut InputStats, len: usize) -> bool {
+        if !stats.admit(len, self.limit_bytes) {
+            return false;
+        }
+        if let Some(throughput) = &self.throughput {
+            throughput.lock().unwrap().record(len);
+        }
+        true
+    }
+
+    /// Whether a decoded payload falls under `--min-chunk-size`. A chunk
+    /// starting with a RIFF/WAVE header always passes, however short, since
+    /// the chunks after it depend on it.
+    fn undersized(&self, data: &[u8]) -> bool {
+        data.len() < self.min_chunk_size && Format::detect(data) != Some(Format::Wav)
+    }
+}
+
+/// Counters reported once the input has been consumed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct InputStats {
+    pub line_count: usize,
+    pub comment_lines: usize,
+    /// Lines or records dropped as invalid UTF-8, JSON or payload encoding.
+    pub parse_errors: usize,
+    /// Of those, records with no payload field at all...
+    pub missing_payloads: usize,
+    /// ...and records whose payload field isn't a string.
+    pub non_string_payloads: usize,
+    /// The last line had no newline and didn't parse; it isn't counted in
+    /// `parse_errors`.
+    pub truncated_tail: bool,
+    /// Lines skipped for not starting with `--line-prefix`.
+    pub ignored_lines: usize,
+    pub valid_json_count: usize,
+    pub successful_decode_count: usize,
+    /// Records whose payload decoded to zero bytes; these aren't sent.
+    pub empty_chunks: usize,
+    /// Chunks dropped for being shorter than `--min-chunk-size`.
+    pub undersized_chunks: usize,
+    /// Lines or records skipped for being longer than `--max-line-bytes`.
+    pub oversized_lines: usize,
+    /// Length of the successfully decoded `data` strings, before decoding;
+    /// under `--encoding json-bytes`, the number of array elements.
+    pub encoded_bytes: usize,
+    /// Length of those payloads after decoding.
+    pub decoded_bytes: usize,
+    /// Decoded bytes handed to the consumer.
+    pub sent_bytes: u64,
+    /// Reading stopped because of `--limit-bytes`.
+    pub byte_limit_reached: bool,
+    /// Reading stopped because the input went idle for `--input-timeout`.
+    pub timed_out: bool,
+}
+
+impl InputStats {
+    fn count_parse_error(&mut self, error: &ChunkerError) {
+        self.parse_errors += 1;
+        match error {
+            ChunkerError::MissingPayload(_) => self.missing_payloads += 1,
+            ChunkerError::PayloadNotString(..) => self.non_string_payloads += 1,
+            _ => {}
+        }
+    }
+
+    /// Accounts for a chunk about to be sent, or returns `false` and flags
+    /// the limit if it doesn't fit under `limit`.
+    fn admit(&mut self, len: usize, limit: Option<u64>) -> bool {
+        let total = self.sent_bytes + len as u64;
+        if limit.is_some_and(|limit| total > limit) {
+            self.byte_limit_reached = true;
+            return false;
+        }
+        self.sent_bytes = total;
+        true
+    }
+
+    /// Encoded bytes per decoded byte, e.g. about 1.33 for base64.
+    pub fn expansion_ratio(&self) -> Option<f64> {
+        (self.decoded_bytes > 0).then(|| self.encoded_bytes as f64 / self.decoded_bytes as f64)
+    }
+}
+
+/// Reads records from `reader` and sends each decoded chunk to `tx`.
+/// Stops early if the receiving side hangs up.
+pub fn read_input<R: BufRead>(reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    match options.format {
+        InputFormat::Jsonl => read_jsonl(reader, tx, options),
+        InputFormat::JsonArray => read_json_array(reader, tx, options),
+        InputFormat::Framed => read_framed(reader, tx, options),
+        InputFormat::JsonSeq => read_json_seq(reader, tx, options),
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => read_parquet(reader, tx, options),
+    }
+}
+
+fn read_jsonl<R: BufRead>(mut reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    let mut stats = InputStats::default();
+    let mut buf = Vec::new();
+
+    loop {
+        // Read raw bytes so one line that isn't UTF-8 can be skipped on its own.
+        buf.clear();
+        match read_capped(&mut reader, b'\n', &mut buf, options.max_line_bytes) {
+            Ok((0, _)) => break,
+            Ok((_, true)) => {
+                stats.line_count += 1;
+                warn!(
+                    "Skipping line {}: longer than --max-line-bytes {}",
+                    stats.line_count,
+                    options.max_line_bytes.unwrap_or_default()
+                );
+                stats.oversized_lines += 1;
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                stats.timed_out = e.kind() == io::ErrorKind::TimedOut;
+                if !stats.timed_out {
+                    warn!("Failed to read line {}: {}", stats.line_count + 1, e);
+                }
+                break;
+            }
+        }
+
+        stats.line_count += 1;
+        // Only the last line can lack its newline: the producer stopped
+        // mid-write, so a parse failure there is a cut-off tail, not corruption.
+        let terminated = buf.ends_with(b"\n");
+        let mut bytes = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if stats.line_count == 1 {
+            bytes = bytes.strip_prefix("\u{feff}".as_bytes()).unwrap_or(bytes);
+        }
+        let line = match std::str::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(e) if !terminated => {
+                warn!("Ignoring truncated final line {}: not valid UTF-8 ({})", stats.line_count, e);
+                stats.truncated_tail = true;
+                continue;
+            }
+            Err(e) => {
+                warn!("Skipping line {}: not valid UTF-8 ({})", stats.line_count, e);
+                stats.parse_errors += 1;
+                continue;
+            }
+        };
+
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if options.allow_comments && trimmed.starts_with('#') {
+            stats.comment_lines += 1;
+            continue;
+        }
+        let line = match options.line_prefix.as_deref() {
+            Some(prefix) => match trimmed.strip_prefix(prefix) {
+                Some(rest) => rest,
+                None => {
+                    stats.ignored_lines += 1;
+                    continue;
+                }
+            },
+            None => line,
+        };
+
+        let location = format!("line {}", stats.line_count);
+        match parse_text(line, &location, options) {
+            Ok(json_data) => {
+                if !send_record(json_data, &location, options, &mut stats, tx) {
+                    break;
+                }
+            }
+            Err(e) if !terminated => {
+                warn!("Ignoring truncated final line {}: {}", stats.line_count, e);
+                stats.truncated_tail = true;
+            }
+            Err(e) => {
+                warn!("Failed to parse JSON on line {}: {}", stats.line_count, e);
+                stats.count_parse_error(&e);
+            }
+        }
+    }
+
+    stats
+}
+
+/// Reads RFC 7464 JSON text sequences: each record starts with an ASCII
+/// record separator (0x1E) and normally ends with a newline.
+fn read_json_seq<R: BufRead>(mut reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    let mut stats = InputStats::default();
+    let mut record = Vec::new();
+    let mut leading = true;
+
+    loop {
+        record.clear();
+        match read_capped(&mut reader, RECORD_SEPARATOR, &mut record, options.max_line_bytes) {
+            Ok((0, _)) => break,
+            Ok((_, true)) if !leading => {
+                stats.line_count += 1;
+                warn!(
+                    "Skipping record {}: longer than --max-line-bytes {}",
+                    stats.line_count,
+                    options.max_line_bytes.unwrap_or_default()
+                );
+                stats.oversized_lines += 1;
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                stats.timed_out = e.kind() == io::ErrorKind::TimedOut;
+                if !stats.timed_out {
+                    warn!("Failed to read record {}: {}", stats.line_count + 1, e);
+                }
+                break;
+            }
+        }
+        if record.last() == Some(&RECORD_SEPARATOR) {
+            record.pop();
+        }
+
+        // Whatever precedes the first separator isn't part of any record.
+        if std::mem::take(&mut leading) {
+            if !record.iter().all(u8::is_ascii_whitespace) {
+                warn!("Ignoring {} bytes before the first record separator", record.len());
+            }
+            continue;
+        }
+
+        let text = match std::str::from_utf8(&record) {
+            Ok(text) => text.trim(),
+            Err(e) => {
+                stats.line_count += 1;
+                warn!("Record {} is not valid UTF-8: {}", stats.line_count, e);
+                stats.parse_errors += 1;
+                continue;
+            }
+        };
+        // Consecutive separators are allowed and carry no record.
+        if text.is_empty() {
+            continue;
+        }
+        stats.line_count += 1;
+
+        let location = format!("record {}", stats.line_count);
+        match parse_text(text, &location, options) {
+            Ok(json_data) => {
+                if !send_record(json_data, &location, options, &mut stats, tx) {
+                    break;
+                }
+            }
+            Err(e) => {
+                warn!("Failed to parse JSON on {}: {}", location, e);
+                stats.count_parse_error(&e);
+            }
+        }
+    }
+
+    stats
+}
+
+/// Like `read_until`, but buffers at most `max` bytes before the delimiter
+/// and discards the rest up to it. Returns the bytes taken from `reader`,
+/// and whether any had to be discarded.
+fn read_capped<R: BufRead>(reader: &mut R, delimiter: u8, buf: &mut Vec<u8>, max: Option<usize>) -> io::Result<(usize, bool)> {
+    let Some(max) = max else {
+        return reader.read_until(delimiter, buf).map(|read| (read, false));
+    };
+    let (mut read, mut oversized) = (0, false);
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            return Ok((read, oversized));
+        }
+        let (used, found) = match available.iter().position(|&byte| byte == delimiter) {
+            Some(end) => (end + 1, true),
+            None => (available.len(), false),
+        };
+        // The delimiter itself doesn't count against the limit.
+        let content = used - usize::from(found);
+        if content > max.saturating_sub(buf.len()) {
+            oversized = true;
+        }
+        if !oversized {
+            buf.extend_from_slice(&available[..used]);
+        }
+        reader.consume(used);
+        read += used;
+        if found {
+            return Ok((read, oversized));
+        }
+    }
+}
+
+fn read_json_array<R: BufRead>(reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    let mut stats = InputStats::default();
+
+    let elements: Vec<serde_json::Value> = match serde_json::from_reader(reader) {
+        Ok(elements) => elements,
+        Err(e) if e.io_error_kind() == Some(io::ErrorKind::TimedOut) => {
+            stats.timed_out = true;
+            return stats;
+        }
+        Err(e) => {
+            warn!("Failed to parse JSON array: {}", e);
+            stats.parse_errors += 1;
+            return stats;
+        }
+    };
+
+    for element in elements {
+        stats.line_count += 1;
+
+        match extract_record(element, options) {
+            Ok(json_data) => {
+                let location = format!("element {}", stats.line_count);
+                if !send_record(json_data, &location, options, &mut stats, tx) {
+                    break;
+                }
+            }
+            Err(e) => {
+                warn!("Failed to parse JSON on element {}: {}", stats.line_count, e);
+                stats.count_parse_error(&e);
+            }
+        }
+    }
+
+    stats
+}
+
+/// Parses one JSONL line or JSON sequence record into a record.
+fn parse_text(text: &str, location: &str, options: &InputOptions) -> Result<JsonData, ChunkerError> {
+    let mut value = parse_value(text, options.strict_lines);
+    if value.is_err() && options.lenient_json {
+        if let Ok(relaxed) = parse_value(&strip_trailing_commas(text), options.strict_lines) {
+            debug!("Parsed {} after removing trailing commas", location);
+            value = Ok(relaxed);
+        }
+    }
+    value.and_then(|value| extract_record(value, options))
+}
+
+/// Parses `text` as JSON. In strict mode it must hold exactly one object;
+/// violations are reported as [`ChunkerError::Strict`].
+pub fn parse_value(text: &str, strict: bool) -> Result<serde_json::Value, ChunkerError> {
+    if !strict {
+        return Ok(serde_json::from_str(text)?);
+    }
+
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<serde_json::Value>();
+    let value = match values.next() {
+        Some(value) => value?,
+        None => return Err(ChunkerError::Strict("no JSON value".to_string())),
+    };
+    if !value.is_object() {
+        return Err(ChunkerError::Strict(format!("expected a JSON object, found {}", json_type(&value))));
+    }
+    match values.next() {
+        None => Ok(value),
+        Some(Ok(_)) => Err(ChunkerError::Strict("more than one JSON value".to_string())),
+        Some(Err(e)) => Err(ChunkerError::Strict(format!("trailing data after the JSON object: {}", e))),
+    }
+}
+
+/// Drops commas that directly precede a closing `}` or `]`, leaving string
+/// contents alone.
+fn strip_trailing_commas(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut pending_comma = None;
+
+    for c in line.chars() {
+        if in_string {
+            out.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            ',' => {
+                pending_comma = Some(out.len());
+                out.push(c);
+            }
+            '}' | ']' => {
+                if let Some(comma) = pending_comma.take() {
+                    out.remove(comma);
+                }
+                out.push(c);
+            }
+            c if c.is_whitespace() => out.push(c),
+            _ => {
+                pending_comma = None;
+                if c == '"' {
+                    in_string = true;
+                }
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// Validates `--json-pointer`: RFC 6901 pointers are empty or start with `/`.
+pub fn parse_json_pointer(pointer: &str) -> Result<String, String> {
+    if pointer.is_empty() || pointer.starts_with('/') {
+        Ok(pointer.to_string())
+    } else {
+        Err(format!("JSON pointer must start with '/', got `{}`", pointer))
+    }
+}
+
+/// Decodes the elements of a `--encoding json-bytes` array, such as
+/// `[82,73,70,70]`, into `out`, checking every element is a byte.
+pub fn decode_json_bytes(elements: &[serde_json::Value], out: &mut Vec<u8>) -> Result<(), ChunkerError> {
+    out.reserve(elements.len());
+    for (index, element) in elements.iter().enumerate() {
+        let byte = element
+            .as_u64()
+            .and_then(|value| u8::try_from(value).ok())
+            .ok_or_else(|| ChunkerError::JsonBytes(format!("element {} is {}, not a byte (0..=255)", index, element)))?;
+        out.push(byte);
+    }
+    Ok(())
+}
+
+/// Picks the payload out of a parsed record, following `--json-pointer`
+/// when given. `content_type` is always read from the top level.
+pub fn extract_record(mut value: serde_json::Value, options: &InputOptions) -> Result<JsonData, ChunkerError> {
+    if let Some(field) = options.unwrap_json.as_deref() {
+        value = unwrap_json(&value, field).map_err(ChunkerError::Unwrap)?;
+    }
+    let (payload, name) = match options.json_pointer.as_deref() {
+        Some(pointer) => (value.pointer(pointer), format!("JSON pointer `{}`", pointer)),
+        None => (value.get("data"), "field `data`".to_string()),
+    };
+    let data = match payload {
+        Some(serde_json::Value::Array(elements)) if options.encoding == Encoding::JsonBytes => {
+            Payload::Bytes(elements.clone())
+        }
+        Some(other) if options.encoding == Encoding::JsonBytes => {
+            let message = format!("{} resolves to {}, not an array of bytes", name, json_type(other));
+            return Err(ChunkerError::JsonBytes(message));
+        }
+        Some(serde_json::Value::String(data)) => Payload::Text(data.clone()),
+        Some(other) => return Err(ChunkerError::PayloadNotString(name, json_type(other))),
+        None => return Err(ChunkerError::MissingPayload(name)),
+    };
+    let content_type = value
+        .get("content_type")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string);
+    let gain = value.get("gain").and_then(serde_json::Value::as_f64).map(|gain| gain as f32);
+    let ts = value.get("ts").and_then(serde_json::Value::as_u64);
+    let seq = value.get("seq").and_then(serde_json::Value::as_u64);
+    Ok(JsonData {
+        data,
+        content_type,
+        gain,
+        ts,
+        seq,
+    })
+}
+
+/// Parses the JSON text held in `value`'s string field `field`.
+fn unwrap_json(value: &serde_json::Value, field: &str) -> Result<serde_json::Value, String> {
+    match value.get(field) {
+        Some(serde_json::Value::String(text)) => serde_json::from_str(text)
+            .map_err(|e| format!("field `{}` doesn't hold valid JSON: {}", field, e)),
+        Some(other) => Err(format!(
+            "field `{}` to unwrap is {}, not a string",
+            field,
+            json_type(other)
+        )),
+        None => Err(format!("no field `{}` to unwrap", field)),
+    }
+}
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Frames are counted in `line_count` and, being audio already, in
+/// `successful_decode_count`.
+fn read_framed<R: BufRead>(mut reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    let mut stats = InputStats::default();
+
+    loop {
+        let mut prefix = [0u8; 4];
+        match reader.read(&mut prefix[..1]) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                stats.timed_out = true;
+                break;
+            }
+            Err(e) => {
+                warn!("Failed to read frame {}: {}", stats.line_count + 1, e);
+                break;
+            }
+        }
+        if let Err(e) = reader.read_exact(&mut prefix[1..]) {
+            warn!("Truncated length prefix for frame {}: {}", stats.line_count + 1, e);
+            break;
+        }
+
+        stats.line_count += 1;
+        let len = read_u32_le(&prefix, 0).unwrap() as usize;
+
+        // Read through `take` so a corrupt length can't allocate up front.
+        let mut data = options.buffer();
+        match (&mut reader).take(len as u64).read_to_end(&mut data) {
+            Ok(n) if n == len => {}
+            Ok(n) => {
+                warn!("Frame {} ends after {} of {} bytes", stats.line_count, n, len);
+                break;
+            }
+            Err(e) => {
+                warn!("Failed to read frame {}: {}", stats.line_count, e);
+                break;
+            }
+        }
+
+        if data.is_empty() {
+            warn!("Frame {} is empty, skipping", stats.line_count);
+            stats.empty_chunks += 1;
+            continue;
+        }
+        if options.undersized(&data) {
+            debug!("Frame {} is only {} bytes, skipping", stats.line_count, data.len());
+            stats.undersized_chunks += 1;
+            continue;
+        }
+
+        if !options.admit(&mut stats, data.len()) {
+            break;
+        }
+        stats.successful_decode_count += 1;
+        let encoded_len = data.len();
+        if tx.send(Chunk {
+            data,
+            format: options.force_format,
+            gain: None,
+            ts: None,
+            seq: None,
+            encoded_len,
+        })
+        .is_err() {
+            break;
+        }
+    }
+
+    stats
+}
+
+#[cfg(feature = "parquet")]
+fn read_parquet<R: BufRead>(mut reader: R, tx: &Sender<Chunk>, options: &InputOptions) -> InputStats {
+    let mut stats = InputStats::default();
+
+    let mut file = Vec::new();
+    match reader.read_to_end(&mut file) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            stats.timed_out = true;
+            return stats;
+        }
+        Err(e) => {
+            warn!("Failed to read Parquet input: {}", e);
+            return stats;
+        }
+    }
+
+    let column = options.parquet_column.as_deref().unwrap_or(parquet::DEFAULT_COLUMN);
+    let result = parquet::for_each_value(&file, column, |value| {
+        stats.line_count += 1;
+        let Some(data) = value else {
+            warn!("Row {} has a null {:?}, skipping", stats.line_count, column);
+            stats.count_parse_error(&ChunkerError::MissingPayload(column.to_string()));
+            return true;
+        };
+        if data.is_empty() {
+            warn!("Row {} is empty, skipping", stats.line_count);
+            stats.empty_chunks += 1;
+            return true;
+        }
+        if options.undersized(&data) {
+            debug!("Row {} is only {} bytes, skipping", stats.line_count, data.len());
+            stats.undersized_chunks += 1;
+            return true;
+        }
+
+        if !options.admit(&mut stats, data.len()) {
+            return false;
+        }
+        stats.successful_decode_count += 1;
+        let encoded_len = data.len();
+        tx.send(Chunk {
+            data,
+            format: options.force_format,
+            gain: None,
+            ts: None,
+            seq: None,
+            encoded_len,
+        })
+        .is_ok()
+    });
+    if let Err(e) = result {
+        warn!("Failed to read Parquet input: {}", e);
+        stats.parse_errors += 1;
+    }
+
+    stats
+}
+
+/// Decodes a parsed record and forwards it. Returns `false` once the
+/// consumer has gone away.
+fn send_record(
+    json_data: JsonData,
+    location: &str,
+    options: &InputOptions,
+    stats: &mut InputStats,
+    tx: &Sender<Chunk>,
+) -> bool {
+    stats.valid_json_count += 1;
+
+    let format = options.force_format.or_else(|| json_data.content_type.as_deref().and_then(|mime| {
+        let format = Format::from_mime(mime);
+        if format.is_none() {
+            warn!(
+                "Unrecognized content_type {:?} on {}, using --playback format",
+                mime, location
+            );
+        }
+        format
+    }));
+
+    let gain = json_data.gain.map(|gain| {
+        let (min, max) = GAIN_RANGE;
+        let clamped = if gain.is_nan() { 1.0 } else { gain.clamp(min, max) };
+        if clamped != gain {
+            warn!("Gain {} on {} is outside {}..={}, using {}", gain, location, min, max, clamped);
+        }
+        clamped
+    });
+
+    let mut data = options.buffer();
+    let decoded = match &json_data.data {
+        Payload::Text(text) => options.encoding.decode_into(text, &mut data),
+        Payload::Bytes(elements) => decode_json_bytes(elements, &mut data),
+    }
+    .map(|()| data);
+    if let Ok(data) = &decoded {
+        stats.encoded_bytes += json_data.data.len();
+        stats.decoded_bytes += data.len();
+    }
+
+    if let (Some(echo), Ok(data)) = (&options.echo, &decoded) {
+        let _ = echo.send(describe_record(location, options, &json_data, format, data));
+    }
+
+    match decoded {
+        Ok(data) if data.is_empty() => {
+            warn!("Empty data on {}, skipping", location);
+            stats.empty_chunks += 1;
+            true
+        }
+        Ok(data) if options.undersized(&data) => {
+            debug!("Only {} bytes of data on {}, skipping", data.len(), location);
+            stats.undersized_chunks += 1;
+            true
+        }
+        Ok(data) => {
+            let chunk = Chunk {
+                data,
+                format,
+                gain,
+                ts: json_data.ts,
+                seq: json_data.seq,
+                encoded_len: json_data.data.len(),
+            };
+            if !options.admit(stats, chunk.data.len()) {
+                return false;
+            }
+            stats.successful_decode_count += 1;
+            tx.send(chunk).is_ok()
+        }
+        Err(e) => {
+            warn!("Failed to decode {} data on {}: {}", options.encoding.name(), location, e);
+            stats.parse_errors += 1;
+            true
+        }
+    }
+}
+
+/// What the parser made of a record, as one line of canonical JSON (keys
+/// sorted, no whitespace) for `--jsonl-output`.
+fn describe_record(
+    location: &str,
+    options: &InputOptions,
+    json_data: &JsonData,
+    format: Option<Format>,
+    data: &[u8],
+) -> String {
+    let field = match &options.json_pointer {
+        Some(pointer) => pointer.clone(),
+        None => "data".to_string(),
+    };
+    serde_json::json!({
+        "location": location,
+        "field": field,
+        "encoding": options.encoding.name(),
+        "encoded_bytes": json_data.data.len(),
+        "decoded_bytes": data.len(),
+        "detected_format": Format::detect(data).map(Format::name),
+        "format": format.map(Format::name),
+        "gain": json_data.gain,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::mpsc;
+
+    fn run(input: &str, options: &InputOptions) -> (InputStats, Vec<Vec<u8>>) {
+        let (stats, chunks) = run_chunks(input, options);
+        (stats, chunks.into_iter().map(|chunk| chunk.data).collect())
+    }
+
+    fn run_chunks(input: &str, options: &InputOptions) -> (InputStats, Vec<Chunk>) {
+        let (tx, rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(input), &tx, options);
+        drop(tx);
+        (stats, rx.into_iter().collect())
+    }
+
+    const COMMENTED: &str = "# intro\n{\"data\":\"AQI=\"}\n  # indented\n\n{\"data\":\"Aw==\"}\n";
+
+    #[test]
+    fn skips_comment_lines_when_allowed() {
+        let options = InputOptions {
+            allow_comments: true,
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run(COMMENTED, &options);
+        assert_eq!(chunks, vec![vec![1, 2], vec![3]]);
+        assert_eq!(stats.line_count, 5);
+        assert_eq!(stats.comment_lines, 2);
+        assert_eq!(stats.valid_json_count, 2);
+    }
+
+    #[test]
+    fn comment_lines_are_errors_by_default() {
+        let (stats, chunks) = run(COMMENTED, &InputOptions::default());
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(stats.comment_lines, 0);
+        assert_eq!(stats.valid_json_count, 2);
+        assert_eq!(stats.parse_errors, 2);
+    }
+
+    #[test]
+    fn only_prefixed_lines_are_records() {
+        let input = concat!(
+            "2024-01-01 INFO starting up\n",
+            "AUDIO: {\"data\":\"AQI=\"}\n",
+            "{\"data\":\"BQ==\"}\n",
+            "  AUDIO:{\"data\":\"Aw==\"}\n",
+            "2024-01-01 INFO done\n",
+        );
+        let options = InputOptions {
+            line_prefix: Some("AUDIO:".to_string()),
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run(input, &options);
+        assert_eq!(chunks, vec![vec![1, 2], vec![3]]);
+        assert_eq!(stats.ignored_lines, 3);
+        assert_eq!(stats.valid_json_count, 2);
+    }
+
+    #[test]
+    fn truncated_final_line_is_not_a_parse_error() {
+        let (stats, chunks) = run("{\"data\":\"AQI=\"}\n{\"data\":\"Aw", &InputOptions::default());
+        assert_eq!(chunks, vec![vec![1, 2]]);
+        assert!(stats.truncated_tail);
+        assert_eq!(stats.parse_errors, 0);
+
+        // The same damage mid-stream is corruption.
+        let (stats, _) = run("{\"data\":\"Aw\n{\"data\":\"AQI=\"}", &InputOptions::default());
+        assert!(!stats.truncated_tail);
+        assert_eq!(stats.parse_errors, 1);
+    }
+
+    #[test]
+    fn reads_json_array() {
+        let options = InputOptions {
+            format: InputFormat::JsonArray,
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run("[{\"data\":\"AQI=\"},\n {\"data\":\"Aw==\"}]", &options);
+        assert_eq!(chunks, vec![vec![1, 2], vec![3]]);
+        assert_eq!(stats.line_count, 2);
+        assert_eq!(stats.successful_decode_count, 2);
+    }
+
+    #[test]
+    fn content_type_selects_format_per_chunk() {
+        let input = concat!(
+            "{\"content_type\":\"audio/wav\",\"data\":\"AQ==\"}\n",
+            "{\"content_type\":\"audio/mpeg\",\"data\":\"Ag==\"}\n",
+            "{\"content_type\":\"text/plain\",\"data\":\"Aw==\"}\n",
+            "{\"data\":\"BA==\"}\n",
+        );
+        let (_, chunks) = run_chunks(input, &InputOptions::default());
+        let formats: Vec<_> = chunks.iter().map(|chunk| chunk.format).collect();
+        assert_eq!(formats, vec![Some(Format::Wav), Some(Format::Mp3), None, None]);
+    }
+
+    #[test]
+    fn forced_format_ignores_content_type() {
+        let wav = crate::wav::tests::wav_file(1, 8000, &[0; 16]);
+        let line = format!(
+            "{{\"content_type\":\"audio/mpeg\",\"data\":\"{}\"}}\n",
+            general_purpose::STANDARD.encode(&wav)
+        );
+        let (_, chunks) = run_chunks(&line, &InputOptions::default());
+        assert_eq!(chunks[0].format, Some(Format::Mp3));
+
+        let options = InputOptions {
+            force_format: Some(Format::Wav),
+            ..InputOptions::default()
+        };
+        let (_, chunks) = run_chunks(&line, &options);
+        assert_eq!(chunks[0].format, Some(Format::Wav));
+        assert!(Format::Wav.decoder(chunks[0].data.clone()).is_ok());
+    }
+
+    #[test]
+    fn hex_payload_matches_base64_payload() {
+        let wav = crate::wav::tests::wav_file(1, 8000, &[1, 0, 255, 127]);
+        let base64_line = format!("{{\"data\":\"{}\"}}\n", general_purpose::STANDARD.encode(&wav));
+        let hex_line = format!("{{\"data\":\"{}\"}}\n", hex::encode(&wav));
+
+        let (_, from_base64) = run(&base64_line, &InputOptions::default());
+        let options = InputOptions {
+            encoding: Encoding::Hex,
+            ..InputOptions::default()
+        };
+        let (stats, from_hex) = run(&hex_line, &options);
+        assert_eq!(stats.successful_decode_count, 1);
+        assert_eq!(from_hex, vec![wav]);
+        assert_eq!(from_hex, from_base64);
+    }
+
+    #[test]
+    fn ascii85_payload_matches_base64_payload() {
+        let wav = crate::wav::tests::wav_file(1, 8000, &[1, 0, 255, 127, 0, 0]);
+        let base64_line = format!("{{\"data\":\"{}\"}}\n", general_purpose::STANDARD.encode(&wav));
+        let ascii85_line = serde_json::json!({ "data": ascii85::tests::encode(&wav) }).to_string();
+
+        let (_, from_base64) = run(&base64_line, &InputOptions::default());
+        let options = InputOptions {
+            encoding: Encoding::Ascii85,
+            ..InputOptions::default()
+        };
+        let (stats, from_ascii85) = run(&ascii85_line, &options);
+        assert_eq!(stats.successful_decode_count, 1);
+        assert_eq!(from_ascii85, vec![wav]);
+        assert_eq!(from_ascii85, from_base64);
+    }
+
+    #[test]
+    fn invalid_hex_is_not_sent() {
+        let options = InputOptions {
+            encoding: Encoding::Hex,
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run("{\"data\":\"52494g\"}\n", &options);
+        assert_eq!(stats.valid_json_count, 1);
+        assert_eq!(stats.successful_decode_count, 0);
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn reads_length_prefixed_frames() {
+        let mut input = Vec::new();
+        for frame in [&[1u8, 2, 3][..], &[4]] {
+            input.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            input.extend_from_slice(frame);
+        }
+        // A trailing frame cut short is dropped.
+        input.extend_from_slice(&[9, 0, 0, 0, 5]);
+
+        let options = InputOptions {
+            format: InputFormat::Framed,
+            ..InputOptions::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(input), &tx, &options);
+        drop(tx);
+        let chunks: Vec<_> = rx.into_iter().map(|chunk| chunk.data).collect();
+        assert_eq!(chunks, vec![vec![1, 2, 3], vec![4]]);
+        assert_eq!(stats.line_count, 3);
+        assert_eq!(stats.successful_decode_count, 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn reads_parquet_rows() {
+        let file = crate::parquet::tests::two_group_file("audio", &[b"RIFF", b"", b"abc", b"d"]);
+        let options = InputOptions {
+            format: InputFormat::Parquet,
+            parquet_column: Some("audio".to_string()),
+            ..InputOptions::default()
+        };
+        let (tx, rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(&file), &tx, &options);
+        drop(tx);
+        let chunks: Vec<_> = rx.into_iter().map(|chunk| chunk.data).collect();
+        assert_eq!(chunks, vec![b"RIFF".to_vec(), b"abc".to_vec(), b"d".to_vec()]);
+        assert_eq!(stats.line_count, 4);
+        assert_eq!(stats.empty_chunks, 1);
+
+        // The default column isn't there.
+        let options = InputOptions {
+            format: InputFormat::Parquet,
+            ..InputOptions::default()
+        };
+        let (tx, _rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(&file), &tx, &options);
+        assert_eq!((stats.line_count, stats.parse_errors), (0, 1));
+    }
+
+    fn with_pointer(pointer: &str) -> InputOptions {
+        InputOptions {
+            json_pointer: Some(parse_json_pointer(pointer).unwrap()),
+            ..InputOptions::default()
+        }
+    }
+
+    #[test]
+    fn json_pointer_into_nested_object() {
+        let input = concat!(
+            "{\"content_type\":\"audio/wav\",\"result\":{\"audio\":{\"data\":\"AQI=\"}}}\n",
+            "{\"result\":{\"audio\":{}}}\n",
+            "{\"result\":{\"audio\":{\"data\":7}}}\n",
+        );
+        let (stats, chunks) = run_chunks(input, &with_pointer("/result/audio/data"));
+        assert_eq!(
+            chunks,
+            vec![Chunk {
+                data: vec![1, 2],
+                format: Some(Format::Wav),
+                gain: None,
+                ts: None,
+                seq: None,
+                encoded_len: 4,
+            }]
+        );
+        assert_eq!(stats.valid_json_count, 1);
+    }
+
+    #[test]
+    fn json_pointer_with_array_index_and_escapes() {
+        let input = "{\"parts\":[{\"a/b\":\"AQ==\"},{\"a/b\":\"Ag==\"}]}\n";
+        let (_, chunks) = run(input, &with_pointer("/parts/1/a~1b"));
+        assert_eq!(chunks, vec![vec![2]]);
+
+        let value = serde_json::json!({"parts": [1]});
+        let err = extract_record(value, &with_pointer("/parts/0")).err().unwrap().to_string();
+        assert_eq!(err, "JSON pointer `/parts/0` resolves to a number, not a string");
+        assert!(parse_json_pointer("parts/0").is_err());
+    }
+
+    #[test]
+    fn unwraps_double_encoded_records() {
+        let wav = crate::wav::tests::wav_file(1, 8000, &[1, 0, 2, 0]);
+        let inner = format!("{{\"data\":\"{}\"}}", general_purpose::STANDARD.encode(&wav));
+        let line = format!("{}\n", serde_json::json!({ "payload": inner }));
+        let options = InputOptions {
+            unwrap_json: Some("payload".to_string()),
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run(&line, &options);
+        assert_eq!(stats.successful_decode_count, 1);
+        assert_eq!(chunks, vec![wav.clone()]);
+        assert!(Format::Wav.decoder(wav).is_ok());
+
+        for (value, expected) in [
+            (serde_json::json!({ "data": "AQ==" }), "no field `payload` to unwrap"),
+            (serde_json::json!({ "payload": 1 }), "field `payload` to unwrap is a number, not a string"),
+            (serde_json::json!({ "payload": "{oops" }), "field `payload` doesn't hold valid JSON"),
+        ] {
+            let err = extract_record(value, &options).err().unwrap().to_string();
+            assert!(err.starts_with(expected), "{}", err);
+        }
+    }
+
+    #[test]
+    fn non_string_data_is_counted_apart_from_missing_data() {
+        let input = "{\"data\":null}\n{\"data\":123}\n{\"data\":{}}\n{\"payload\":\"AQ==\"}\n{\"data\":\"AQ==\"}\n";
+        let (stats, chunks) = run(input, &InputOptions::default());
+        assert_eq!(chunks, vec![vec![1]]);
+        assert_eq!(stats.parse_errors, 4);
+        assert_eq!((stats.non_string_payloads, stats.missing_payloads), (3, 1));
+
+        for (value, found) in [
+            (serde_json::json!({ "data": null }), "null"),
+            (serde_json::json!({ "data": 123 }), "a number"),
+            (serde_json::json!({ "data": {} }), "an object"),
+        ] {
+            let err = extract_record(value, &InputOptions::default()).err().unwrap().to_string();
+            assert_eq!(err, format!("field `data` resolves to {}, not a string", found));
+        }
+    }
+
+    #[test]
+    fn echoes_one_line_per_decoded_record() {
+        let (echo, lines) = mpsc::channel();
+        let options = InputOptions {
+            echo: Some(echo),
+            ..InputOptions::default()
+        };
+        let input = "{\"data\":\"UklGRg==\",\"content_type\":\"audio/wav\"}\nnot json\n{\"data\":\"AQ==\"}\n";
+        let (stats, _) = run(input, &options);
+        drop(options);
+        let lines: Vec<String> = lines.into_iter().collect();
+        assert_eq!(lines.len(), stats.successful_decode_count);
+        assert_eq!(
+            lines[0],
+            "{\"decoded_bytes\":4,\"detected_format\":null,\"encoded_bytes\":8,\"encoding\":\"base64\",\
+             \"field\":\"data\",\"format\":\"wav\",\"gain\":null,\"location\":\"line 1\"}"
+        );
+        assert!(lines[1].contains("\"location\":\"line 3\""));
+    }
+
+    #[test]
+    fn gain_is_clamped() {
+        let input = "{\"gain\":0.5,\"data\":\"AQ==\"}\n{\"gain\":10,\"data\":\"AQ==\"}\n{\"data\":\"AQ==\"}\n";
+        let (_, chunks) = run_chunks(input, &InputOptions::default());
+        let gains: Vec<_> = chunks.iter().map(|chunk| chunk.gain).collect();
+        assert_eq!(gains, vec![Some(0.5), Some(4.0), None]);
+    }
+
+    #[test]
+    fn reads_timestamps() {
+        let input = "{\"ts\":12345,\"data\":\"AQ==\"}\n{\"ts\":-1,\"data\":\"AQ==\"}\n{\"data\":\"AQ==\"}\n";
+        let (_, chunks) = run_chunks(input, &InputOptions::default());
+        let stamps: Vec<_> = chunks.iter().map(|chunk| chunk.ts).collect();
+        assert_eq!(stamps, vec![Some(12345), None, None]);
+    }
+
+    #[test]
+    fn skips_lines_over_max_line_bytes() {
+        let options = InputOptions {
+            max_line_bytes: Some(16),
+            ..InputOptions::default()
+        };
+        let long = format!("{{\"data\":\"{}\"}}", "A".repeat(64));
+        let input = format!("{{\"data\":\"AQI=\"}}\n{}\n{{\"data\":\"Aw==\"}}\n", long);
+        let (stats, chunks) = run(&input, &options);
+        assert_eq!(chunks, vec![vec![1, 2], vec![3]]);
+        assert_eq!(stats.line_count, 3);
+        assert_eq!(stats.oversized_lines, 1);
+        assert_eq!(stats.parse_errors, 0);
+
+        let mut reader = Cursor::new(format!("{}\nshort\n", long));
+        let mut buf = Vec::new();
+        assert_eq!(read_capped(&mut reader, b'\n', &mut buf, Some(16)).unwrap(), (long.len() + 1, true));
+        buf.clear();
+        assert_eq!(read_capped(&mut reader, b'\n', &mut buf, Some(16)).unwrap(), (6, false));
+        assert_eq!(buf, b"short\n");
+    }
+
+    #[test]
+    fn json_bytes_payloads_are_byte_arrays() {
+        let options = InputOptions {
+            encoding: Encoding::JsonBytes,
+            ..InputOptions::default()
+        };
+        let input = concat!(
+            "{\"data\":[82, 73,70,70]}\n{\"data\":[1,256]}\n{\"data\":[1,-1]}\n{\"data\":[1.5]}\n",
+            "{\"data\":\"[1,2]\"}\n{\"data\":{\"0\":1}}\n{\"data\":[]}\n",
+        );
+        let (stats, chunks) = run(input, &options);
+        assert_eq!(chunks, vec![b"RIFF".to_vec()]);
+        assert_eq!((stats.parse_errors, stats.empty_chunks), (5, 1));
+        // Encoded bytes are counted in array elements.
+        assert_eq!((stats.encoded_bytes, stats.decoded_bytes), (4, 4));
+        // Only arrays are accepted as payloads under json-bytes.
+        let (_, chunks) = run("{\"data\":[1,2]}\n", &InputOptions::default());
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn reads_sequence_numbers() {
+        let input = "{\"seq\":7,\"data\":\"AQ==\"}\n{\"seq\":\"8\",\"data\":\"AQ==\"}\n";
+        let (_, chunks) = run_chunks(input, &InputOptions::default());
+        let seqs: Vec<_> = chunks.iter().map(|chunk| chunk.seq).collect();
+        assert_eq!(seqs, vec![Some(7), None]);
+    }
+
+    #[test]
+    fn strips_bom_from_first_line() {
+        let (stats, chunks) = run("\u{feff}{\"data\":\"AQI=\"}\n", &InputOptions::default());
+        assert_eq!(chunks, vec![vec![1, 2]]);
+        assert_eq!(stats.valid_json_count, 1);
+    }
+
+    #[test]
+    fn lenient_json_accepts_trailing_commas() {
+        let input = "{\"data\":\"AQI=\", \"tags\":[\"a,\",],}\n";
+        let (stats, _) = run(input, &InputOptions::default());
+        assert_eq!(stats.valid_json_count, 0);
+
+        let options = InputOptions {
+            lenient_json: true,
+            ..InputOptions::default()
+        };
+        let (stats, chunks) = run(input, &options);
+        assert_eq!(chunks, vec![vec![1, 2]]);
+        assert_eq!(stats.valid_json_count, 1);
+        assert_eq!(strip_trailing_commas("[1,,]"), "[1,]");
+    }
+
+    #[test]
+    fn empty_data_is_skipped() {
+        let input = "{\"data\":\"\"}\n{\"data\":\"AQI=\"}\n";
+        let (stats, chunks) = run(input, &InputOptions::default());
+        assert_eq!(chunks, vec![vec![1, 2]]);
+        assert_eq!(stats.valid_json_count, 2);
+        assert_eq!(stats.successful_decode_count, 1);
+        assert_eq!(stats.empty_chunks, 1);
+    }
+
+    #[test]
+    fn undersized_chunks_are_dropped() {
+        let options = InputOptions {
+            min_chunk_size: 4,
+            ..InputOptions::default()
+        };
+        let header = crate::wav::tests::wav_file(1, 8000, &[]);
+        let input = format!(
+            "{{\"data\":\"{}\"}}\n{{\"data\":\"AQ==\"}}\n{{\"data\":\"AQIDBA==\"}}\n",
+            general_purpose::STANDARD.encode(&header)
+        );
+        let (stats, chunks) = run(&input, &options);
+        assert_eq!(chunks, vec![header, vec![1, 2, 3, 4]]);
+        assert_eq!(stats.undersized_chunks, 1);
+        assert_eq!(stats.successful_decode_count, 2);
+    }
+
+    #[test]
+    fn strict_lines_reject_concatenated_objects() {
+        let options = InputOptions {
+            strict_lines: true,
+            ..InputOptions::default()
+        };
+        assert_eq!(
+            parse_value("{\"data\":\"AQ==\"}{\"data\":\"Ag==\"}", true).unwrap_err().to_string(),
+            "strict: more than one JSON value"
+        );
+        assert!(parse_value("[1]", true).unwrap_err().to_string().starts_with("strict: expected a JSON object"));
+
+        let input = "{\"data\":\"AQ==\"} {\"data\":\"Ag==\"}\n{\"data\":\"Aw==\"}\n";
+        let (stats, chunks) = run(input, &options);
+        assert_eq!(chunks, vec![vec![3]]);
+        assert_eq!(stats.valid_json_count, 1);
+    }
+
+    #[test]
+    fn reads_json_text_sequences() {
+        let options = InputOptions {
+            format: InputFormat::JsonSeq,
+            ..InputOptions::default()
+        };
+        let input = "\u{1e}{\"data\":\"AQI=\"}\n\u{1e}\u{1e}{\"data\":\n\"Aw==\"}\n\u{1e}{\"data\":\u{1e}{\"data\":\"BA==\"}";
+        let (stats, chunks) = run(input, &options);
+        // The third record is truncated; the last one lacks its newline.
+        assert_eq!(chunks, vec![vec![1, 2], vec![3], vec![4]]);
+        assert_eq!(stats.line_count, 4);
+        assert_eq!(stats.valid_json_count, 3);
+    }
+
+    #[test]
+    fn tracks_encoding_overhead() {
+        // 6 and 3 bytes, as 8 and 4 base64 characters.
+        let input = "{\"data\":\"AQIDBAUG\"}\n{\"data\":\"AQID\"}\n{\"data\":\"!\"}\n";
+        let (stats, _) = run(input, &InputOptions::default());
+        assert_eq!((stats.encoded_bytes, stats.decoded_bytes), (12, 9));
+        assert_eq!(stats.expansion_ratio(), Some(12.0 / 9.0));
+        assert_eq!(InputStats::default().expansion_ratio(), None);
+    }
+
+    #[test]
+    fn invalid_utf8_line_is_skipped() {
+        let mut input = b"{\"data\":\"AQ==\"}\r\n".to_vec();
+        input.extend_from_slice(b"{\"data\":\"\xff\xfe\"}\n");
+        input.extend_from_slice(b"{\"data\":\"Ag==\"}");
+
+        let (tx, rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(input), &tx, &InputOptions::default());
+        drop(tx);
+        let chunks: Vec<_> = rx.into_iter().map(|chunk| chunk.data).collect();
+        assert_eq!(chunks, vec![vec![1], vec![2]]);
+        assert_eq!(stats.line_count, 3);
+        assert_eq!(stats.valid_json_count, 2);
+    }
+
+    #[test]
+    fn byte_limit_stops_mid_stream() {
+        let options = InputOptions {
+            limit_bytes: Some(5),
+            ..InputOptions::default()
+        };
+        let input = "{\"data\":\"AQI=\"}\n".repeat(4);
+        let (stats, chunks) = run(&input, &options);
+        assert_eq!(chunks, vec![vec![1, 2], vec![1, 2]]);
+        assert_eq!(stats.sent_bytes, 4);
+        assert!(stats.byte_limit_reached);
+        assert_eq!(stats.line_count, 3);
+        // The chunk over the limit isn't counted as decoded.
+        assert_eq!(stats.successful_decode_count, 2);
+
+        let framed = [&2u32.to_le_bytes()[..], &[1, 2]].concat().repeat(4);
+        let options = InputOptions {
+            format: InputFormat::Framed,
+            ..options
+        };
+        let (tx, rx) = mpsc::channel();
+        let stats = read_input(Cursor::new(framed), &tx, &options);
+        drop(tx);
+        assert_eq!(rx.into_iter().count(), 2);
+        assert!(stats.byte_limit_reached);
+        assert_eq!(stats.successful_decode_count, 2);
+    }
+}
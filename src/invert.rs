@@ -0,0 +1,117 @@
+use rodio::Source;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Channels negated by `--invert-channel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvertChannel {
+    Left,
+    Right,
+    Both,
+}
+
+impl InvertChannel {
+    pub const NAMES: [&'static str; 3] = ["left", "right", "both"];
+
+    /// Whether the channel at `index` within a frame is inverted. Channels
+    /// past the front pair are never touched.
+    fn inverts(self, index: u16) -> bool {
+        matches!(
+            (self, index),
+            (InvertChannel::Left | InvertChannel::Both, 0) | (InvertChannel::Right | InvertChannel::Both, 1)
+        )
+    }
+}
+
+impl FromStr for InvertChannel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(InvertChannel::Left),
+            "right" => Ok(InvertChannel::Right),
+            "both" => Ok(InvertChannel::Both),
+            _ => Err(format!("unknown channel `{}`", s)),
+        }
+    }
+}
+
+/// Flips the polarity of the selected channels, deinterleaving by the
+/// source's channel count.
+pub struct Invert<S> {
+    input: S,
+    channel: InvertChannel,
+    index: u16,
+}
+
+impl<S> Invert<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(input: S, channel: InvertChannel) -> Self {
+        Self {
+            input,
+            channel,
+            index: 0,
+        }
+    }
+}
+
+impl<S> Iterator for Invert<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.input.next()?;
+        let inverted = self.channel.inverts(self.index);
+        self.index = (self.index + 1) % self.input.channels().max(1);
+        // -32768 has no positive counterpart; saturate to 32767.
+        Some(if inverted { sample.saturating_neg() } else { sample })
+    }
+}
+
+impl<S> Source for Invert<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn invert(channel: InvertChannel, samples: Vec<i16>) -> Vec<i16> {
+        Invert::new(SamplesBuffer::new(2, 8000, samples), channel).collect()
+    }
+
+    #[test]
+    fn both_negates_every_sample() {
+        let frames = vec![100, -200, 0, i16::MIN];
+        assert_eq!(invert(InvertChannel::Both, frames), vec![-100, 200, 0, i16::MAX]);
+    }
+
+    #[test]
+    fn one_channel_leaves_the_other() {
+        let frames = vec![100, -200, 300, -400];
+        assert_eq!(invert(InvertChannel::Left, frames.clone()), vec![-100, -200, -300, -400]);
+        assert_eq!(invert(InvertChannel::Right, frames), vec![100, 200, 300, 400]);
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+/// What part of the next chunk fits within a playback duration limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// The whole chunk fits.
+    Whole,
+    /// Only the leading part of the chunk fits.
+    Partial(Duration),
+    /// The limit has been reached; nothing more should be played.
+    Exhausted,
+}
+
+/// Tracks cumulative appended duration against `--limit-duration`.
+#[derive(Debug, Clone)]
+pub struct DurationLimit {
+    limit: Duration,
+    elapsed: Duration,
+}
+
+impl DurationLimit {
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            limit,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Accounts for a chunk of `duration` and reports how much of it to play.
+    pub fn admit(&mut self, duration: Duration) -> Budget {
+        let remaining = self.limit.saturating_sub(self.elapsed);
+        if remaining.is_zero() {
+            return Budget::Exhausted;
+        }
+        if duration <= remaining {
+            self.elapsed += duration;
+            Budget::Whole
+        } else {
+            self.elapsed = self.limit;
+            Budget::Partial(remaining)
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.elapsed >= self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::{parse_wav_info, tests::wav_file};
+
+    #[test]
+    fn stops_within_known_length_wav_stream() {
+        // 100 ms per chunk at 8 kHz mono 16-bit.
+        let chunk = wav_file(1, 8000, &[0; 1600]);
+        let duration = parse_wav_info(&chunk).unwrap().duration();
+        assert_eq!(duration, Duration::from_millis(100));
+
+        let mut limit = DurationLimit::new(Duration::from_millis(250));
+        assert_eq!(limit.admit(duration), Budget::Whole);
+        assert_eq!(limit.admit(duration), Budget::Whole);
+        assert!(!limit.is_exhausted());
+        assert_eq!(limit.admit(duration), Budget::Partial(Duration::from_millis(50)));
+        assert!(limit.is_exhausted());
+        assert_eq!(limit.admit(duration), Budget::Exhausted);
+    }
+}
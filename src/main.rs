@@ -1,150 +1,1618 @@
-use anyhow::Result;
-use base64::{engine::general_purpose, Engine as _};
+use anyhow::{anyhow, Context, Result};
+use clap::parser::ValueSource;
 use clap::{Arg, Command};
-use rodio::{Decoder, OutputStream, Sink};
-use serde::Deserialize;
-use std::io::{self, BufRead, Cursor};
-use std::sync::mpsc;
-use std::thread;
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{OutputStream, Sink, Source};
+use std::any::Any;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info, warn, Level};
+use tracing_subscriber::fmt::time::{SystemTime, Uptime};
 
-#[derive(Deserialize)]
-struct JsonData {
-    data: String,
+mod agc;
+mod ascii85;
+mod bitdepth;
+mod capture;
+mod config;
+mod consumer;
+mod control;
+mod cues;
+mod downmix;
+mod error;
+mod exit;
+mod follow;
+mod format;
+mod framelog;
+mod hook;
+mod inject;
+mod input;
+mod invert;
+mod limit;
+mod manifest;
+mod marker;
+mod merge;
+mod metadata;
+mod mp3;
+#[cfg(feature = "object-store")]
+mod object;
+mod output;
+mod pacing;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod passthrough;
+mod pcm;
+mod playback;
+mod pool;
+mod reconstruct;
+mod record;
+mod remap;
+mod resample;
+mod segment;
+mod seqgap;
+mod serve;
+mod silence;
+mod spill;
+mod stream;
+mod throughput;
+mod timeout;
+mod wav;
+
+/// A decoded chunk ready to be queued on the sink.
+pub type BoxedSource = Box<dyn Source<Item = i16> + Send>;
+
+use agc::Agc;
+use capture::CaptureRing;
+use config::Config;
+use consumer::{Consumer, DecodeStats};
+use cues::Cues;
+use exit::Exit;
+use follow::Follow;
+use format::Format;
+use framelog::FrameLog;
+use hook::{AsrHook, ChunkHook};
+use inject::Injector;
+use input::{Chunk, Encoding, InputFormat, InputOptions, InputStats};
+use invert::InvertChannel;
+use limit::DurationLimit;
+use manifest::Manifest;
+use marker::Marker;
+#[cfg(feature = "object-store")]
+use object::ObjectUrl;
+use output::{AudioWriter, OutputFormat};
+use pacing::Pacer;
+use pcm::Pcm;
+use playback::Outputs;
+use reconstruct::{FrameAligner, Reconstructor, WavFraming};
+use record::{Tee, WavRecorder};
+use remap::ChannelRemap;
+use resample::{Quality, Resampler};
+use segment::{Destination, SegmentWriter};
+use seqgap::GapConcealer;
+use throughput::Throughput;
+use timeout::IdleTimeout;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            Exit::of(&e).into()
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    let matches = Command::new("jsonl_player")
-        .version("1.0")
-        .author("Your Name")
-        .about("Plays audio chunks from JSONL stream")
-        .arg(
-            Arg::new("playback")
-                .long("playback")
-                .value_name("FORMAT")
-                .help("Audio format for playback")
-                .value_parser(["mp3", "wav"])
-                .default_value("mp3")
-        )
-        .arg(
-            Arg::new("verbose")
-                .long("verbose")
-                .short('v')
-                .help("Enable verbose output")
-                .action(clap::ArgAction::SetTrue)
-        )
-        .get_matches();
+fn run() -> Result<()> {
+    let mut matches = cli().get_matches();
+    if let Some(path) = matches.get_one::<String>("config") {
+        let config = Config::load(path).map_err(|e| anyhow!(e)).context(Exit::Config)?;
+        let from_config =
+            config.to_args(|id| matches.value_source(id) == Some(ValueSource::CommandLine));
+        let mut args: Vec<OsString> = std::env::args_os().collect();
+        args.splice(1..1, from_config.into_iter().map(OsString::from));
+        matches = cli().get_matches_from(args);
+    }
+    if matches.get_flag("list-formats") {
+        print!("{}", Format::listing());
+        return Ok(());
+    }
 
-    let playback_format = matches.get_one::<String>("playback").unwrap();
-    let verbose = matches.get_flag("verbose");
-    
-    if verbose {
-        println!("Using playback format: {}", playback_format);
+    // --force-format beats a record's content_type, which beats --playback.
+    let force_format: Option<Format> =
+        matches.get_one::<String>("force-format").map(|name| name.parse().unwrap());
+    let playback_format: Format = force_format
+        .unwrap_or_else(|| matches.get_one::<String>("playback").unwrap().parse().unwrap());
+    playback_format.check_available().map_err(|e| anyhow!(e))?;
+    let trace_wav = matches.get_flag("trace-reconstruction");
+    if trace_wav && playback_format != Format::Wav {
+        return Err(anyhow!("--trace-reconstruction only applies to --playback wav"));
     }
+    let level = if trace_wav {
+        Level::TRACE
+    } else if matches.get_flag("verbose") {
+        Level::DEBUG
+    } else if matches.get_flag("summary-only") {
+        Level::ERROR
+    } else {
+        Level::WARN
+    };
+    init_logging(level, matches.get_one::<String>("timestamps").unwrap());
+
+    let devices: Vec<Option<&str>> = match matches.get_many::<String>("device") {
+        Some(names) => names.map(|name| Some(name.as_str())).collect(),
+        None => vec![None],
+    };
+    if matches.get_flag("print-device-config") {
+        for name in devices {
+            let device = find_device(name).context(Exit::Device)?;
+            println!("{}", describe_device(&device).context(Exit::Device)?);
+        }
+        return Ok(());
+    }
+
+    if matches.get_flag("watch") {
+        if !stdin_is_file() {
+            return Err(anyhow!("--watch follows a file redirected to stdin, e.g. jsonl_player --watch < capture.jsonl"));
+        }
+        if let Some(format) = matches.get_one::<String>("format").filter(|format| ["json-array", "parquet"].contains(&format.as_str())) {
+            return Err(anyhow!("--watch can't follow --format {}, which is read whole before playing", format));
+        }
+    }
+    check_format(&matches)?;
+
+    // Fail before any device is opened if the object can't be read.
+    #[cfg(feature = "object-store")]
+    let object_input = matches
+        .get_one::<ObjectUrl>("url")
+        .map(|url| url.open())
+        .transpose()
+        .map_err(|e| anyhow!(e))?;
+    #[cfg(not(feature = "object-store"))]
+    let object_input: Option<Box<dyn io::Read + Send>> = None;
+
+    let allow_multichannel = matches.get_flag("allow-multichannel");
+    let error_silence = matches
+        .get_flag("replace-on-error")
+        .then(|| Duration::from_millis(*matches.get_one::<u64>("error-silence-ms").unwrap()));
+    let warmup = match matches.get_one::<u64>("warmup-ms") {
+        Some(ms) => Duration::from_millis(*ms),
+        None => {
+            let defaults = format::default_buffering(playback_format);
+            debug!("Default buffering for {}: {:?}", playback_format, defaults);
+            defaults.warmup
+        }
+    };
+    let warmup = Some(warmup).filter(|warmup| !warmup.is_zero());
+    let start_delay = matches
+        .get_one::<u64>("start-delay")
+        .map(|ms| Duration::from_millis(*ms))
+        .filter(|delay| !delay.is_zero());
+    let chunk_gap = matches
+        .get_one::<u64>("chunk-gap")
+        .map(|ms| Duration::from_millis(*ms))
+        .filter(|gap| !gap.is_zero());
+    let marker = matches.get_one::<u64>("marker-every").map(|&every| Marker {
+        every: usize::try_from(every).unwrap_or(usize::MAX),
+        frequency: *matches.get_one::<f32>("marker-freq").unwrap(),
+        duration: Duration::from_millis(*matches.get_one::<u64>("marker-ms").unwrap()),
+    });
+    let capture = matches.get_one::<usize>("capture-ring").map(|n| CaptureRing::new(*n));
+    let exit_on_empty = matches.get_flag("exit-on-empty");
+    let invert: Option<InvertChannel> = matches
+        .get_one::<String>("invert-channel")
+        .map(|name| name.parse().unwrap());
+    let mp3_stream = matches.get_flag("mp3-stream");
+    if mp3_stream && playback_format != Format::Mp3 {
+        return Err(anyhow!("--mp3-stream only applies to --playback mp3"));
+    }
+    if matches.get_flag("reverse") && playback_format == Format::Mp3 {
+        return Err(anyhow!("--reverse can't play mp3 backwards; it needs PCM, FLAC or Vorbis chunks"));
+    }
+    if matches.get_flag("spill-to-disk") && !matches.get_flag("reverse") && !matches.get_flag("loop") {
+        return Err(anyhow!("--spill-to-disk only applies to --reverse and --loop, which buffer the whole stream"));
+    }
+    if matches.get_flag("loop") && devices.len() > 1 {
+        // Every device but one gets a collected copy of each source, and a
+        // loop never ends.
+        return Err(anyhow!("--loop plays on one device only; give at most one --device"));
+    }
+    if invert.is_some() && playback_format != Format::Wav {
+        return Err(anyhow!("--invert-channel only applies to PCM, use it with --playback wav"));
+    }
+    let remap = matches.get_one::<ChannelRemap>("remap").cloned();
+    if remap.is_some() && playback_format != Format::Wav {
+        return Err(anyhow!("--remap only applies to PCM, use it with --playback wav"));
+    }
+    let reconstruct = if matches.get_flag("no-reconstruct") {
+        None
+    } else {
+        let mut reconstructor = Reconstructor::new(matches.get_flag("reconstruct").then_some(WavFraming::HeaderOnce));
+        if let Some(path) = matches.get_one::<String>("header-cache") {
+            if matches.get_flag("resume") {
+                let header = reconstruct::load_header(Path::new(path)).map_err(|e| anyhow!(e))?;
+                info!("Resuming with the WAV header cached in {}", path);
+                reconstructor = reconstructor.resume(header);
+            }
+            reconstructor = reconstructor.with_cache(Path::new(path));
+        }
+        Some(reconstructor)
+    };
+    let intro = matches.get_one::<String>("prepend-file").map(|path| read_audio_file(path)).transpose()?;
+    let outro = matches.get_one::<String>("append-file").map(|path| read_audio_file(path)).transpose()?;
+    let limit = matches
+        .get_one::<Duration>("limit-duration")
+        .map(|limit| DurationLimit::new(*limit));
     
-    let (_stream, stream_handle) = OutputStream::try_default()?;
-    let sink = Sink::try_new(&stream_handle)?;
+    if force_format.is_some() {
+        info!("Using playback format: {} (forced)", playback_format);
+    } else {
+        info!("Using playback format: {}", playback_format);
+    }
     
-    if verbose {
-        println!("Audio output initialized");
-    }
-
-    let (tx, rx) = mpsc::channel::<Vec<u8>>();
-
-    let format = playback_format.clone();
-    let consumer_thread = thread::spawn(move || {
-        let mut chunk_count = 0;
-        let mut successful_chunks = 0;
-        
-        for decoded_data in rx {
-            chunk_count += 1;
-            
-            if verbose {
-                println!("Processing audio chunk {}, size: {} bytes", chunk_count, decoded_data.len());
+    let passthrough = matches.get_flag("passthrough");
+    let writer = match matches.get_one::<String>("out").filter(|_| !passthrough) {
+        Some(path) => {
+            let output_format = match matches.get_one::<String>("output-format") {
+                Some(name) => name.parse().unwrap(),
+                None => OutputFormat::default_for(playback_format),
+            };
+            output_format.check_input(playback_format).map_err(|e| anyhow!(e))?;
+            let out_bits = matches.get_one::<String>("out-bits").map(|bits| bits.parse().unwrap());
+            if out_bits.is_some() && output_format == OutputFormat::Mp3 {
+                return Err(anyhow!("--out-bits converts PCM and doesn't apply to mp3 output"));
             }
-            
-            let audio_file = Cursor::new(decoded_data);
-            let source = match format.as_str() {
-                "mp3" => Decoder::new_mp3(audio_file),
-                "wav" => Decoder::new_wav(audio_file),
-                _ => unreachable!("Invalid format should be caught by clap"),
+            let chunk_bytes = matches
+                .get_one::<u64>("chunk-bytes")
+                .map_or(output::DEFAULT_CHUNK_BYTES, |&n| usize::try_from(n).unwrap_or(usize::MAX));
+            let dither = matches.get_flag("dither");
+            let configure = move |writer: AudioWriter<BufWriter<File>>| {
+                writer
+                    .with_chunk_bytes(chunk_bytes)
+                    .with_out_bits(out_bits, dither)
             };
-            
-            match source {
-                Ok(source) => {
-                    successful_chunks += 1;
-                    if verbose {
-                        println!("Successfully decoded audio chunk {}", chunk_count);
+            match matches.get_one::<Duration>("out-segment") {
+                Some(&length) => {
+                    if playback_format != Format::Wav || output_format != OutputFormat::Wav {
+                        return Err(anyhow!("--out-segment splits WAV files, use it with --playback wav and WAV output"));
                     }
-                    sink.append(source);
+                    info!("Writing {:?} WAV segments named after {}", length, path);
+                    Some(Destination::Segments(SegmentWriter::new(Path::new(path), length, configure)))
                 }
-                Err(e) => {
-                    eprintln!("Failed to decode audio chunk {}: {}", chunk_count, e);
+                None => {
+                    let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+                    info!("Writing {} audio to {}", output_format, path);
+                    let writer = AudioWriter::new(BufWriter::new(file), output_format);
+                    Some(Destination::File(configure(writer)))
                 }
             }
         }
-        
-        if verbose {
-            println!("Processed {} audio chunks total ({} successful)", chunk_count, successful_chunks);
+        None => None,
+    };
+
+    // --passthrough requires --out.
+    let passthrough_out: Option<Box<dyn Write + Send>> = match matches.get_one::<String>("out") {
+        Some(path) if passthrough && path == "-" => Some(Box::new(io::stdout())),
+        Some(path) if passthrough => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            info!("Forwarding decoded chunks to {}", path);
+            Some(Box::new(file))
+        }
+        _ => None,
+    };
+
+    let manifest = match matches.get_one::<String>("manifest") {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            Some(Manifest::new(BufWriter::new(file)))
         }
-        
-        // Wait for the last sound to finish playing.
-        sink.sleep_until_end();
-        
-        if verbose {
-            println!("Audio playback finished");
+        None => None,
+    };
+    let cues = match matches.get_one::<String>("cues") {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            Some(Cues::new(BufWriter::new(file)).with_context(|| format!("Failed to write {}", path))?)
         }
-    });
+        None => None,
+    };
+    let frame_log = match matches.get_one::<String>("frame-log") {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            Some(FrameLog::new(BufWriter::new(file)).with_context(|| format!("Failed to write {}", path))?)
+        }
+        None => None,
+    };
 
-    let stdin = io::stdin();
-    let handle = stdin.lock();
+    let hook = match matches.get_one::<String>("on-chunk-cmd") {
+        Some(command) => {
+            Some(ChunkHook::spawn(command).with_context(|| format!("Failed to run `{}`", command))?)
+        }
+        None => None,
+    };
+    let asr = match matches.get_one::<String>("asr-cmd") {
+        Some(command) => {
+            if playback_format != Format::Wav {
+                return Err(anyhow!("--asr-cmd is sent PCM, use it with --playback wav"));
+            }
+            let captions = |line: String| {
+                let _ = writeln!(io::stdout().lock(), "{}", line);
+            };
+            Some(AsrHook::spawn(command, captions).with_context(|| format!("Failed to run `{}`", command))?)
+        }
+        None => None,
+    };
 
-    let mut line_count = 0;
-    let mut valid_json_count = 0;
-    let mut successful_decode_count = 0;
+    let mut _streams = Vec::new();
+    let mut sinks = Vec::new();
+    if matches.get_flag("dry-run") || writer.is_some() || passthrough {
+        info!("Dry run: audio output disabled");
+    } else {
+        for &device in &devices {
+            let (stream, sink) = open_output(device)?;
+            info!("Audio output initialized on {}", device.unwrap_or("the default device"));
+            _streams.push(stream);
+            sinks.push(sink);
+        }
+    }
+    // Every device is fed the same samples, so they're resampled for the
+    // first; rodio converts for any other device at another rate.
+    let resample = match devices.first().filter(|_| !sinks.is_empty()).and_then(|&device| device_rate(device)) {
+        Some(rate) => {
+            let quality: Quality = matches.get_one::<String>("resample-quality").unwrap().parse().unwrap();
+            info!("Resampling chunks at other rates to the device's {} Hz with the {} resampler", rate, quality);
+            Some(Resampler::new(rate, quality))
+        }
+        None => None,
+    };
+    let recorder = match matches.get_one::<String>("record") {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+            info!("Recording playback to {}", path);
+            Some(WavRecorder::new(BufWriter::new(file)))
+        }
+        None => None,
+    };
+    let mut outputs = Tee::new(Outputs::new(sinks), recorder);
 
-    for line in handle.lines() {
-        let line = match line {
-            Ok(line) => line,
-            Err(_) => break,
-        };
+    let (tx, rx) = mpsc::channel::<Chunk>();
+    let (right_input, right_rx) = match matches.get_one::<String>("right-input") {
+        Some(path) => {
+            let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+            info!("Reading the right channel from {}", path);
+            let (right_tx, right_rx) = mpsc::channel::<Chunk>();
+            (Some((file, right_tx)), Some(right_rx))
+        }
+        None => (None, None),
+    };
+    let controls = if matches.get_flag("interactive") {
+        let (control_tx, control_rx) = mpsc::channel();
+        control::spawn_tty(control_tx).context("Failed to open the terminal for --interactive")?;
+        info!("Interactive: press r and Enter to replay the last chunk");
+        Some(control_rx)
+    } else {
+        None
+    };
 
-        line_count += 1;
+    let consumer = Consumer {
+        playback_format,
+        allow_multichannel,
+        error_silence,
+        warmup,
+        start_delay,
+        chunk_gap,
+        marker,
+        exit_on_empty,
+        shutdown_timeout: matches.get_one::<Duration>("shutdown-timeout").copied(),
+        limit,
+        capture,
+        writer,
+        manifest,
+        frame_log,
+        cues,
+        hook,
+        asr,
+        invert,
+        remap,
+        wav_validate: matches.get_flag("wav-validate"),
+        strict: matches.get_flag("strict"),
+        trace_wav,
+        mp3_stream,
+        right: right_rx,
+        controls,
+        max_conceal: matches
+            .get_flag("repeat-on-underrun")
+            .then(|| *matches.get_one::<usize>("max-conceal").unwrap()),
+        pacer: matches.get_flag("use-timestamps").then(Pacer::new),
+        sink_queue_limit: matches
+            .get_one::<u64>("sink-queue-limit")
+            .map(|&n| usize::try_from(n).unwrap_or(usize::MAX)),
+        max_queued: matches.get_one::<Duration>("max-queued-duration").copied(),
+        reverse: matches.get_flag("reverse"),
+        looping: matches.get_flag("loop"),
+        spill: matches.get_flag("spill-to-disk").then(std::env::temp_dir),
+        conceal_gaps: matches.get_flag("conceal-gaps").then(GapConcealer::new),
+        intro,
+        outro,
+        resample,
+        agc: matches.get_flag("agc").then(|| {
+            Agc::new(
+                *matches.get_one::<f64>("agc-target").unwrap(),
+                matches.get_one::<Duration>("agc-speed").unwrap().as_secs_f64(),
+            )
+        }),
+        inject: matches.get_one::<String>("inject-file").map(|path| Injector::new(Path::new(path))),
+        reconstruct,
+        align: matches.get_flag("align-frames").then(FrameAligner::default),
+    };
+    let mut input_options = InputOptions {
+        format: match matches.get_one::<String>("format").unwrap().as_str() {
+            "json-array" => InputFormat::JsonArray,
+            "framed" => InputFormat::Framed,
+            "json-seq" => InputFormat::JsonSeq,
+            #[cfg(feature = "parquet")]
+            "parquet" => InputFormat::Parquet,
+            _ => InputFormat::Jsonl,
+        },
+        encoding: match matches.get_one::<String>("encoding").unwrap().as_str() {
+            "hex" => Encoding::Hex,
+            "ascii85" | "base85" => Encoding::Ascii85,
+            "json-bytes" => Encoding::JsonBytes,
+            _ => Encoding::Base64,
+        },
+        allow_comments: matches.get_flag("allow-comments"),
+        line_prefix: matches.get_one::<String>("line-prefix").cloned(),
+        json_pointer: matches.get_one::<String>("json-pointer").cloned(),
+        unwrap_json: matches.get_one::<String>("unwrap-json").cloned(),
+        lenient_json: matches.get_flag("lenient-json"),
+        strict_lines: matches.get_flag("json-lines-strict"),
+        limit_bytes: matches.get_one::<u64>("limit-bytes").copied(),
+        min_chunk_size: usize::try_from(*matches.get_one::<u64>("min-chunk-size").unwrap()).unwrap_or(usize::MAX),
+        max_line_bytes: Some(*matches.get_one::<u64>("max-line-bytes").unwrap())
+            .filter(|&max| max > 0)
+            .map(|max| usize::try_from(max).unwrap_or(usize::MAX)),
+        force_format,
+        #[cfg(feature = "parquet")]
+        parquet_column: matches.get_one::<String>("parquet-column").cloned(),
+        throughput: None,
+        pool: None,
+        echo: None,
+    };
+    if let Some(addr) = matches.get_one::<String>("serve") {
+        // Each request gets its own run of the consumer on these outputs.
+        return serve::run(addr.as_str(), consumer, &mut outputs, &input_options)
+            .with_context(|| format!("Failed to serve on {}", addr));
+    }
 
-        if line.trim().is_empty() {
-            continue;
+    // Only --passthrough is done with the payloads once it has seen
+    // them; decoders and writers keep the buffers they're given.
+    let (pool, consumer_thread) = match passthrough_out {
+        Some(out) => {
+            let (pool, recycler) = pool::pool();
+            let thread = thread::spawn(move || {
+                passthrough::forward(rx, out, Some(&recycler));
+                DecodeStats::default()
+            });
+            (Some(Arc::new(pool)), thread)
         }
+        None => (None, thread::spawn(move || consumer.run(rx, &mut outputs))),
+    };
+    input_options.pool = pool;
 
-        match serde_json::from_str::<JsonData>(&line) {
-            Ok(json_data) => {
-                valid_json_count += 1;
-                match general_purpose::STANDARD.decode(&json_data.data) {
-                    Ok(decoded_data) => {
-                        successful_decode_count += 1;
-                        if tx.send(decoded_data).is_err() {
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to decode base64 data on line {}: {}", line_count, e);
-                    }
+    let echo_thread = matches.get_flag("jsonl-output").then(|| {
+        let (echo, lines) = mpsc::channel::<String>();
+        input_options.echo = Some(echo);
+        thread::spawn(move || {
+            let mut out = io::stdout().lock();
+            for line in lines {
+                if writeln!(out, "{}", line).is_err() {
+                    break;
                 }
             }
-            Err(e) => {
-                eprintln!("Failed to parse JSON on line {}: {}", line_count, e);
+        })
+    });
+    let stats_interval = *matches.get_one::<Duration>("stats-interval").unwrap();
+    if !stats_interval.is_zero() {
+        let throughput = Arc::new(Mutex::new(Throughput::new()));
+        throughput::spawn_logger(Arc::clone(&throughput), stats_interval);
+        input_options.throughput = Some(throughput);
+    }
+    if let Some((file, right_tx)) = right_input {
+        let options = InputOptions {
+            throughput: None,
+            echo: None,
+            ..input_options.clone()
+        };
+        thread::spawn(move || input::read_input(BufReader::new(file), &right_tx, &options));
+    }
+    let stats = match (object_input, matches.get_one::<Duration>("input-timeout")) {
+        (Some(object), _) => input::read_input(BufReader::new(object), &tx, &input_options),
+        (None, Some(timeout)) => {
+            if stdin_is_local() {
+                warn!("--input-timeout only helps with piped streams; stdin is a file or terminal");
             }
+            let reader = BufReader::new(IdleTimeout::spawn(io::stdin(), *timeout));
+            input::read_input(reader, &tx, &input_options)
+        }
+        (None, None) if matches.get_flag("watch") => {
+            info!("Watching stdin for lines appended to it; Ctrl-C stops");
+            let reader = BufReader::new(Follow::new(io::stdin().lock(), follow::WATCH_POLL_INTERVAL));
+            input::read_input(reader, &tx, &input_options)
         }
+        (None, None) => input::read_input(io::stdin().lock(), &tx, &input_options),
+    };
+    if let Some(thread) = echo_thread {
+        input_options.echo = None;
+        let _ = thread.join();
+    }
+    if stats.byte_limit_reached {
+        info!("Stopped reading after {} decoded bytes (--limit-bytes)", stats.sent_bytes);
+    }
+    if stats.timed_out {
+        warn!("No input for {:?}, stopping", matches.get_one::<Duration>("input-timeout").unwrap());
     }
 
-    if verbose {
-        println!("Input processing complete:");
-        println!("  Total lines: {}", line_count);
-        println!("  Valid JSON lines: {}", valid_json_count);
-        println!("  Successfully decoded chunks: {}", successful_decode_count);
+    info!("Input processing complete:");
+    if input_options.format == InputFormat::Framed {
+        info!("  Total frames: {}", stats.line_count);
+    } else {
+        info!("  Total lines: {}", stats.line_count);
+        if input_options.allow_comments {
+            info!("  Comment lines: {}", stats.comment_lines);
+        }
+        if input_options.line_prefix.is_some() {
+            info!("  Ignored lines: {}", stats.ignored_lines);
+        }
+        info!("  Valid JSON lines: {}", stats.valid_json_count);
+    }
+    if stats.truncated_tail {
+        info!("  Final line was cut off and ignored");
+    }
+    if stats.missing_payloads > 0 {
+        info!("  Records without a payload field: {}", stats.missing_payloads);
+    }
+    if stats.non_string_payloads > 0 {
+        info!("  Records whose payload isn't a string: {}", stats.non_string_payloads);
+    }
+    info!("  Successfully decoded chunks: {}", stats.successful_decode_count);
+    if stats.empty_chunks > 0 {
+        info!("  Empty chunks skipped: {}", stats.empty_chunks);
+    }
+    if let Some(pool) = &input_options.pool {
+        let (taken, reused) = pool.counts();
+        info!("  Chunk buffers reused: {} of {}", reused, taken);
+    }
+    if stats.undersized_chunks > 0 {
+        info!("  Undersized chunks skipped: {}", stats.undersized_chunks);
+    }
+    if stats.oversized_lines > 0 {
+        info!("  Lines over --max-line-bytes skipped: {}", stats.oversized_lines);
+    }
+    if let Some(ratio) = stats.expansion_ratio() {
+        info!(
+            "  Input {} bytes: {}, decoded bytes: {}, ratio: {:.3}",
+            input_options.encoding.name(),
+            stats.encoded_bytes,
+            stats.decoded_bytes,
+            ratio
+        );
     }
 
     drop(tx);
 
-    consumer_thread.join().expect("Consumer thread panicked");
+    let decodes = join_consumer(consumer_thread)?;
+    if matches.get_flag("summary-only") {
+        #[cfg(feature = "object-store")]
+        let input = matches.get_one::<ObjectUrl>("url").map_or("-".to_string(), ToString::to_string);
+        #[cfg(not(feature = "object-store"))]
+        let input = "-".to_string();
+        println!("{}", summary_line(&input, &stats, &decodes));
+    }
+
+    if input_options.strict_lines && stats.parse_errors > 0 {
+        let e = anyhow!("{} lines or records failed to parse (--json-lines-strict)", stats.parse_errors);
+        return Err(e.context(Exit::Input));
+    }
+    if let Some(timeout) = matches.get_one::<Duration>("input-timeout").filter(|_| stats.timed_out) {
+        return Err(anyhow!("no input for {:?} (--input-timeout)", timeout).context(Exit::Network));
+    }
+    if let Some(&max_rate) = matches.get_one::<f64>("max-decode-error-rate") {
+        check_decode_error_rate(&decodes, max_rate)?;
+    }
+    check_wav_validation(&decodes)?;
+    check_silence(&decodes, matches.get_flag("strict"))?;
+
+    Ok(())
+}
+
+/// Fails when `--wav-validate --strict` stopped the stream at a malformed
+/// chunk; its problems have been logged already.
+fn check_wav_validation(decodes: &DecodeStats) -> Result<()> {
+    match decodes.invalid_wav {
+        Some(index) => {
+            let e = anyhow!("audio chunk {} fails WAV validation (--wav-validate --strict)", index);
+            Err(e.context(Exit::Input))
+        }
+        None => Ok(()),
+    }
+}
+
+/// `--format parquet` is accepted by every build, so that one without the
+/// reader says what's missing instead of listing the other formats.
+fn check_format(matches: &clap::ArgMatches) -> Result<()> {
+    if !cfg!(feature = "parquet") && matches.get_one::<String>("format").is_some_and(|format| format == "parquet") {
+        return Err(anyhow!("--format parquet needs a build with the parquet feature (cargo build --features parquet)"));
+    }
+    Ok(())
+}
+
+/// Warns when every decoded chunk was silent, which usually means the
+/// producer failed upstream; with `--strict` that's an error.
+fn check_silence(decodes: &DecodeStats, strict: bool) -> Result<()> {
+    if !decodes.all_silent() {
+        return Ok(());
+    }
+    let message = format!(
+        "the whole stream is silent: none of the {} decoded chunks peaks above {} dBFS",
+        decodes.attempted - decodes.failed,
+        (20.0 * consumer::SILENCE_PEAK.log10()).round()
+    );
+    if strict {
+        return Err(anyhow!("{} (--strict)", message).context(Exit::Input));
+    }
+    warn!("Silent stream, check the producer: {}", message);
+    Ok(())
+}
+
+/// The `--summary-only` line. Errors are records that failed to parse or
+/// decode plus audio chunks that failed to decode.
+fn summary_line(input: &str, stats: &InputStats, decodes: &DecodeStats) -> String {
+    format!(
+        "file={} lines={} valid={} decoded={} errors={}",
+        input,
+        stats.line_count,
+        stats.valid_json_count,
+        stats.successful_decode_count,
+        stats.parse_errors + decodes.failed
+    )
+}
+
+/// Decodes a `--prepend-file` or `--append-file`, whose format is detected
+/// from its contents. WAV sizes are patched first, so files left by
+/// streaming writers with placeholder sizes decode too.
+fn read_audio_file(path: &str) -> Result<Pcm> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    Pcm::decode_file(data).map_err(|e| anyhow!("Failed to decode {}: {}", path, e))
+}
 
+/// Fails when more than `max_rate` percent of the audio decodes failed.
+fn check_decode_error_rate(decodes: &DecodeStats, max_rate: f64) -> Result<()> {
+    let rate = decodes.error_rate();
+    if rate > max_rate {
+        return Err(anyhow!(
+            "{} of {} audio chunks failed to decode ({:.1}%), more than --max-decode-error-rate {}%",
+            decodes.failed,
+            decodes.attempted,
+            rate,
+            max_rate
+        ));
+    }
     Ok(())
 }
+
+/// The command-line interface. `--config` values are fed back through it as
+/// extra arguments.
+fn cli() -> Command {
+    let command = Command::new("jsonl_player")
+        .version("1.0")
+        .author("Your Name")
+        .about("Plays audio chunks from JSONL stream")
+        .after_help(exit::TABLE)
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Read default settings from a JSON file keyed by long option names; command-line options take precedence")
+        )
+        .arg(
+            Arg::new("playback")
+                .long("playback")
+                .value_name("FORMAT")
+                .help("Audio format for playback, unless a record's content_type says otherwise")
+                .value_parser(Format::NAMES)
+                .default_value("mp3")
+        )
+        .arg(
+            Arg::new("force-format")
+                .long("force-format")
+                .value_name("FORMAT")
+                .help("Decode every chunk in this format, ignoring records' content_type and --playback")
+                .value_parser(Format::NAMES)
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .help("Enable verbose output")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("wav-validate")
+                .long("wav-validate")
+                .help("Refuse to play WAV chunks that break the spec (RIFF size, fmt chunk size and position, block alignment), logging each problem; with --strict, stop and exit with code 2 at the first such chunk")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("trace-reconstruction")
+                .long("trace-reconstruction")
+                .help("Log the first 64 bytes of every WAV chunk handed to the decoder as hex, at trace level; implies --verbose")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("timestamps")
+                .long("timestamps")
+                .value_name("TIMER")
+                .help("Timestamp format for log lines")
+                .value_parser(["none", "rfc3339", "uptime"])
+                .default_value("rfc3339")
+        )
+        .arg(
+            Arg::new("limit-duration")
+                .long("limit-duration")
+                .value_name("SECONDS")
+                .help("Stop after playing this many seconds of audio")
+                .value_parser(parse_seconds)
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("INPUT")
+                .help("Input framing; json-array reads the whole input before playing, so it can't be used with unbounded streams; framed reads raw audio frames with a 4-byte little-endian length prefix; json-seq reads RFC 7464 record-separator-framed JSON; parquet reads each row of a binary column (--parquet-column) of a Parquet file as one chunk, and needs a build with the parquet feature")
+                .value_parser(["jsonl", "json-array", "framed", "json-seq", "parquet"])
+                .default_value("jsonl")
+        )
+        .arg(
+            Arg::new("encoding")
+                .long("encoding")
+                .value_name("ENCODING")
+                .help("Encoding of the data field; json-bytes reads it as a JSON array of byte values, such as [82,73,70,70]")
+                .value_parser(["base64", "hex", "ascii85", "base85", "json-bytes"])
+                .default_value("base64")
+        )
+        .arg(
+            Arg::new("json-pointer")
+                .long("json-pointer")
+                .value_name("POINTER")
+                .help("RFC 6901 pointer to the payload string in each record, e.g. /result/audio/data [default: /data]")
+                .value_parser(input::parse_json_pointer)
+        )
+        .arg(
+            Arg::new("unwrap-json")
+                .long("unwrap-json")
+                .value_name("FIELD")
+                .help("Parse the string in this top-level field as the record, for double-encoded input; --json-pointer then applies to the inner record")
+        )
+        .arg(
+            Arg::new("input-timeout")
+                .long("input-timeout")
+                .value_name("SECONDS")
+                .help("Stop reading when no input arrives for this long, e.g. from a stalled network stream")
+                .value_parser(parse_seconds)
+        )
+        .arg(
+            Arg::new("stats-interval")
+                .long("stats-interval")
+                .value_name("SECONDS")
+                .help("Log the average decoded bytes/s and chunks/s over the last 5 seconds at this interval (shown with --verbose); 0 disables")
+                .value_parser(parse_interval)
+                .default_value("0")
+        )
+        .arg(
+            Arg::new("lenient-json")
+                .long("lenient-json")
+                .help("Accept JSONL lines with trailing commas before '}' or ']'")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("json-lines-strict")
+                .long("json-lines-strict")
+                .help("Reject lines that hold anything other than exactly one JSON object")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("max-decode-error-rate")
+                .long("max-decode-error-rate")
+                .value_name("PCT")
+                .help("Exit with an error if more than this percentage of audio chunks failed to decode over the whole run; unlike --json-lines-strict, occasional failures are tolerated")
+                .value_parser(parse_percent)
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("limit-bytes")
+                .long("limit-bytes")
+                .value_name("SIZE")
+                .help("Stop reading once this many decoded bytes have been sent; accepts K, M and G suffixes (powers of 1024)")
+                .value_parser(parse_size)
+        )
+        .arg(
+            Arg::new("min-chunk-size")
+                .long("min-chunk-size")
+                .value_name("BYTES")
+                .help("Drop decoded chunks shorter than this, except WAV headers; accepts K, M and G suffixes")
+                .value_parser(parse_size)
+                .default_value("0")
+        )
+        .arg(
+            Arg::new("max-line-bytes")
+                .long("max-line-bytes")
+                .value_name("SIZE")
+                .help("Skip JSONL lines and json-seq records longer than this, with a warning, instead of buffering them whole; accepts K, M and G suffixes; 0 disables")
+                .value_parser(parse_size)
+                .default_value("256M")
+        )
+        .arg(
+            Arg::new("allow-comments")
+                .long("allow-comments")
+                .help("Skip input lines starting with '#'")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("mp3-stream")
+                .long("mp3-stream")
+                .help("Decode all mp3 chunks with one decoder, as a single gapless stream; per-chunk gain and error handling don't apply")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "manifest", "frame-log", "on-chunk-cmd", "capture-ring"])
+        )
+        .arg(
+            Arg::new("line-prefix")
+                .long("line-prefix")
+                .value_name("STR")
+                .help("Only treat JSONL lines starting with this (after leading whitespace) as records, stripping it before parsing; other lines are ignored")
+        )
+        .arg(
+            Arg::new("right-input")
+                .long("right-input")
+                .value_name("PATH")
+                .help("Read a second stream of mono chunks from this file and play it as the right channel, with stdin's mono chunks on the left; both need the same sample rate")
+                .conflicts_with_all(["out", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("allow-multichannel")
+                .long("allow-multichannel")
+                .help("Play audio with more than two channels as is instead of downmixing 5.1 to stereo")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("replace-on-error")
+                .long("replace-on-error")
+                .help("Play silence in place of chunks that fail to decode, keeping the timeline aligned")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("error-silence-ms")
+                .long("error-silence-ms")
+                .value_name("MS")
+                .help("Silence used by --replace-on-error when the failed chunk's duration is unknown")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("200")
+        )
+        .arg(
+            Arg::new("start-delay")
+                .long("start-delay")
+                .value_name("MS")
+                .help("Wait this long before starting playback, e.g. to line up with a video player; input keeps being read and buffered meanwhile")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("chunk-gap")
+                .long("chunk-gap")
+                .value_name("MS")
+                .help("Play this much silence between consecutive chunks; only useful when each chunk is a separate phrase, it breaks up continuous audio")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .conflicts_with("mp3-stream")
+        )
+        .arg(
+            Arg::new("sink-queue-limit")
+                .long("sink-queue-limit")
+                .value_name("CHUNKS")
+                .help("Stop taking chunks from the input while this many are queued for playback, so a fast input doesn't pile up decoded audio in the output; chunks not yet taken wait in the input channel, which is unbounded, so this caps decoded audio rather than total memory")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("max-queued-duration")
+                .long("max-queued-duration")
+                .value_name("SECONDS")
+                .help("Stop taking chunks from the input while this much audio is queued for playback, bounding the latency of a realtime stream; counts the chunk playing in full, and needs chunks of known length, which rules out --mp3-stream")
+                .value_parser(parse_seconds)
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("reverse")
+                .long("reverse")
+                .help("Buffer the whole input, then play it backwards; for finite inputs only, since nothing plays until input ends")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "out",
+                    "passthrough",
+                    "mp3-stream",
+                    "input-timeout",
+                    "interactive",
+                    "repeat-on-underrun",
+                    "chunk-gap",
+                    "marker-every",
+                    "use-timestamps",
+                ])
+        )
+        .arg(
+            Arg::new("agc")
+                .long("agc")
+                .help("Even out loudness across a live stream with automatic gain control, scaling toward --agc-target as the running level changes; gain is raised at most 20 dB, so pauses stay quiet")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("agc-target")
+                .long("agc-target")
+                .value_name("DBFS")
+                .help("RMS level --agc aims for, in dBFS")
+                .value_parser(clap::value_parser!(f64))
+                .allow_negative_numbers(true)
+                .default_value("-20")
+        )
+        .arg(
+            Arg::new("agc-speed")
+                .long("agc-speed")
+                .value_name("SECONDS")
+                .help("Time constant of --agc's level tracking: lower reacts faster to changes in loudness but pumps more on short peaks")
+                .value_parser(parse_seconds)
+                .default_value("0.5")
+        )
+        .arg(
+            Arg::new("spill-to-disk")
+                .long("spill-to-disk")
+                .help("Buffer --reverse and --loop audio in a temp file rather than in memory, for captures too long to fit in RAM; the file is removed on exit")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("loop")
+                .long("loop")
+                .help("Buffer the whole input, then play it on repeat until interrupted or --limit-duration; a WAV smpl chunk's loop points make only that region repeat, after playing the audio before it once")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "out",
+                    "passthrough",
+                    "mp3-stream",
+                    "reverse",
+                    "input-timeout",
+                    "interactive",
+                    "repeat-on-underrun",
+                    "chunk-gap",
+                    "marker-every",
+                    "use-timestamps",
+                    "conceal-gaps",
+                    "watch",
+                    "record",
+                ])
+        )
+        .arg(
+            Arg::new("resample-quality")
+                .long("resample-quality")
+                .value_name("QUALITY")
+                .help("How chunks at another rate than the output device are resampled: fast is linear interpolation, medium is cubic, high is a windowed sinc that doesn't alias when downsampling")
+                .value_parser(Quality::NAMES)
+                .default_value("medium")
+        )
+        .arg(
+            Arg::new("prepend-file")
+                .long("prepend-file")
+                .value_name("PATH")
+                .help("Play this audio file before the first chunk, converted to the stream's channels and sample rate")
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("append-file")
+                .long("append-file")
+                .value_name("PATH")
+                .help("Play this audio file after the last chunk, converted to the stream's channels and sample rate")
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("inject-file")
+                .long("inject-file")
+                .value_name("PATH")
+                .help("Watch this path for complete audio files, such as a notification sound, and play each new one between streamed chunks, converted to the stream's layout; what's there at start-up isn't played")
+                .conflicts_with_all(["out", "passthrough", "mp3-stream", "reverse", "loop", "serve"])
+        )
+        .arg(
+            Arg::new("reconstruct")
+                .long("reconstruct")
+                .help("Treat the WAV stream as one header followed by bare PCM chunks, putting the first chunk's header in front of each; by default this is detected from whether the second chunk starts with its own RIFF header")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("no-reconstruct")
+                .long("no-reconstruct")
+                .help("Decode every WAV chunk as the complete file it is, like mp3 chunks, skipping header reuse and framing detection; use it when each record holds a whole WAV file and a chunk without a proper RIFF header should fail rather than borrow the first chunk's")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("reconstruct")
+        )
+        .arg(
+            Arg::new("align-frames")
+                .long("align-frames")
+                .help("Carry the trailing partial frame of each WAV chunk over to the next, for producers that split chunks mid-frame; frame size is the header's block_align")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("header-cache")
+                .long("header-cache")
+                .value_name("PATH")
+                .help("Save each WAV header the stream sends to this file, so a restarted player can --resume reconstructing bare chunks")
+                .conflicts_with("no-reconstruct")
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Start from the WAV header saved in --header-cache, for a stream that continues with bare PCM chunks")
+                .action(clap::ArgAction::SetTrue)
+                .requires("header-cache")
+        )
+        .arg(
+            Arg::new("conceal-gaps")
+                .long("conceal-gaps")
+                .help("When a jump in the records' seq fields shows chunks were lost, queue silence as long as the missing chunks are estimated to be (the average chunk so far), keeping the timeline aligned")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "passthrough", "mp3-stream", "reverse"])
+        )
+        .arg(
+            Arg::new("use-timestamps")
+                .long("use-timestamps")
+                .help("Space chunks out by the deltas between their records' ts fields, in ms, to replay a capture in real time; chunks with a missing or backwards ts play immediately")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("marker-every")
+                .long("marker-every")
+                .value_name("N")
+                .help("Beep before every Nth chunk, to measure latency from a recording of the output")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .conflicts_with_all(["out", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("marker-freq")
+                .long("marker-freq")
+                .value_name("HZ")
+                .help("Pitch of the --marker-every beep")
+                .value_parser(clap::value_parser!(f32))
+                .default_value("1000")
+        )
+        .arg(
+            Arg::new("marker-ms")
+                .long("marker-ms")
+                .value_name("MS")
+                .help("Length of the --marker-every beep")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("20")
+        )
+        .arg(
+            Arg::new("warmup-ms")
+                .long("warmup-ms")
+                .value_name("MS")
+                .help("Play this much silence before the first chunk so a slow-starting device doesn't clip it [default: 150 for mp3 and ogg, 50 for flac, 0 for wav]")
+                .value_parser(clap::value_parser!(u64))
+        )
+        .arg(
+            Arg::new("capture-ring")
+                .long("capture-ring")
+                .value_name("N")
+                .help("Keep the last N raw chunks and dump them to crash_dump/ when a chunk fails to decode")
+                .value_parser(clap::value_parser!(usize))
+        )
+        .arg(
+            Arg::new("exit-on-empty")
+                .long("exit-on-empty")
+                .help("Once input ends, exit as soon as the queued audio has drained instead of blocking until the sink reports the end; audio already queued, such as --warmup-ms silence, still plays")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("shutdown-timeout")
+                .long("shutdown-timeout")
+                .value_name("SECONDS")
+                .help("Once input ends, wait at most this long for queued audio to finish before exiting, in case the device is stuck [default: no limit]")
+                .value_parser(parse_seconds)
+        )
+        .arg(
+            Arg::new("invert-channel")
+                .long("invert-channel")
+                .value_name("CHANNEL")
+                .help("Flip the polarity of a channel to check channel wiring; WAV playback only")
+                .value_parser(InvertChannel::NAMES)
+        )
+        .arg(
+            Arg::new("remap")
+                .long("remap")
+                .value_name("ORDER")
+                .help("Output channel order as comma-separated input channel indices, e.g. 1,0 swaps left and right; WAV playback only")
+                .value_parser(|s: &str| s.parse::<ChannelRemap>())
+        )
+        .arg(
+            Arg::new("out")
+                .long("out")
+                .value_name("PATH")
+                .help("Write the audio to a file instead of playing it; with --passthrough, - means stdout. A WAV file gets the RIFF and data sizes of the audio written, with a warning where the input claimed others")
+        )
+        .arg(
+            Arg::new("out-segment")
+                .long("out-segment")
+                .value_name("SECONDS")
+                .help("Split the --out WAV into files of this length, named out_000.wav, out_001.wav, ... after the --out path")
+                .value_parser(parse_seconds)
+                .requires("out")
+                .conflicts_with("passthrough")
+        )
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .help("Container for --out [default: mp3 for mp3 input, wav otherwise]")
+                .value_parser(OutputFormat::NAMES)
+                .requires("out")
+        )
+        .arg(
+            Arg::new("chunk-bytes")
+                .long("chunk-bytes")
+                .value_name("BYTES")
+                .help("PCM bytes per record with --output-format jsonl, rounded down to whole frames; accepts K, M and G suffixes [default: 8192]")
+                .value_parser(parse_size)
+                .requires("out")
+        )
+        .arg(
+            Arg::new("out-bits")
+                .long("out-bits")
+                .value_name("BITS")
+                .help("Convert the PCM written by --out to this many bits per sample, rewriting the WAV header to match")
+                .value_parser(bitdepth::BIT_DEPTHS)
+                .requires("out")
+                .conflicts_with("passthrough")
+        )
+        .arg(
+            Arg::new("dither")
+                .long("dither")
+                .help("Add triangular dither when --out-bits narrows the samples")
+                .action(clap::ArgAction::SetTrue)
+                .requires("out-bits")
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .value_name("PATH")
+                .help("Write one JSON line per chunk with its size, detected format, peak level and decode status")
+        )
+        .arg(
+            Arg::new("frame-log")
+                .long("frame-log")
+                .value_name("PATH")
+                .help("Write a CSV row per chunk with its encoded and decoded sizes, detected format, peak and RMS levels, decode time and whether it was played, for a spreadsheet")
+        )
+        .arg(
+            Arg::new("cues")
+                .long("cues")
+                .value_name("PATH")
+                .help("Write a WebVTT file with a cue per decoded chunk, timed by the stream's running duration and captioned with the chunk index, to line a transcript up with chunk positions")
+                .conflicts_with_all(["out", "passthrough", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("on-chunk-cmd")
+                .long("on-chunk-cmd")
+                .value_name("CMD")
+                .help("Run CMD through the shell once and write every decoded chunk to its stdin, in order, before the chunk is played")
+        )
+        .arg(
+            Arg::new("asr-cmd")
+                .long("asr-cmd")
+                .value_name("CMD")
+                .help("Run CMD through the shell once and stream it the PCM of every WAV chunk, headers stripped, printing the lines it writes back on stdout as live captions; the PCM format is logged at startup")
+                .conflicts_with_all(["out", "passthrough", "mp3-stream", "jsonl-output"])
+        )
+        .arg(
+            Arg::new("device")
+                .long("device")
+                .value_name("NAME")
+                .help("Output device to play on; repeat to play on several at once, which keeps an extra decoded copy of each chunk per device [default: system default]")
+                .action(clap::ArgAction::Append)
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .value_name("PATH")
+                .help("Also write the audio as it is played to a 16-bit WAV file")
+                .conflicts_with_all(["out", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .help("Read keys from the terminal while playing: r and Enter replays the last chunk")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("repeat-on-underrun")
+                .long("repeat-on-underrun")
+                .help("When the output runs dry waiting for input, replay the last chunk to mask network jitter")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["out", "mp3-stream"])
+        )
+        .arg(
+            Arg::new("max-conceal")
+                .long("max-conceal")
+                .value_name("N")
+                .help("Replay the last chunk at most this many times in a row with --repeat-on-underrun")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("3")
+        )
+        .arg(
+            Arg::new("passthrough")
+                .long("passthrough")
+                .help("Write each chunk's decoded bytes to --out as they arrive, without decoding or playing them; --out - writes to stdout")
+                .action(clap::ArgAction::SetTrue)
+                .requires("out")
+                .conflicts_with_all(["mp3-stream", "record", "right-input", "interactive", "manifest", "frame-log", "on-chunk-cmd"])
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Exit with code 2, instead of warning, when every decoded chunk is silent (peaks below -60 dBFS) or, with --wav-validate, at the first chunk that fails validation; that is all --strict affects: parse errors are --json-lines-strict's and decode errors --max-decode-error-rate's")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .help("Keep reading a file redirected to stdin as it grows, like tail -f, to play a capture that's still being written; runs until interrupted")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["input-timeout", "serve", "reverse"])
+        )
+        .arg(
+            Arg::new("summary-only")
+                .long("summary-only")
+                .help("Log errors only and print one summary line to stdout when done (file=... lines=N valid=N decoded=N errors=N), for validating files in shell loops; audio still plays unless --dry-run")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["verbose", "trace-reconstruction", "jsonl-output", "serve"])
+        )
+        .arg(
+            Arg::new("serve")
+                .long("serve")
+                .value_name("ADDR")
+                .help("Instead of reading stdin, listen for HTTP on ADDR (e.g. 127.0.0.1:8080) and play the JSONL body of each POST, replying with the run's stats as JSON once it has played; one POST plays at a time, others get a 409")
+                .conflicts_with_all([
+                    "out",
+                    "passthrough",
+                    "right-input",
+                    "interactive",
+                    "manifest",
+                    "frame-log",
+                    "cues",
+                    "on-chunk-cmd",
+                    "asr-cmd",
+                    "jsonl-output",
+                    "input-timeout",
+                    "capture-ring",
+                    "record",
+                    "stats-interval",
+                    "max-decode-error-rate",
+                ])
+        )
+        .arg(
+            Arg::new("jsonl-output")
+                .long("jsonl-output")
+                .help("Print a line of JSON to stdout for each decoded record: where it was, its payload field, decoded length and detected format")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("list-formats")
+                .long("list-formats")
+                .help("Print each playback format, whether its decoder is compiled into this build and the magic bytes it's detected by, then exit")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("print-device-config")
+                .long("print-device-config")
+                .help("Print the sample rate, channels and sample format the output device (or each --device) is opened with, then exit")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Parse and decode the stream without opening an audio device")
+                .action(clap::ArgAction::SetTrue)
+        );
+    #[cfg(feature = "object-store")]
+    let command = command.arg(
+        Arg::new("url")
+            .long("url")
+            .value_name("URL")
+            .help("Read the input from an s3:// or gs:// object instead of stdin, streamed with `aws s3 cp` or `gcloud storage cat`")
+            .value_parser(|url: &str| url.parse::<ObjectUrl>())
+            .conflicts_with_all(["input-timeout", "watch", "serve"]),
+    );
+    #[cfg(feature = "parquet")]
+    let command = command.arg(
+        Arg::new("parquet-column")
+            .long("parquet-column")
+            .value_name("COLUMN")
+            .help(format!("Binary column --format parquet reads the chunks from [default: {}]", parquet::DEFAULT_COLUMN)),
+    );
+    command
+}
+
+/// Logs go to stderr so they never mix with audio written to stdout.
+/// Verbose mode enables the informational and per-chunk lines.
+fn init_logging(level: Level, timestamps: &str) {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_writer(io::stderr);
+    match timestamps {
+        "none" => builder.without_time().init(),
+        "uptime" => builder.with_timer(Uptime::default()).init(),
+        _ => builder.with_timer(SystemTime).init(),
+    }
+}
+
+const NO_DEVICE_HINT: &str =
+    "No usable audio output device; use --dry-run to process the stream without playback";
+
+/// Opens the named output device, or the default one. Failures exit with
+/// [`Exit::Device`].
+fn open_output(device: Option<&str>) -> Result<(OutputStream, Sink)> {
+    open_device(device).context(Exit::Device)
+}
+
+fn open_device(name: Option<&str>) -> Result<(OutputStream, Sink)> {
+    let device = find_device(name)?;
+    match describe_device(&device) {
+        Ok(config) => info!("Output device {}", config),
+        Err(e) => warn!("{:#}", e),
+    }
+    let (stream, stream_handle) = match name {
+        // rodio falls back to other devices if the default one fails.
+        None => OutputStream::try_default().context(NO_DEVICE_HINT)?,
+        Some(name) => OutputStream::try_from_device(&device)
+            .with_context(|| format!("Failed to open output device `{}`", name))?,
+    };
+    let sink = Sink::try_new(&stream_handle).context(NO_DEVICE_HINT)?;
+    Ok((stream, sink))
+}
+
+fn find_device(name: Option<&str>) -> Result<rodio::cpal::Device> {
+    let host = rodio::cpal::default_host();
+    match name {
+        None => host.default_output_device().context(NO_DEVICE_HINT),
+        Some(name) => host
+            .output_devices()
+            .context(NO_DEVICE_HINT)?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| anyhow!("No output device named `{}`", name)),
+    }
+}
+
+/// The sample rate rodio opens the device with, if it can be queried.
+fn device_rate(name: Option<&str>) -> Option<u32> {
+    let config = find_device(name).ok()?.default_output_config().ok()?;
+    Some(config.sample_rate().0)
+}
+
+/// The device's name and the stream config rodio opens it with, which is
+/// the device's default; rodio converts samples to its sample format.
+fn describe_device(device: &rodio::cpal::Device) -> Result<String> {
+    let name = device.name().unwrap_or_else(|_| "(unnamed)".to_string());
+    let config = device
+        .default_output_config()
+        .with_context(|| format!("Failed to query the config of output device `{}`", name))?;
+    Ok(format!(
+        "`{}`: {} Hz, {} channels, {} samples",
+        name,
+        config.sample_rate().0,
+        config.channels(),
+        config.sample_format()
+    ))
+}
+
+/// Waits for the consumer and turns a panic into an error instead of
+/// re-panicking the main thread.
+fn join_consumer(handle: JoinHandle<DecodeStats>) -> Result<DecodeStats> {
+    handle
+        .join()
+        .map_err(|payload| anyhow!("Audio consumer thread panicked: {}", panic_message(payload.as_ref())))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+/// Whether stdin is redirected from a regular file, which `--watch` can
+/// keep reading as it grows.
+fn stdin_is_file() -> bool {
+    #[cfg(unix)]
+    if let Ok(metadata) = std::fs::metadata("/dev/stdin") {
+        return metadata.is_file();
+    }
+    false
+}
+
+/// Whether stdin is a terminal or a regular file, where an idle timeout
+/// makes no sense.
+fn stdin_is_local() -> bool {
+    io::stdin().is_terminal() || stdin_is_file()
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a percentage", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err("must be a percentage from 0 to 100".to_string());
+    }
+    Ok(percent)
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a number of seconds", value))?;
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err("must be a positive number of seconds".to_string());
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Like [`parse_seconds`], but `0` is allowed and means "off".
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(0.0) => Ok(Duration::ZERO),
+        _ => parse_seconds(value),
+    }
+}
+
+/// Parses a byte count such as `4096`, `64K` or `10M`.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let digits = trimmed.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier: u64 = match trimmed[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        suffix => return Err(format!("unknown size suffix `{}`", suffix)),
+    };
+    let count: u64 = digits
+        .parse()
+        .map_err(|_| format!("`{}` is not a byte count", value))?;
+    count
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("`{}` is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_line_is_one_line() {
+        let stats = InputStats {
+            line_count: 12,
+            valid_json_count: 10,
+            successful_decode_count: 9,
+            parse_errors: 2,
+            ..InputStats::default()
+        };
+        let decodes = DecodeStats { attempted: 9, failed: 1, audible: 8, invalid_wav: None };
+        assert_eq!(
+            summary_line("-", &stats, &decodes),
+            "file=- lines=12 valid=10 decoded=9 errors=3"
+        );
+    }
+
+    #[test]
+    fn strict_wav_validation_exits_with_input_error() {
+        assert!(check_wav_validation(&DecodeStats::default()).is_ok());
+        let decodes = DecodeStats { invalid_wav: Some(3), ..DecodeStats::default() };
+        let err = check_wav_validation(&decodes).unwrap_err();
+        assert_eq!(Exit::of(&err).code(), 2);
+        assert_eq!(err.root_cause().to_string(), "audio chunk 3 fails WAV validation (--wav-validate --strict)");
+    }
+
+    #[test]
+    fn parquet_format_needs_the_feature() {
+        let matches = cli().try_get_matches_from(["jsonl_player", "--format", "parquet"]).unwrap();
+        if cfg!(feature = "parquet") {
+            assert!(check_format(&matches).is_ok());
+        } else {
+            assert_eq!(
+                check_format(&matches).unwrap_err().to_string(),
+                "--format parquet needs a build with the parquet feature (cargo build --features parquet)"
+            );
+        }
+        let matches = cli().try_get_matches_from(["jsonl_player", "--format", "framed"]).unwrap();
+        assert!(check_format(&matches).is_ok());
+    }
+
+    #[test]
+    fn silent_streams_fail_under_strict() {
+        let silent = DecodeStats { attempted: 4, failed: 1, audible: 0, invalid_wav: None };
+        assert!(check_silence(&silent, false).is_ok());
+        let err = check_silence(&silent, true).unwrap_err();
+        assert_eq!(Exit::of(&err).code(), 2);
+        assert_eq!(
+            err.root_cause().to_string(),
+            "the whole stream is silent: none of the 3 decoded chunks peaks above -60 dBFS (--strict)"
+        );
+        let audible = DecodeStats { audible: 1, ..silent };
+        assert!(check_silence(&audible, true).is_ok());
+        assert!(check_silence(&DecodeStats::default(), true).is_ok());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("10M"), Ok(10 << 20));
+        assert_eq!(parse_size("1gib"), Ok(1 << 30));
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn decode_error_rate_is_checked_over_the_run() {
+        let few = DecodeStats { attempted: 200, failed: 3, audible: 197, invalid_wav: None };
+        assert!(check_decode_error_rate(&few, 2.0).is_ok());
+        let many = DecodeStats { attempted: 200, failed: 10, audible: 190, invalid_wav: None };
+        assert_eq!(
+            check_decode_error_rate(&many, 2.0).unwrap_err().to_string(),
+            "10 of 200 audio chunks failed to decode (5.0%), more than --max-decode-error-rate 2%"
+        );
+        assert!(check_decode_error_rate(&DecodeStats::default(), 0.0).is_ok());
+        assert!(parse_percent("101").is_err());
+    }
+
+    #[test]
+    fn missing_device_exits_with_device_code() {
+        let err = open_output(Some("no such device")).err().unwrap();
+        assert_eq!(Exit::of(&err), Exit::Device);
+        assert_eq!(Exit::of(&err).code(), 3);
+    }
+
+    #[test]
+    fn consumer_panic_becomes_error() {
+        let handle = thread::spawn(|| panic!("decoder exploded on chunk {}", 3));
+        let err = join_consumer(handle).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Audio consumer thread panicked: decoder exploded on chunk 3"
+        );
+    }
+
+    #[test]
+    fn reader_stops_once_consumer_is_gone() {
+        let (tx, rx) = mpsc::channel();
+        let consumer = thread::spawn(move || {
+            drop(rx);
+            panic!("boom");
+        });
+        assert!(join_consumer(consumer).is_err());
+
+        let input = "{\"data\":\"AQ==\"}\n".repeat(10);
+        let stats = input::read_input(io::Cursor::new(input), &tx, &InputOptions::default());
+        assert_eq!(stats.successful_decode_count, 1);
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// One `--manifest` line describing a processed chunk.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestEntry {
+    pub index: usize,
+    pub bytes: usize,
+    /// Format sniffed from the chunk's leading bytes.
+    pub format: Option<&'static str>,
+    /// Peak level as a fraction of full scale, when the chunk was decoded.
+    pub peak: Option<f32>,
+    pub decoded: bool,
+    /// The rest is only written to the `--frame-log`.
+    #[serde(skip)]
+    pub encoded_bytes: usize,
+    #[serde(skip)]
+    pub rms: Option<f32>,
+    /// Time spent decoding the chunk into samples.
+    #[serde(skip)]
+    pub decode_latency: Option<Duration>,
+    /// Whether the chunk was queued for playback, or written with `--out`.
+    #[serde(skip)]
+    pub played: bool,
+}
+
+/// Writes manifest lines, flushing each one so an interrupted run still
+/// leaves a valid JSONL file.
+pub struct Manifest<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Manifest<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn record(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, entry)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_line_per_chunk() {
+        let mut manifest = Manifest::new(Vec::new());
+        manifest
+            .record(&ManifestEntry {
+                index: 1,
+                bytes: 8192,
+                format: Some("wav"),
+                peak: Some(0.5),
+                decoded: true,
+                encoded_bytes: 10924,
+                rms: Some(0.25),
+                decode_latency: None,
+                played: true,
+            })
+            .unwrap();
+        manifest
+            .record(&ManifestEntry {
+                index: 2,
+                bytes: 3,
+                format: None,
+                peak: None,
+                decoded: false,
+                encoded_bytes: 4,
+                rms: None,
+                decode_latency: None,
+                played: false,
+            })
+            .unwrap();
+
+        let text = String::from_utf8(manifest.out).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                serde_json::json!({"index": 1, "bytes": 8192, "format": "wav", "peak": 0.5, "decoded": true}),
+                serde_json::json!({"index": 2, "bytes": 3, "format": null, "peak": null, "decoded": false}),
+            ]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use rodio::source::SineWave;
+use rodio::Source;
+use std::time::Duration;
+
+use crate::BoxedSource;
+
+/// `--marker-every`: a short beep queued ahead of every `every`th chunk,
+/// for finding chunk starts in a recording of the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+    pub every: usize,
+    pub frequency: f32,
+    pub duration: Duration,
+}
+
+impl Marker {
+    /// Whether the chunk numbered `index`, counting from 1, gets a marker.
+    pub fn due(&self, index: usize) -> bool {
+        self.every > 0 && index.is_multiple_of(self.every)
+    }
+
+    pub fn source(&self) -> BoxedSource {
+        Box::new(
+            SineWave::new(self.frequency)
+                .take_duration(self.duration)
+                .amplify(0.5)
+                .convert_samples(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_every_nth_chunk() {
+        let marker = Marker {
+            every: 3,
+            frequency: 1000.0,
+            duration: Duration::from_millis(10),
+        };
+        let due: Vec<usize> = (1..=7).filter(|&index| marker.due(index)).collect();
+        assert_eq!(due, vec![3, 6]);
+
+        let samples: Vec<i16> = marker.source().collect();
+        assert_eq!(samples.len(), 480);
+        assert!(samples.iter().any(|&sample| sample > 8000));
+    }
+}
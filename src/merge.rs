@@ -0,0 +1,86 @@
+use tracing::warn;
+
+use crate::pcm::Pcm;
+
+/// Interleaves a mono chunk from the primary input (left) with the matching
+/// mono chunk from `--right-input` into one stereo chunk. The shorter side
+/// is padded with silence; a missing right chunk, once that input has
+/// ended, is all silence.
+pub fn stereo(index: usize, left: Pcm, right: Option<Pcm>) -> Result<Pcm, String> {
+    let ended = right.is_none();
+    let right = match right {
+        Some(right) => right,
+        None => Pcm {
+            channels: 1,
+            sample_rate: left.sample_rate,
+            samples: Vec::new(),
+        },
+    };
+    if left.channels != 1 || right.channels != 1 {
+        return Err(format!(
+            "--right-input needs mono on both sides, got {} left and {} right channels",
+            left.channels, right.channels
+        ));
+    }
+    if left.sample_rate != right.sample_rate {
+        return Err(format!(
+            "left input is {} Hz but right input is {} Hz",
+            left.sample_rate, right.sample_rate
+        ));
+    }
+    if !ended && left.samples.len() != right.samples.len() {
+        warn!(
+            "Chunk {} drifts: left has {} frames, right {}; padding the shorter side with silence",
+            index,
+            left.samples.len(),
+            right.samples.len()
+        );
+    }
+
+    let frames = left.samples.len().max(right.samples.len());
+    let side = |samples: &[i16], frame: usize| samples.get(frame).copied().unwrap_or(0);
+    Ok(Pcm {
+        channels: 2,
+        sample_rate: left.sample_rate,
+        samples: (0..frames)
+            .flat_map(|frame| [side(&left.samples, frame), side(&right.samples, frame)])
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mono(sample_rate: u32, samples: &[i16]) -> Pcm {
+        Pcm {
+            channels: 1,
+            sample_rate,
+            samples: samples.to_vec(),
+        }
+    }
+
+    #[test]
+    fn interleaves_left_and_right() {
+        let merged = stereo(1, mono(8000, &[1, 2, 3]), Some(mono(8000, &[-1, -2, -3]))).unwrap();
+        assert_eq!(merged.channels, 2);
+        assert_eq!(merged.samples, vec![1, -1, 2, -2, 3, -3]);
+
+        let padded = stereo(2, mono(8000, &[1, 2]), Some(mono(8000, &[-1]))).unwrap();
+        assert_eq!(padded.samples, vec![1, -1, 2, 0]);
+
+        let ended = stereo(3, mono(8000, &[1]), None).unwrap();
+        assert_eq!(ended.samples, vec![1, 0]);
+    }
+
+    #[test]
+    fn rejects_mismatched_formats() {
+        assert!(stereo(1, mono(8000, &[1]), Some(mono(16000, &[1]))).is_err());
+        let two = Pcm {
+            channels: 2,
+            sample_rate: 8000,
+            samples: vec![1, 2],
+        };
+        assert!(stereo(1, two, Some(mono(8000, &[1]))).is_err());
+    }
+}
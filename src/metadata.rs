@@ -0,0 +1,223 @@
+use tracing::info;
+
+use crate::format::Format;
+use crate::wav::read_u32_le;
+
+/// Stream details and Vorbis comments found in the headers at the start of
+/// an ogg or flac stream.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub channels: Option<u16>,
+    pub sample_rate: Option<u32>,
+    pub vendor: Option<String>,
+    /// `KEY=value` comments in stream order, keys as written.
+    pub comments: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// Reads the headers at the start of the first chunk of an ogg (Vorbis)
+    /// or flac stream. `None` for other formats or when there are none.
+    pub fn read(format: Format, data: &[u8]) -> Option<Metadata> {
+        let metadata = match format {
+            Format::Ogg => read_vorbis(data),
+            Format::Flac => read_flac(data),
+            _ => return None,
+        };
+        Some(metadata).filter(|metadata| *metadata != Metadata::default())
+    }
+
+    pub fn log(&self) {
+        info!("Stream metadata:");
+        if let (Some(channels), Some(sample_rate)) = (self.channels, self.sample_rate) {
+            info!("  {} channels, {} Hz", channels, sample_rate);
+        }
+        if let Some(vendor) = &self.vendor {
+            info!("  Encoder: {}", vendor);
+        }
+        for (key, value) in &self.comments {
+            info!("  {}: {}", key, value);
+        }
+    }
+}
+
+fn read_vorbis(data: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    // The identification header is the first packet, the comment header the second.
+    for packet in ogg_packets(data).into_iter().take(2) {
+        match packet.split_at_checked(7) {
+            Some(([1, b'v', b'o', b'r', b'b', b'i', b's'], id)) => {
+                metadata.channels = id.get(4).map(|&channels| u16::from(channels));
+                metadata.sample_rate = read_u32_le(id, 5);
+            }
+            Some(([3, b'v', b'o', b'r', b'b', b'i', b's'], comments)) => read_comments(comments, &mut metadata),
+            _ => break,
+        }
+    }
+    metadata
+}
+
+/// Reassembles the packets of the complete Ogg pages at the start of
+/// `data`. A packet still open at the end is dropped.
+fn ogg_packets(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut pos = 0;
+    while data.get(pos..pos + 4) == Some(b"OggS") {
+        let Some(&segments) = data.get(pos + 26) else {
+            break;
+        };
+        let table = pos + 27;
+        let Some(lacing) = data.get(table..table + segments as usize) else {
+            break;
+        };
+        let mut body = table + segments as usize;
+        for &len in lacing {
+            let Some(segment) = data.get(body..body + len as usize) else {
+                return packets;
+            };
+            packet.extend_from_slice(segment);
+            body += len as usize;
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        pos = body;
+    }
+    packets
+}
+
+fn read_flac(data: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+    if !data.starts_with(b"fLaC") {
+        return metadata;
+    }
+    // Metadata blocks: a last-block flag and 7-bit type, then a 24-bit
+    // big-endian length.
+    let mut pos = 4;
+    while let Some(header) = data.get(pos..pos + 4) {
+        let flags = header[0];
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let Some(block) = data.get(pos + 4..pos + 4 + len) else {
+            break;
+        };
+        match flags & 0x7f {
+            0 if block.len() >= 13 => {
+                // STREAMINFO: 20 bits of sample rate, then 3 bits of channels - 1.
+                metadata.sample_rate = Some(u32::from_be_bytes([0, block[10], block[11], block[12]]) >> 4);
+                metadata.channels = Some(u16::from((block[12] >> 1) & 0x7) + 1);
+            }
+            4 => read_comments(block, &mut metadata),
+            _ => {}
+        }
+        if flags & 0x80 != 0 {
+            break;
+        }
+        pos += 4 + len;
+    }
+    metadata
+}
+
+/// Parses a Vorbis comment block: a vendor string and `KEY=value` strings,
+/// each behind a little-endian length. Stops at the first malformed entry.
+fn read_comments(data: &[u8], metadata: &mut Metadata) {
+    let mut pos = 0;
+    let string = |pos: &mut usize| {
+        let len = read_u32_le(data, *pos)? as usize;
+        let text = data.get(*pos + 4..(*pos + 4).checked_add(len)?)?;
+        *pos += 4 + len;
+        Some(String::from_utf8_lossy(text).into_owned())
+    };
+    metadata.vendor = string(&mut pos);
+    let count = read_u32_le(data, pos).unwrap_or(0);
+    pos += 4;
+    for _ in 0..count {
+        let Some(comment) = string(&mut pos) else {
+            break;
+        };
+        if let Some((key, value)) = comment.split_once('=') {
+            metadata.comments.push((key.to_string(), value.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment_block(vendor: &str, comments: &[&str]) -> Vec<u8> {
+        let string = |block: &mut Vec<u8>, text: &str| {
+            block.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            block.extend_from_slice(text.as_bytes());
+        };
+        let mut block = Vec::new();
+        string(&mut block, vendor);
+        block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for text in comments {
+            string(&mut block, text);
+        }
+        block
+    }
+
+    /// One Ogg page holding `packets`, with the CRC left zero.
+    fn ogg_page(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS\0\x02".to_vec();
+        page.extend_from_slice(&[0; 20]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        for packet in packets {
+            page.extend_from_slice(packet);
+        }
+        page
+    }
+
+    #[test]
+    fn reads_vorbis_comment_header() {
+        let mut id = b"\x01vorbis".to_vec();
+        id.extend_from_slice(&0u32.to_le_bytes());
+        id.push(2);
+        id.extend_from_slice(&44100u32.to_le_bytes());
+        id.extend_from_slice(&[0; 13]);
+        let mut comments = b"\x03vorbis".to_vec();
+        let long_title = format!("TITLE={}", "x".repeat(300));
+        comments.extend(comment_block("Xiph.Org libVorbis", &["ARTIST=Someone", &long_title, "junk"]));
+        comments.push(1);
+
+        let metadata = Metadata::read(Format::Ogg, &ogg_page(&[id, comments])).unwrap();
+        assert_eq!(metadata.channels, Some(2));
+        assert_eq!(metadata.sample_rate, Some(44100));
+        assert_eq!(metadata.vendor.as_deref(), Some("Xiph.Org libVorbis"));
+        assert_eq!(
+            metadata.comments,
+            vec![("ARTIST".to_string(), "Someone".to_string()), ("TITLE".to_string(), "x".repeat(300))]
+        );
+
+        assert_eq!(Metadata::read(Format::Ogg, b"OggS"), None);
+        assert_eq!(Metadata::read(Format::Mp3, &ogg_page(&[b"\x01vorbis".to_vec()])), None);
+    }
+
+    #[test]
+    fn reads_flac_streaminfo_and_comments() {
+        let mut streaminfo = vec![0; 34];
+        // 48000 Hz, 2 channels, 16 bits per sample.
+        streaminfo[10..13].copy_from_slice(&[0x0b, 0xb8, 0x02]);
+        streaminfo[13] = 0xf0;
+        let comments = comment_block("reference libFLAC", &["TITLE=Intro"]);
+
+        let mut data = b"fLaC".to_vec();
+        data.push(0);
+        data.extend_from_slice(&(streaminfo.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(&streaminfo);
+        data.push(0x84);
+        data.extend_from_slice(&(comments.len() as u32).to_be_bytes()[1..]);
+        data.extend_from_slice(&comments);
+
+        let metadata = Metadata::read(Format::Flac, &data).unwrap();
+        assert_eq!((metadata.channels, metadata.sample_rate), (Some(2), Some(48000)));
+        assert_eq!(metadata.comments, vec![("TITLE".to_string(), "Intro".to_string())]);
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::wav::read_u32_le;
+
+/// Total duration announced by a Xing/Info header in the first MPEG audio
+/// frame, as written by VBR encoders. `None` if there's no such header or
+/// it doesn't carry a frame count.
+pub fn xing_duration(data: &[u8]) -> Option<Duration> {
+    let pos = skip_id3v2(data);
+    let header = data.get(pos..pos + 4)?;
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+        return None;
+    }
+
+    // Version: 3 = MPEG1, 2 = MPEG2, 0 = MPEG2.5. Only Layer III (1) is handled.
+    let version = (header[1] >> 3) & 0x3;
+    let layer = (header[1] >> 1) & 0x3;
+    if version == 1 || layer != 1 {
+        return None;
+    }
+    let rates = match version {
+        3 => [44100, 48000, 32000],
+        2 => [22050, 24000, 16000],
+        _ => [11025, 12000, 8000],
+    };
+    let sample_rate = *rates.get(((header[2] >> 2) & 0x3) as usize)?;
+    let mono = header[3] >> 6 == 3;
+
+    let side_info = match (version == 3, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let tag = pos + 4 + side_info;
+    if !matches!(data.get(tag..tag + 4)?, b"Xing" | b"Info") {
+        return None;
+    }
+    let flags = read_u32_be(data, tag + 4)?;
+    if flags & 1 == 0 {
+        return None;
+    }
+    let frames = read_u32_be(data, tag + 8)?;
+
+    let samples_per_frame = if version == 3 { 1152 } else { 576 };
+    Some(Duration::from_secs_f64(
+        frames as f64 * samples_per_frame as f64 / sample_rate as f64,
+    ))
+}
+
+/// Offset of the first byte after a leading ID3v2 tag, or 0 without one.
+fn skip_id3v2(data: &[u8]) -> usize {
+    match data {
+        [b'I', b'D', b'3', _, _, flags, size @ ..] if size.len() >= 4 => {
+            let size = size[..4].iter().fold(0usize, |acc, b| acc << 7 | (*b & 0x7f) as usize);
+            let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    }
+}
+
+fn read_u32_be(data: &[u8], pos: usize) -> Option<u32> {
+    read_u32_le(data, pos).map(u32::swap_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An MPEG1 Layer III, 44.1 kHz stereo frame header followed by a Xing
+    /// tag announcing `frames` frames.
+    fn xing_frame(tag: &[u8; 4], frames: u32) -> Vec<u8> {
+        let mut frame = vec![0xff, 0xfb, 0x90, 0x00];
+        frame.extend_from_slice(&[0; 32]);
+        frame.extend_from_slice(tag);
+        frame.extend_from_slice(&1u32.to_be_bytes());
+        frame.extend_from_slice(&frames.to_be_bytes());
+        frame.resize(417, 0);
+        frame
+    }
+
+    #[test]
+    fn reads_xing_frame_count() {
+        let duration = xing_duration(&xing_frame(b"Xing", 1000)).unwrap();
+        assert_eq!(duration.as_millis(), 26122);
+
+        let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x05".to_vec();
+        tagged.extend_from_slice(&[0; 5]);
+        tagged.extend_from_slice(&xing_frame(b"Info", 38));
+        assert_eq!(xing_duration(&tagged).unwrap().as_millis(), 992);
+    }
+
+    #[test]
+    fn no_header_means_unknown() {
+        assert_eq!(xing_duration(&xing_frame(b"LAME", 1000)), None);
+        assert_eq!(xing_duration(&[0xff, 0xfb]), None);
+        assert_eq!(xing_duration(b"RIFF"), None);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::str::FromStr;
+
+/// Object storage services `--url` knows the schemes of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    S3,
+    Gcs,
+}
+
+/// A `s3://bucket/key` or `gs://bucket/key` object to read the input from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectUrl {
+    pub store: Store,
+    pub bucket: String,
+    pub key: String,
+}
+
+impl ObjectUrl {
+    /// The provider's own CLI writing the object to stdout, which picks up
+    /// the usual credentials.
+    fn command(&self) -> Command {
+        let url = self.to_string();
+        let mut command = match self.store {
+            Store::S3 => Command::new("aws"),
+            Store::Gcs => Command::new("gcloud"),
+        };
+        match self.store {
+            Store::S3 => command.args(["s3", "cp", &url, "-"]),
+            Store::Gcs => command.args(["storage", "cat", &url]),
+        };
+        command
+    }
+
+    /// Starts streaming the object. The CLI's errors go to stderr; one that
+    /// exits unsuccessfully fails the read at the end of its output.
+    pub fn open(&self) -> Result<Box<dyn Read + Send>, String> {
+        let mut command = self.command();
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run `{}` to read {}: {}", program, self, e))?;
+        let stdout = child.stdout.take().unwrap();
+        Ok(Box::new(ObjectReader { child, stdout, program }))
+    }
+}
+
+/// The output of the CLI an object is read with.
+struct ObjectReader {
+    child: Child,
+    stdout: ChildStdout,
+    program: String,
+}
+
+impl Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("`{}` {}", self.program, status)));
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl FromStr for ObjectUrl {
+    type Err = String;
+
+    fn from_str(url: &str) -> Result<Self, String> {
+        let (store, rest) = if let Some(rest) = url.strip_prefix("s3://") {
+            (Store::S3, rest)
+        } else if let Some(rest) = url.strip_prefix("gs://") {
+            (Store::Gcs, rest)
+        } else {
+            return Err(format!("unsupported URL {:?}, expected s3://BUCKET/KEY or gs://BUCKET/KEY", url));
+        };
+        match rest.split_once('/') {
+            Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok(ObjectUrl {
+                store,
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            }),
+            _ => Err(format!("{:?} doesn't name an object, expected BUCKET/KEY after the scheme", url)),
+        }
+    }
+}
+
+impl fmt::Display for ObjectUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.store {
+            Store::S3 => "s3",
+            Store::Gcs => "gs",
+        };
+        write!(f, "{}://{}/{}", scheme, self.bucket, self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_object_urls() {
+        let url: ObjectUrl = "s3://captures/2024/run-1.jsonl".parse().unwrap();
+        assert_eq!(url.store, Store::S3);
+        assert_eq!(url.bucket, "captures");
+        assert_eq!(url.key, "2024/run-1.jsonl");
+        assert_eq!(url.to_string(), "s3://captures/2024/run-1.jsonl");
+        assert_eq!("gs://b/k".parse::<ObjectUrl>().unwrap().store, Store::Gcs);
+
+        assert!("https://example.com/a".parse::<ObjectUrl>().is_err());
+        assert!("s3://bucket".parse::<ObjectUrl>().is_err());
+        assert!("gs:///key".parse::<ObjectUrl>().is_err());
+    }
+
+    #[test]
+    fn reads_objects_with_the_provider_cli() {
+        let command = |url: &str| {
+            let command = url.parse::<ObjectUrl>().unwrap().command();
+            let mut line = vec![command.get_program().to_string_lossy().into_owned()];
+            line.extend(command.get_args().map(|arg| arg.to_string_lossy().into_owned()));
+            line.join(" ")
+        };
+        assert_eq!(command("s3://b/k/run.jsonl"), "aws s3 cp s3://b/k/run.jsonl -");
+        assert_eq!(command("gs://b/k"), "gcloud storage cat gs://b/k");
+    }
+}
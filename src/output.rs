@@ -0,0 +1,480 @@
+use base64::{engine::general_purpose, Engine as _};
+use rodio::Source;
+use std::fmt;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use crate::bitdepth::{self, Dither};
+use crate::format::Format;
+use crate::wav::{self, WavInfo};
+
+/// `--chunk-bytes` default.
+pub const DEFAULT_CHUNK_BYTES: usize = 8192;
+
+/// Container written by `--out`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Wav,
+    /// Headerless little-endian PCM.
+    Raw,
+    /// The mp3 chunks concatenated as received. There is no encoder, so this
+    /// requires mp3 input.
+    Mp3,
+    /// JSONL records of complete WAV chunks holding `--chunk-bytes` of PCM
+    /// each, for re-chunking a stream.
+    Jsonl,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 4] = ["wav", "raw", "mp3", "jsonl"];
+
+    /// The output matching what is received when `--output-format` isn't given.
+    pub fn default_for(input: Format) -> Self {
+        match input {
+            Format::Mp3 => OutputFormat::Mp3,
+            _ => OutputFormat::Wav,
+        }
+    }
+
+    /// Checks up front that chunks in `input` can be written in this format.
+    pub fn check_input(self, input: Format) -> Result<(), String> {
+        if self == OutputFormat::Mp3 && input != Format::Mp3 {
+            return Err(format!(
+                "mp3 output needs mp3 input; {} can't be transcoded to mp3",
+                input
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::Raw => "raw",
+            OutputFormat::Mp3 => "mp3",
+            OutputFormat::Jsonl => "jsonl",
+        })
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wav" => Ok(OutputFormat::Wav),
+            "raw" => Ok(OutputFormat::Raw),
+            "mp3" => Ok(OutputFormat::Mp3),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!("unknown output format `{}`", s)),
+        }
+    }
+}
+
+/// How audio bytes are obtained from chunks, fixed by the first chunk.
+enum Layout {
+    /// WAV input: the PCM bodies are copied without decoding. `header` is the
+    /// first chunk's header, rewritten with the final sizes on finish.
+    Pcm { header: Vec<u8>, info: WavInfo },
+    /// Compressed input decoded to 16-bit PCM.
+    Decoded {
+        header: Vec<u8>,
+        channels: u16,
+        sample_rate: u32,
+    },
+    /// Compressed bytes copied as is.
+    Mp3,
+}
+
+/// Serializes received chunks into a single file.
+pub struct AudioWriter<W: Write + Seek> {
+    out: W,
+    format: OutputFormat,
+    layout: Option<Layout>,
+    data_len: u64,
+    /// Sum of the `data` sizes the WAV chunks claimed.
+    declared_len: u64,
+    /// PCM bytes per `jsonl` record.
+    chunk_bytes: usize,
+    /// PCM not yet written as a `jsonl` record.
+    pending: Vec<u8>,
+    /// `--out-bits`: sample width PCM is converted to.
+    out_bits: Option<u16>,
+    dither: Option<Dither>,
+}
+
+impl<W: Write + Seek> AudioWriter<W> {
+    pub fn new(out: W, format: OutputFormat) -> Self {
+        Self {
+            out,
+            format,
+            layout: None,
+            data_len: 0,
+            declared_len: 0,
+            chunk_bytes: DEFAULT_CHUNK_BYTES,
+            pending: Vec::new(),
+            out_bits: None,
+            dither: None,
+        }
+    }
+
+    /// Sets how much PCM each `jsonl` record holds, rounded down to whole
+    /// frames.
+    pub fn with_chunk_bytes(mut self, chunk_bytes: usize) -> Self {
+        self.chunk_bytes = chunk_bytes;
+        self
+    }
+
+    /// Converts PCM to `bits` per sample before writing it, dithering when
+    /// that narrows it if `dither` is set.
+    pub fn with_out_bits(mut self, bits: Option<u16>, dither: bool) -> Self {
+        self.out_bits = bits;
+        self.dither = dither.then(Dither::new);
+        self
+    }
+
+    /// Appends one chunk. Chunks that don't match the layout established by
+    /// the first chunk are rejected without writing anything.
+    pub fn write_chunk(&mut self, format: Format, data: Vec<u8>) -> Result<(), String> {
+        self.format.check_input(format)?;
+
+        let first = self.layout.is_none();
+        if first {
+            let layout = self.layout_for(format, &data)?;
+            let header: &[u8] = match &layout {
+                Layout::Pcm { header, .. } | Layout::Decoded { header, .. } => header,
+                Layout::Mp3 => &[],
+            };
+            if self.format == OutputFormat::Wav {
+                // Start out as a valid empty file; the sizes are patched in `finish`.
+                let placeholder = wav::reconstruct_wav_file(header, &[]);
+                self.out.write_all(&placeholder).map_err(|e| e.to_string())?;
+            }
+            self.layout = Some(layout);
+        }
+
+        let mut width = None;
+        let bytes = match self.layout.as_ref().unwrap() {
+            Layout::Mp3 => data,
+            Layout::Pcm { info, .. } => {
+                let chunk_info = wav::parse_wav_info(&data);
+                if chunk_info.map(|chunk| same_pcm_format(&chunk, info)) != Some(true) {
+                    return Err("WAV chunk format differs from the first chunk".to_string());
+                }
+                let (header, body) = wav::extract_wav_header(&data).unwrap();
+                if !first && body.is_empty() {
+                    // Reconnecting producers start over with a bare header,
+                    // whose data size means nothing for this file.
+                    info!("WAV header re-sent mid-stream, skipping it");
+                    return Ok(());
+                }
+                let declared = wav::declared_sizes(header).map_or(body.len() as u32, |(_, size)| size);
+                let width = *width.insert(info.bits_per_sample);
+                self.declared_len += u64::from(declared) * u64::from(self.out_bits.unwrap_or(width)) / u64::from(width);
+                body.to_vec()
+            }
+            Layout::Decoded {
+                channels,
+                sample_rate,
+                ..
+            } => {
+                let source = format.decoder(data).map_err(|e| e.to_string())?;
+                if source.channels() != *channels || source.sample_rate() != *sample_rate {
+                    return Err("decoded chunk layout differs from the first chunk".to_string());
+                }
+                width = Some(16);
+                source.flat_map(i16::to_le_bytes).collect()
+            }
+        };
+        let bytes = match (width, self.out_bits) {
+            (Some(from), Some(to)) if from != to => bitdepth::convert(&bytes, from, to, self.dither.as_mut()),
+            _ => bytes,
+        };
+
+        self.data_len += bytes.len() as u64;
+        if self.format == OutputFormat::Jsonl {
+            self.pending.extend_from_slice(&bytes);
+            return self.write_records(false).map_err(|e| e.to_string());
+        }
+        self.out.write_all(&bytes).map_err(|e| e.to_string())
+    }
+
+    /// Writes the pending PCM as records of whole `chunk_bytes`, each with
+    /// a fresh copy of the input's header. With `all`, the remainder is
+    /// written as a shorter last record.
+    fn write_records(&mut self, all: bool) -> io::Result<()> {
+        let (header, frame) = match &self.layout {
+            Some(Layout::Pcm { header, info }) => {
+                let bits = self.out_bits.unwrap_or(info.bits_per_sample);
+                (header, info.channels as usize * (bits as usize / 8))
+            }
+            Some(Layout::Decoded { header, channels, .. }) => {
+                (header, *channels as usize * (self.out_bits.unwrap_or(16) as usize / 8))
+            }
+            _ => return Ok(()),
+        };
+        let frame = frame.max(1);
+        let size = (self.chunk_bytes / frame).max(1) * frame;
+
+        let mut start = 0;
+        while self.pending.len() - start >= size || (all && start < self.pending.len()) {
+            let end = (start + size).min(self.pending.len());
+            let file = wav::reconstruct_wav_file(header, &self.pending[start..end]);
+            writeln!(self.out, "{{\"data\":\"{}\"}}", general_purpose::STANDARD.encode(file))?;
+            start = end;
+        }
+        self.pending.drain(..start);
+        Ok(())
+    }
+
+    fn layout_for(&self, format: Format, data: &[u8]) -> Result<Layout, String> {
+        if self.format == OutputFormat::Mp3 {
+            return Ok(Layout::Mp3);
+        }
+        if format == Format::Wav {
+            let (header, _) = wav::extract_wav_header(data).ok_or("first chunk has no WAV header")?;
+            let info = wav::parse_wav_info(data).ok_or("first chunk has no fmt chunk")?;
+            let mut header = header.to_vec();
+            // Both rewrite the samples as little-endian, which a RIFX
+            // header would misdescribe.
+            if info.rifx && (self.out_bits.is_some() || self.format == OutputFormat::Raw) {
+                return Err(format!(
+                    "big-endian RIFX input can't be written {}",
+                    if self.out_bits.is_some() { "with --out-bits" } else { "as little-endian raw PCM" }
+                ));
+            }
+            if let Some(bits) = self.out_bits {
+                if info.format_tag == wav::FORMAT_IEEE_FLOAT || !bitdepth::supported(info.bits_per_sample) {
+                    return Err(format!(
+                        "--out-bits converts 16, 24 and 32-bit integer PCM, not {}-bit{}",
+                        info.bits_per_sample,
+                        if info.format_tag == wav::FORMAT_IEEE_FLOAT { " float" } else { "" }
+                    ));
+                }
+                if !wav::set_bits_per_sample(&mut header, bits) {
+                    return Err(format!(
+                        "--out-bits {} overflows the WAV header of {} channels at {} Hz",
+                        bits, info.channels, info.sample_rate
+                    ));
+                }
+            }
+            return Ok(Layout::Pcm { header, info });
+        }
+        let source = format.decoder(data.to_vec()).map_err(|e| e.to_string())?;
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        let bits = self.out_bits.unwrap_or(16);
+        let header = wav::pcm_header(channels, sample_rate, bits).ok_or_else(|| {
+            format!("{}-bit audio of {} channels at {} Hz overflows a WAV header", bits, channels, sample_rate)
+        })?;
+        Ok(Layout::Decoded {
+            header,
+            channels,
+            sample_rate,
+        })
+    }
+
+    /// Patches the WAV sizes from the bytes written, now that the total is
+    /// known, and flushes. Sizes the input claimed differently are logged.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.format == OutputFormat::Jsonl {
+            self.write_records(true)?;
+        }
+        if self.format == OutputFormat::Wav {
+            match self.layout.take() {
+                Some(Layout::Pcm { mut header, .. }) => {
+                    self.check_sizes(&header);
+                    let data_len = u32::try_from(self.data_len).unwrap_or(u32::MAX);
+                    wav::patch_wav_sizes(&mut header, data_len);
+                    self.rewrite_header(&header)?;
+                }
+                Some(Layout::Decoded { mut header, .. }) => {
+                    let data_len = u32::try_from(self.data_len).unwrap_or(u32::MAX);
+                    wav::patch_wav_sizes(&mut header, data_len);
+                    self.rewrite_header(&header)?;
+                }
+                _ => {}
+            }
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    /// Logs where the sizes claimed by the input disagree with what was
+    /// written.
+    fn check_sizes(&self, header: &[u8]) {
+        let Some((riff, data)) = wav::declared_sizes(header) else {
+            return;
+        };
+        if self.declared_len != self.data_len {
+            warn!(
+                "WAV chunks claim {} data bytes but {} were written; writing the real size",
+                self.declared_len, self.data_len
+            );
+        }
+        let overhead = header.len() as u32 - 8;
+        if riff.wrapping_sub(data) != overhead {
+            warn!(
+                "WAV header RIFF size {} doesn't match its {} header bytes and {} data bytes; writing the real size",
+                riff,
+                header.len(),
+                data
+            );
+        }
+    }
+
+    fn rewrite_header(&mut self, header: &[u8]) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(header)?;
+        self.out.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+fn same_pcm_format(a: &WavInfo, b: &WavInfo) -> bool {
+    (a.channels, a.sample_rate, a.byte_rate) == (b.channels, b.sample_rate, b.byte_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::{rifx_file, wav_file};
+    use std::io::Cursor;
+
+    fn write(format: OutputFormat, chunks: &[(Format, Vec<u8>)]) -> Vec<u8> {
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), format);
+        for (chunk_format, data) in chunks {
+            writer.write_chunk(*chunk_format, data.clone()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn wav_chunks() -> Vec<(Format, Vec<u8>)> {
+        vec![
+            (Format::Wav, wav_file(1, 8000, &[1, 0, 2, 0])),
+            (Format::Wav, wav_file(1, 8000, &[3, 0, 4, 0, 5, 0])),
+        ]
+    }
+
+    #[test]
+    fn wav_round_trip() {
+        let file = write(OutputFormat::Wav, &wav_chunks());
+        assert_eq!(file, wav_file(1, 8000, &[1, 0, 2, 0, 3, 0, 4, 0, 5, 0]));
+
+        let info = wav::parse_wav_info(&file).unwrap();
+        assert_eq!(info.data_len, 10);
+        let samples: Vec<i16> = Format::Wav.decoder(file).unwrap().collect();
+        assert_eq!(samples, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let file = write(OutputFormat::Raw, &wav_chunks());
+        assert_eq!(file, vec![1, 0, 2, 0, 3, 0, 4, 0, 5, 0]);
+    }
+
+    #[test]
+    fn jsonl_rechunks_pcm() {
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Jsonl).with_chunk_bytes(5);
+        for (format, data) in wav_chunks() {
+            writer.write_chunk(format, data).unwrap();
+        }
+        let out = String::from_utf8(writer.finish().unwrap().into_inner()).unwrap();
+
+        // Five bytes round down to two 16-bit frames.
+        let chunks: Vec<Vec<i16>> = out
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                let data = general_purpose::STANDARD.decode(record["data"].as_str().unwrap()).unwrap();
+                crate::pcm::Pcm::decode(Format::Wav, data).unwrap().samples
+            })
+            .collect();
+        assert_eq!(chunks, vec![vec![1, 2], vec![3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn out_bits_narrows_24_bit_pcm() {
+        let header = wav::pcm_header(2, 8000, 24).unwrap();
+        let body = [0x56, 0x34, 0x12, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00];
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Wav).with_out_bits(Some(16), false);
+        writer.write_chunk(Format::Wav, wav::reconstruct_wav_file(&header, &body)).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let info = wav::parse_wav_info(&file).unwrap();
+        assert_eq!((info.channels, info.bits_per_sample, info.byte_rate), (2, 16, 32000));
+        assert_eq!(wav::read_u16_le(&file, 32), Some(4));
+        assert_eq!(info.data_len, 8);
+        let samples: Vec<i16> = Format::Wav.decoder(file).unwrap().collect();
+        assert_eq!(samples, vec![0x1234, 0, -0x8000, 0]);
+    }
+
+    #[test]
+    fn rejects_rifx_where_samples_become_little_endian() {
+        let rifx = rifx_file(1, 8000, &[0, 1, 0, 2]);
+
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Wav).with_out_bits(Some(24), false);
+        let error = writer.write_chunk(Format::Wav, rifx.clone()).unwrap_err();
+        assert_eq!(error, "big-endian RIFX input can't be written with --out-bits");
+        assert!(writer.finish().unwrap().into_inner().is_empty());
+
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Raw);
+        let error = writer.write_chunk(Format::Wav, rifx.clone()).unwrap_err();
+        assert_eq!(error, "big-endian RIFX input can't be written as little-endian raw PCM");
+
+        // Copied as is, it stays a valid RIFX file.
+        assert_eq!(write(OutputFormat::Wav, &[(Format::Wav, rifx.clone())]), rifx);
+    }
+
+    #[test]
+    fn out_bits_rejects_headers_it_would_overflow() {
+        let file = wav::reconstruct_wav_file(&wav::pcm_header(20000, 8000, 16).unwrap(), &[]);
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Wav).with_out_bits(Some(32), false);
+        let error = writer.write_chunk(Format::Wav, file).unwrap_err();
+        assert_eq!(error, "--out-bits 32 overflows the WAV header of 20000 channels at 8000 Hz");
+    }
+
+    #[test]
+    fn mp3_round_trip() {
+        let chunks = vec![(Format::Mp3, vec![0xff, 0xfb, 1]), (Format::Mp3, vec![0xff, 0xfb, 2])];
+        let file = write(OutputFormat::Mp3, &chunks);
+        assert_eq!(file, vec![0xff, 0xfb, 1, 0xff, 0xfb, 2]);
+    }
+
+    #[test]
+    fn skips_header_resent_mid_stream() {
+        let mut resent = wav_file(1, 8000, &[]);
+        wav::write_u32_le(&mut resent, 40, u32::MAX);
+        let chunks = vec![
+            (Format::Wav, wav_file(1, 8000, &[1, 0, 2, 0])),
+            (Format::Wav, resent),
+            (Format::Wav, wav_file(1, 8000, &[3, 0])),
+        ];
+        assert_eq!(write(OutputFormat::Wav, &chunks), wav_file(1, 8000, &[1, 0, 2, 0, 3, 0]));
+    }
+
+    #[test]
+    fn rejects_incompatible_chunks() {
+        assert!(OutputFormat::Mp3.check_input(Format::Wav).is_err());
+
+        let mut writer = AudioWriter::new(Cursor::new(Vec::new()), OutputFormat::Wav);
+        writer.write_chunk(Format::Wav, wav_file(1, 8000, &[1, 0])).unwrap();
+        assert!(writer.write_chunk(Format::Wav, wav_file(2, 8000, &[1, 0, 2, 0])).is_err());
+        let file = writer.finish().unwrap().into_inner();
+        assert_eq!(file, wav_file(1, 8000, &[1, 0]));
+    }
+
+    #[test]
+    fn fixes_stale_sizes() {
+        let mut stale = wav_file(1, 8000, &[1, 0, 2, 0]);
+        wav::write_u32_le(&mut stale, 4, 1000);
+        // Claimed sizes from before the producer knew the final length.
+        wav::write_u32_le(&mut stale, 40, 100);
+        let chunks = vec![(Format::Wav, stale), (Format::Wav, wav_file(1, 8000, &[3, 0]))];
+
+        let fixed = write(OutputFormat::Wav, &chunks);
+        assert_eq!(fixed, wav_file(1, 8000, &[1, 0, 2, 0, 3, 0]));
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Spaces chunks out by the deltas between their `ts` fields, for
+/// `--use-timestamps`. Each chunk is due its delta after the previous one
+/// was due, so time spent decoding doesn't accumulate as drift.
+#[derive(Debug, Default)]
+pub struct Pacer {
+    /// The last usable timestamp, in ms, and when its chunk was due.
+    last: Option<(u64, Instant)>,
+}
+
+impl Pacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait before appending chunk `index` stamped `ts`. A
+    /// missing or backwards timestamp is appended right away.
+    pub fn wait(&mut self, index: usize, ts: Option<u64>) -> Duration {
+        self.wait_at(Instant::now(), index, ts)
+    }
+
+    fn wait_at(&mut self, now: Instant, index: usize, ts: Option<u64>) -> Duration {
+        let Some(ts) = ts else {
+            warn!("Audio chunk {} has no timestamp, appending it immediately", index);
+            return Duration::ZERO;
+        };
+        match self.last {
+            Some((last_ts, last_due)) if ts >= last_ts => {
+                let due = last_due + Duration::from_millis(ts - last_ts);
+                self.last = Some((ts, due));
+                due.saturating_duration_since(now)
+            }
+            Some((last_ts, _)) => {
+                warn!(
+                    "Audio chunk {} is stamped {} ms, before the previous {} ms; appending it immediately",
+                    index, ts, last_ts
+                );
+                self.last = Some((ts, now));
+                Duration::ZERO
+            }
+            None => {
+                self.last = Some((ts, now));
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_timestamp_deltas() {
+        let start = Instant::now();
+        let mut pacer = Pacer::new();
+        assert_eq!(pacer.wait_at(start, 1, Some(1000)), Duration::ZERO);
+        assert_eq!(pacer.wait_at(start, 2, Some(1250)), Duration::from_millis(250));
+        // Decoding took 100 ms; the next chunk is still due 250 ms + 500 ms in.
+        let now = start + Duration::from_millis(350);
+        assert_eq!(pacer.wait_at(now, 3, Some(1750)), Duration::from_millis(400));
+
+        // Missing and backwards timestamps don't wait; pacing restarts from
+        // the backwards one.
+        let now = start + Duration::from_millis(750);
+        assert_eq!(pacer.wait_at(now, 4, None), Duration::ZERO);
+        assert_eq!(pacer.wait_at(now, 5, Some(500)), Duration::ZERO);
+        assert_eq!(pacer.wait_at(now, 6, Some(600)), Duration::from_millis(100));
+    }
+}
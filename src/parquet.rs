@@ -0,0 +1,883 @@
+/// Column `--format parquet` reads when `--field` isn't given.
+pub const DEFAULT_COLUMN: &str = "data";
+
+/// Magic bytes at both ends of a Parquet file.
+const MAGIC: &[u8; 4] = b"PAR1";
+
+/// How deeply thrift structures and lists nest before the metadata is taken
+/// to be corrupt.
+const MAX_DEPTH: usize = 32;
+
+// Physical types, repetitions, codecs, page types and encodings, as
+// numbered by parquet.thrift.
+const BYTE_ARRAY: i32 = 6;
+const OPTIONAL: i32 = 1;
+const REPEATED: i32 = 2;
+const UNCOMPRESSED: i32 = 0;
+const SNAPPY: i32 = 1;
+const DATA_PAGE: i32 = 0;
+const DICTIONARY_PAGE: i32 = 2;
+const DATA_PAGE_V2: i32 = 3;
+const PLAIN: i32 = 0;
+const PLAIN_DICTIONARY: i32 = 2;
+const RLE_DICTIONARY: i32 = 8;
+
+/// Calls `each` with the values of the top-level binary column `column`,
+/// row by row and `None` for nulls, until it returns `false`.
+///
+/// This reads what common writers produce for a flat table: PLAIN or
+/// dictionary-encoded values in v1 or v2 data pages, uncompressed or
+/// snappy-compressed. Anything else fails with what isn't supported.
+pub fn for_each_value(file: &[u8], column: &str, mut each: impl FnMut(Option<Vec<u8>>) -> bool) -> Result<(), String> {
+    let metadata = FileMetaData::read(file)?;
+    let optional = metadata.find(column)?;
+    for (index, group) in metadata.row_groups.iter().enumerate() {
+        let chunk = group
+            .iter()
+            .find(|chunk| chunk.path == [column])
+            .ok_or_else(|| format!("row group {} has no {:?} column", index + 1, column))?;
+        if !read_chunk(file, chunk, optional, &mut each)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// A reader of the thrift compact protocol the metadata is written in, and
+/// of the varints that also frame RLE runs and snappy data.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, depth: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len());
+        let bytes = &self.data[self.pos..end.ok_or("Parquet data ends early")?];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("overlong varint in Parquet data".to_string())
+    }
+
+    fn size(&mut self) -> Result<usize, String> {
+        usize::try_from(self.varint()?).map_err(|_| "size out of range in Parquet data".to_string())
+    }
+
+    /// A zigzag-encoded integer.
+    fn int(&mut self) -> Result<i64, String> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        i32::try_from(self.int()?).map_err(|_| "i32 out of range in Parquet metadata".to_string())
+    }
+
+    fn binary(&mut self) -> Result<&'a [u8], String> {
+        let len = self.size()?;
+        self.bytes(len)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        Ok(String::from_utf8_lossy(self.binary()?).into_owned())
+    }
+
+    fn nest(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("Parquet metadata nests too deeply".to_string());
+        }
+        Ok(())
+    }
+
+    /// Reads a list, calling `element` with the element type for each.
+    fn list<T>(&mut self, mut element: impl FnMut(&mut Self, u8) -> Result<T, String>) -> Result<Vec<T>, String> {
+        self.nest()?;
+        let header = self.byte()?;
+        let size = match header >> 4 {
+            15 => self.size()?,
+            size => usize::from(size),
+        };
+        // Every element takes a byte, so a corrupt size can't allocate up front.
+        let mut items = Vec::with_capacity(size.min(self.data.len() - self.pos));
+        for _ in 0..size {
+            items.push(element(self, header & 0x0f)?);
+        }
+        self.depth -= 1;
+        Ok(items)
+    }
+
+    /// Reads a struct, calling `field` with the id and type of each field.
+    /// It must read the value or [`skip`](Self::skip) it.
+    fn fields(&mut self, mut field: impl FnMut(&mut Self, i16, u8) -> Result<(), String>) -> Result<(), String> {
+        self.nest()?;
+        let mut last = 0i16;
+        loop {
+            let header = self.byte()?;
+            if header == 0 {
+                break;
+            }
+            let id = match header >> 4 {
+                0 => i16::try_from(self.int()?).map_err(|_| "field id out of range in Parquet metadata")?,
+                delta => last.wrapping_add(i16::from(delta)),
+            };
+            last = id;
+            field(self, id, header & 0x0f)?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    /// Skips a struct field's value. Booleans are held in the type itself.
+    fn skip(&mut self, kind: u8) -> Result<(), String> {
+        match kind {
+            1 | 2 => Ok(()),
+            3 => self.byte().map(drop),
+            4..=6 => self.varint().map(drop),
+            7 => self.bytes(8).map(drop),
+            8 => self.binary().map(drop),
+            9 | 10 => self.list(Self::skip_element).map(drop),
+            11 => {
+                let size = self.varint()?;
+                if size > 0 {
+                    let kinds = self.byte()?;
+                    for _ in 0..size {
+                        self.skip_element(kinds >> 4)?;
+                        self.skip_element(kinds & 0x0f)?;
+                    }
+                }
+                Ok(())
+            }
+            12 => self.fields(|reader, _, kind| reader.skip(kind)),
+            _ => Err(format!("unknown thrift type {} in Parquet metadata", kind)),
+        }
+    }
+
+    /// Skips a list or map element, where booleans take a byte.
+    fn skip_element(&mut self, kind: u8) -> Result<(), String> {
+        match kind {
+            1 | 2 => self.byte().map(drop),
+            _ => self.skip(kind),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SchemaElement {
+    name: String,
+    physical_type: Option<i32>,
+    repetition: Option<i32>,
+    children: usize,
+}
+
+#[derive(Debug, Default)]
+struct ColumnChunk {
+    path: Vec<String>,
+    physical_type: i32,
+    codec: i32,
+    num_values: i64,
+    compressed_size: i64,
+    data_page_offset: i64,
+    dictionary_page_offset: Option<i64>,
+    /// Set when the data is in another file.
+    file_path: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct FileMetaData {
+    schema: Vec<SchemaElement>,
+    row_groups: Vec<Vec<ColumnChunk>>,
+}
+
+impl FileMetaData {
+    fn read(file: &[u8]) -> Result<Self, String> {
+        if file.len() < 12 || &file[..4] != MAGIC || &file[file.len() - 4..] != MAGIC {
+            return Err("not a Parquet file".to_string());
+        }
+        let footer_end = file.len() - 8;
+        let footer_len = u32::from_le_bytes(file[footer_end..footer_end + 4].try_into().unwrap()) as usize;
+        let footer_start = footer_end
+            .checked_sub(footer_len)
+            .filter(|&start| start >= 4)
+            .ok_or("Parquet footer is longer than the file")?;
+
+        let mut metadata = FileMetaData::default();
+        let mut reader = Reader::new(&file[footer_start..footer_end]);
+        reader.fields(|reader, id, kind| match (id, kind) {
+            (2, 9) => {
+                metadata.schema = reader.list(|reader, _| {
+                    let mut element = SchemaElement::default();
+                    reader.fields(|reader, id, kind| {
+                        match (id, kind) {
+                            (1, 5) => element.physical_type = Some(reader.i32()?),
+                            (3, 5) => element.repetition = Some(reader.i32()?),
+                            (4, 8) => element.name = reader.string()?,
+                            (5, 5) => element.children = usize::try_from(reader.i32()?).unwrap_or(0),
+                            _ => reader.skip(kind)?,
+                        }
+                        Ok(())
+                    })?;
+                    Ok(element)
+                })?;
+                Ok(())
+            }
+            (4, 9) => {
+                metadata.row_groups = reader.list(|reader, _| {
+                    let mut columns = Vec::new();
+                    reader.fields(|reader, id, kind| match (id, kind) {
+                        (1, 9) => {
+                            columns = reader.list(|reader, _| read_column_chunk(reader))?;
+                            Ok(())
+                        }
+                        _ => reader.skip(kind),
+                    })?;
+                    Ok(columns)
+                })?;
+                Ok(())
+            }
+            _ => reader.skip(kind),
+        })?;
+        Ok(metadata)
+    }
+
+    /// Whether the top-level column `name` is optional, after checking it
+    /// holds binary values this reader can read.
+    fn find(&self, name: &str) -> Result<bool, String> {
+        let root = self.schema.first().ok_or("Parquet file has no schema")?;
+        let mut names = Vec::new();
+        let mut index = 1;
+        for _ in 0..root.children {
+            let element = self.schema.get(index).ok_or("Parquet schema ends early")?;
+            if element.name == name {
+                if element.children > 0 {
+                    return Err(format!("column {:?} is nested, only flat columns can be read", name));
+                }
+                if element.repetition == Some(REPEATED) {
+                    return Err(format!("column {:?} is repeated, only single values can be read", name));
+                }
+                if element.physical_type != Some(BYTE_ARRAY) {
+                    return Err(format!("column {:?} isn't a binary (BYTE_ARRAY) column", name));
+                }
+                return Ok(element.repetition == Some(OPTIONAL));
+            }
+            names.push(format!("{:?}", element.name));
+            // Step over the element's descendants.
+            let mut left = 1;
+            while left > 0 {
+                left += self.schema.get(index).ok_or("Parquet schema ends early")?.children;
+                left -= 1;
+                index += 1;
+            }
+        }
+        Err(format!("no {:?} column in the Parquet file; it has {}", name, names.join(", ")))
+    }
+}
+
+fn read_column_chunk(reader: &mut Reader) -> Result<ColumnChunk, String> {
+    let mut chunk = ColumnChunk::default();
+    reader.fields(|reader, id, kind| {
+        match (id, kind) {
+            (1, 8) => chunk.file_path = Some(reader.string()?),
+            (3, 12) => reader.fields(|reader, id, kind| {
+                match (id, kind) {
+                    (1, 5) => chunk.physical_type = reader.i32()?,
+                    (3, 9) => chunk.path = reader.list(|reader, _| reader.string())?,
+                    (4, 5) => chunk.codec = reader.i32()?,
+                    (5, 6) => chunk.num_values = reader.int()?,
+                    (7, 6) => chunk.compressed_size = reader.int()?,
+                    (9, 6) => chunk.data_page_offset = reader.int()?,
+                    (11, 6) => chunk.dictionary_page_offset = Some(reader.int()?),
+                    _ => reader.skip(kind)?,
+                }
+                Ok(())
+            })?,
+            _ => reader.skip(kind)?,
+        }
+        Ok(())
+    })?;
+    Ok(chunk)
+}
+
+#[derive(Debug, Default)]
+struct PageHeader {
+    kind: i32,
+    uncompressed_size: i32,
+    compressed_size: i32,
+    values: i32,
+    encoding: i32,
+    /// Data page v2 only: the byte lengths of the definition and repetition
+    /// levels, and whether the values are compressed.
+    v2: Option<(i32, i32, bool)>,
+}
+
+impl PageHeader {
+    fn read(reader: &mut Reader) -> Result<Self, String> {
+        let mut header = PageHeader::default();
+        reader.fields(|reader, id, kind| {
+            match (id, kind) {
+                (1, 5) => header.kind = reader.i32()?,
+                (2, 5) => header.uncompressed_size = reader.i32()?,
+                (3, 5) => header.compressed_size = reader.i32()?,
+                (5, 12) | (7, 12) => reader.fields(|reader, id, kind| {
+                    match (id, kind) {
+                        (1, 5) => header.values = reader.i32()?,
+                        (2, 5) => header.encoding = reader.i32()?,
+                        _ => reader.skip(kind)?,
+                    }
+                    Ok(())
+                })?,
+                (8, 12) => {
+                    let (mut definition, mut repetition, mut compressed) = (0, 0, true);
+                    reader.fields(|reader, id, kind| {
+                        match (id, kind) {
+                            (1, 5) => header.values = reader.i32()?,
+                            (4, 5) => header.encoding = reader.i32()?,
+                            (5, 5) => definition = reader.i32()?,
+                            (6, 5) => repetition = reader.i32()?,
+                            (7, 1) => compressed = true,
+                            (7, 2) => compressed = false,
+                            _ => reader.skip(kind)?,
+                        }
+                        Ok(())
+                    })?;
+                    header.v2 = Some((definition, repetition, compressed));
+                }
+                _ => reader.skip(kind)?,
+            }
+            Ok(())
+        })?;
+        Ok(header)
+    }
+}
+
+/// Reads one row group's pages of a column. Returns `false` once `each`
+/// has asked to stop.
+fn read_chunk(
+    file: &[u8],
+    chunk: &ColumnChunk,
+    optional: bool,
+    each: &mut impl FnMut(Option<Vec<u8>>) -> bool,
+) -> Result<bool, String> {
+    if let Some(path) = &chunk.file_path {
+        return Err(format!("column data is in another file, {}", path));
+    }
+    if chunk.physical_type != BYTE_ARRAY {
+        return Err("column chunk isn't binary (BYTE_ARRAY)".to_string());
+    }
+    let start = chunk
+        .dictionary_page_offset
+        .filter(|&offset| offset > 0)
+        .map_or(chunk.data_page_offset, |offset| offset.min(chunk.data_page_offset));
+    let range = usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(chunk.compressed_size).ok())
+        .and_then(|(start, len)| Some(start..start.checked_add(len)?))
+        .filter(|range| range.end <= file.len())
+        .ok_or("column chunk runs past the end of the Parquet file")?;
+    let data = &file[range];
+
+    let mut dictionary: Option<Vec<Vec<u8>>> = None;
+    let mut remaining = chunk.num_values;
+    let mut reader = Reader::new(data);
+    while remaining > 0 && reader.pos < data.len() {
+        let header = PageHeader::read(&mut reader)?;
+        let size = usize::try_from(header.compressed_size).map_err(|_| "negative Parquet page size")?;
+        let body = reader.bytes(size)?;
+        let uncompressed_size = usize::try_from(header.uncompressed_size).map_err(|_| "negative Parquet page size")?;
+        let values = usize::try_from(header.values).map_err(|_| "negative Parquet value count")?;
+        match header.kind {
+            DICTIONARY_PAGE => {
+                let page = decompress(chunk.codec, body, uncompressed_size)?;
+                dictionary = Some(plain_values(&page, values)?);
+            }
+            DATA_PAGE | DATA_PAGE_V2 => {
+                // Definition levels, if the column is optional, then the values.
+                let (levels, page) = match header.v2 {
+                    None => {
+                        let page = decompress(chunk.codec, body, uncompressed_size)?;
+                        if optional {
+                            let mut reader = Reader::new(&page);
+                            let len = u32::from_le_bytes(reader.bytes(4)?.try_into().unwrap()) as usize;
+                            let levels = rle_hybrid(reader.bytes(len)?, 1, values)?;
+                            (Some(levels), page[4 + len..].to_vec())
+                        } else {
+                            (None, page)
+                        }
+                    }
+                    Some((definition, repetition, compressed)) => {
+                        let (definition, repetition) = usize::try_from(definition)
+                            .ok()
+                            .zip(usize::try_from(repetition).ok())
+                            .ok_or("negative Parquet level length")?;
+                        let mut reader = Reader::new(body);
+                        reader.bytes(repetition)?;
+                        let levels = reader.bytes(definition)?;
+                        let levels = optional.then(|| rle_hybrid(levels, 1, values)).transpose()?;
+                        let rest = &body[reader.pos..];
+                        let page = if compressed {
+                            decompress(chunk.codec, rest, uncompressed_size.saturating_sub(reader.pos))?
+                        } else {
+                            rest.to_vec()
+                        };
+                        (levels, page)
+                    }
+                };
+                let present = levels.as_ref().map_or(values, |levels| levels.iter().filter(|&&level| level == 1).count());
+                let decoded = match header.encoding {
+                    PLAIN => plain_values(&page, present)?,
+                    PLAIN_DICTIONARY | RLE_DICTIONARY => {
+                        let dictionary = dictionary.as_ref().ok_or("dictionary-encoded page without a dictionary")?;
+                        let (&width, indices) = page.split_first().ok_or("dictionary-encoded page is empty")?;
+                        rle_hybrid(indices, width, present)?
+                            .into_iter()
+                            .map(|index| dictionary.get(index as usize).cloned())
+                            .collect::<Option<Vec<_>>>()
+                            .ok_or("dictionary index out of range")?
+                    }
+                    encoding => return Err(format!("Parquet encoding {} isn't supported", encoding)),
+                };
+                let mut decoded = decoded.into_iter();
+                for row in 0..values {
+                    let value = match &levels {
+                        Some(levels) if levels[row] != 1 => None,
+                        _ => Some(decoded.next().ok_or("Parquet page holds fewer values than it says")?),
+                    };
+                    if !each(value) {
+                        return Ok(false);
+                    }
+                }
+                remaining -= values as i64;
+            }
+            // Index pages say nothing about the values.
+            _ => {}
+        }
+    }
+    Ok(true)
+}
+
+/// `count` PLAIN byte arrays, each prefixed with its length.
+fn plain_values(page: &[u8], count: usize) -> Result<Vec<Vec<u8>>, String> {
+    let mut reader = Reader::new(page);
+    let mut values = Vec::with_capacity(count.min(page.len() / 4));
+    for _ in 0..count {
+        let len = u32::from_le_bytes(reader.bytes(4)?.try_into().unwrap()) as usize;
+        values.push(reader.bytes(len)?.to_vec());
+    }
+    Ok(values)
+}
+
+/// `count` values of the RLE/bit-packing hybrid encoding, `width` bits each.
+fn rle_hybrid(data: &[u8], width: u8, count: usize) -> Result<Vec<u32>, String> {
+    if width > 32 {
+        return Err(format!("{}-bit RLE values in a Parquet page", width));
+    }
+    let width = usize::from(width);
+    let mut reader = Reader::new(data);
+    let mut values = Vec::with_capacity(count.min(data.len() * 8));
+    while values.len() < count {
+        let header = reader.size()?;
+        let left = count - values.len();
+        if header & 1 == 0 {
+            let mut value = 0u32;
+            for (i, &byte) in reader.bytes(width.div_ceil(8))?.iter().enumerate() {
+                value |= u32::from(byte) << (8 * i);
+            }
+            values.extend(std::iter::repeat_n(value, (header >> 1).min(left)));
+        } else {
+            let groups = header >> 1;
+            let bytes = reader.bytes(groups.checked_mul(width).ok_or("RLE run too long")?)?;
+            for i in 0..(groups * 8).min(left) {
+                let mut value = 0u32;
+                for bit in 0..width {
+                    let at = i * width + bit;
+                    value |= u32::from(bytes[at / 8] >> (at % 8) & 1) << bit;
+                }
+                values.push(value);
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn decompress(codec: i32, data: &[u8], uncompressed_size: usize) -> Result<Vec<u8>, String> {
+    match codec {
+        UNCOMPRESSED => Ok(data.to_vec()),
+        SNAPPY => snappy(data, uncompressed_size),
+        codec => {
+            let name = match codec {
+                2 => "gzip",
+                3 => "lzo",
+                4 => "brotli",
+                5 | 7 => "lz4",
+                6 => "zstd",
+                _ => "an unknown codec",
+            };
+            Err(format!(
+                "the column is compressed with {}; only uncompressed and snappy Parquet columns can be read",
+                name
+            ))
+        }
+    }
+}
+
+/// Decompresses a raw (unframed) snappy block, which should hold
+/// `expected` bytes.
+fn snappy(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut reader = Reader::new(data);
+    let len = reader.size()?;
+    if len != expected {
+        return Err(format!("snappy page holds {} bytes, not the {} its header says", len, expected));
+    }
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        let tag = reader.byte()?;
+        let (copy_len, offset) = match tag & 3 {
+            0 => {
+                let mut literal = usize::from(tag >> 2);
+                if literal >= 60 {
+                    let bytes = reader.bytes(literal - 59)?;
+                    literal = bytes.iter().rev().fold(0, |value, &byte| value << 8 | usize::from(byte));
+                }
+                let bytes = reader.bytes(literal + 1)?;
+                if out.len() + bytes.len() > len {
+                    return Err("snappy data decompresses past its length".to_string());
+                }
+                out.extend_from_slice(bytes);
+                continue;
+            }
+            1 => (4 + usize::from(tag >> 2 & 7), usize::from(tag >> 5) << 8 | usize::from(reader.byte()?)),
+            2 => (1 + usize::from(tag >> 2), usize::from(u16::from_le_bytes(reader.bytes(2)?.try_into().unwrap()))),
+            _ => (1 + usize::from(tag >> 2), u32::from_le_bytes(reader.bytes(4)?.try_into().unwrap()) as usize),
+        };
+        if offset == 0 || offset > out.len() || out.len() + copy_len > len {
+            return Err("corrupt snappy data".to_string());
+        }
+        // Copies may overlap what they write, so go byte by byte.
+        for _ in 0..copy_len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Thrift compact encoding, enough to write the files these tests read.
+    #[derive(Default)]
+    struct Writer {
+        out: Vec<u8>,
+        /// The last field id of each struct being written.
+        last: Vec<i16>,
+    }
+
+    impl Writer {
+        fn varint(&mut self, mut value: u64) {
+            while value >= 0x80 {
+                self.out.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            self.out.push(value as u8);
+        }
+
+        fn zigzag(&mut self, value: i64) {
+            self.varint(((value << 1) ^ (value >> 63)) as u64);
+        }
+
+        fn begin(&mut self) {
+            self.last.push(0);
+        }
+
+        fn end(&mut self) {
+            self.out.push(0);
+            self.last.pop();
+        }
+
+        fn field(&mut self, id: i16, kind: u8) {
+            let last = self.last.last_mut().unwrap();
+            match id - *last {
+                delta @ 1..=15 => self.out.push((delta as u8) << 4 | kind),
+                _ => {
+                    self.out.push(kind);
+                    self.zigzag(i64::from(id));
+                }
+            }
+            *self.last.last_mut().unwrap() = id;
+        }
+
+        fn i32(&mut self, id: i16, value: i32) {
+            self.field(id, 5);
+            self.zigzag(i64::from(value));
+        }
+
+        fn i64(&mut self, id: i16, value: i64) {
+            self.field(id, 6);
+            self.zigzag(value);
+        }
+
+        fn binary(&mut self, id: i16, value: &[u8]) {
+            self.field(id, 8);
+            self.varint(value.len() as u64);
+            self.out.extend_from_slice(value);
+        }
+
+        fn list(&mut self, id: i16, kind: u8, len: usize) {
+            self.field(id, 9);
+            self.out.push((len as u8) << 4 | kind);
+        }
+
+        fn structure(&mut self, id: i16) {
+            self.field(id, 12);
+            self.begin();
+        }
+    }
+
+    /// A page header followed by `body`. `sub` writes the header of the
+    /// page's kind, in field `id`.
+    fn page(kind: i32, uncompressed_size: usize, body: &[u8], id: i16, sub: impl FnOnce(&mut Writer)) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.begin();
+        writer.i32(1, kind);
+        writer.i32(2, uncompressed_size as i32);
+        writer.i32(3, body.len() as i32);
+        writer.structure(id);
+        sub(&mut writer);
+        writer.end();
+        writer.end();
+        writer.out.extend_from_slice(body);
+        writer.out
+    }
+
+    /// A v1 data page of PLAIN values, without definition levels.
+    fn plain_page(values: &[&[u8]]) -> Vec<u8> {
+        let body = plain(values);
+        page(DATA_PAGE, body.len(), &body, 5, |writer| {
+            writer.i32(1, values.len() as i32);
+            writer.i32(2, PLAIN);
+            writer.i32(3, 3);
+            writer.i32(4, 3);
+        })
+    }
+
+    fn plain(values: &[&[u8]]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| [&(value.len() as u32).to_le_bytes()[..], value].concat())
+            .collect()
+    }
+
+    /// Snappy output made of one literal.
+    fn snappy_literal(data: &[u8]) -> Vec<u8> {
+        assert!((1..=60).contains(&data.len()));
+        let mut out = vec![data.len() as u8, ((data.len() - 1) as u8) << 2];
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// One row group of a column: its pages, how many values they hold and
+    /// the length of the dictionary page they start with, if any.
+    pub(crate) struct Group {
+        pages: Vec<u8>,
+        values: i64,
+        dictionary: Option<usize>,
+    }
+
+    /// A Parquet file with one binary column.
+    fn file(column: &str, optional: bool, codec: i32, groups: &[Group]) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        let mut offsets = Vec::new();
+        for group in groups {
+            offsets.push(out.len() as i64);
+            out.extend_from_slice(&group.pages);
+        }
+
+        let mut writer = Writer::default();
+        writer.begin();
+        writer.i32(1, 1);
+        writer.list(2, 12, 2);
+        writer.begin();
+        writer.binary(4, b"schema");
+        writer.i32(5, 1);
+        writer.end();
+        writer.begin();
+        writer.i32(1, BYTE_ARRAY);
+        writer.i32(3, optional as i32);
+        writer.binary(4, column.as_bytes());
+        writer.end();
+        writer.i64(3, groups.iter().map(|group| group.values).sum());
+        writer.list(4, 12, groups.len());
+        for (group, &offset) in groups.iter().zip(&offsets) {
+            writer.begin();
+            writer.list(1, 12, 1);
+            writer.begin();
+            writer.i64(2, offset);
+            writer.structure(3);
+            writer.i32(1, BYTE_ARRAY);
+            writer.list(2, 5, 1);
+            writer.zigzag(i64::from(PLAIN));
+            writer.list(3, 8, 1);
+            writer.varint(column.len() as u64);
+            writer.out.extend_from_slice(column.as_bytes());
+            writer.i32(4, codec);
+            writer.i64(5, group.values);
+            writer.i64(6, group.pages.len() as i64);
+            writer.i64(7, group.pages.len() as i64);
+            writer.i64(9, offset + group.dictionary.unwrap_or(0) as i64);
+            if group.dictionary.is_some() {
+                writer.i64(11, offset);
+            }
+            writer.end();
+            writer.end();
+            writer.i64(2, group.pages.len() as i64);
+            writer.i64(3, group.values);
+            writer.end();
+        }
+        // Metadata the reader doesn't look at.
+        writer.list(5, 12, 1);
+        writer.begin();
+        writer.binary(1, b"writer");
+        writer.binary(2, b"test");
+        writer.end();
+        writer.binary(6, b"jsonl_player tests");
+        writer.end();
+
+        let footer_len = writer.out.len() as u32;
+        out.extend_from_slice(&writer.out);
+        out.extend_from_slice(&footer_len.to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        out
+    }
+
+    /// Two row groups of a required, uncompressed column.
+    pub(crate) fn two_group_file(column: &str, values: &[&[u8]]) -> Vec<u8> {
+        let (first, second) = values.split_at(values.len() / 2);
+        let group = |values: &[&[u8]]| Group {
+            pages: plain_page(values),
+            values: values.len() as i64,
+            dictionary: None,
+        };
+        file(column, false, UNCOMPRESSED, &[group(first), group(second)])
+    }
+
+    fn read(file: &[u8], column: &str) -> Result<Vec<Option<Vec<u8>>>, String> {
+        let mut values = Vec::new();
+        for_each_value(file, column, |value| {
+            values.push(value);
+            true
+        })?;
+        Ok(values)
+    }
+
+    #[test]
+    fn reads_plain_values_across_row_groups() {
+        let file = two_group_file("data", &[b"RIFF1", b"RIFF22", b"", b"x"]);
+        let values = read(&file, "data").unwrap();
+        let expected: Vec<&[u8]> = vec![b"RIFF1", b"RIFF22", b"", b"x"];
+        assert_eq!(values, expected.into_iter().map(|value| Some(value.to_vec())).collect::<Vec<_>>());
+
+        let mut seen = 0;
+        for_each_value(&file, "data", |_| {
+            seen += 1;
+            seen < 3
+        })
+        .unwrap();
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn reads_snappy_dictionary_pages_with_nulls() {
+        let dictionary = plain(&[b"aaaa", b"bb"]);
+        let mut pages = page(DICTIONARY_PAGE, dictionary.len(), &snappy_literal(&dictionary), 7, |writer| {
+            writer.i32(1, 2);
+            writer.i32(2, PLAIN_DICTIONARY);
+        });
+        let dictionary_len = pages.len();
+        // Rows aaaa, null, bb, aaaa: definition levels 1, 0, 1, 1 and indices
+        // 0, 1, 0, each one bit-packed group.
+        let levels = [3, 0b1101];
+        let indices = [1, 3, 0b010];
+        let mut body = levels.to_vec();
+        body.extend(snappy_literal(&indices));
+        pages.extend(page(DATA_PAGE_V2, levels.len() + indices.len(), &body, 8, |writer| {
+            writer.i32(1, 4);
+            writer.i32(2, 1);
+            writer.i32(3, 4);
+            writer.i32(4, RLE_DICTIONARY);
+            writer.i32(5, levels.len() as i32);
+            writer.i32(6, 0);
+        }));
+        let group = Group {
+            pages,
+            values: 4,
+            dictionary: Some(dictionary_len),
+        };
+        let file = file("audio", true, SNAPPY, &[group]);
+
+        let values = read(&file, "audio").unwrap();
+        let (a, b) = (Some(b"aaaa".to_vec()), Some(b"bb".to_vec()));
+        assert_eq!(values, vec![a.clone(), None, b, a]);
+    }
+
+    #[test]
+    fn decodes_snappy_copies() {
+        // "abc", then a copy of 9 bytes from 3 back, which overlaps itself.
+        let data = [12, 2 << 2, b'a', b'b', b'c', 1 | (9 - 4) << 2, 3];
+        assert_eq!(snappy(&data, 12).unwrap(), b"abcabcabcabc");
+        // The same with a two-byte offset.
+        let data = [12, 2 << 2, b'a', b'b', b'c', 2 | (9 - 1) << 2, 3, 0];
+        assert_eq!(snappy(&data, 12).unwrap(), b"abcabcabcabc");
+
+        assert!(snappy(&[4, 1, 5], 4).is_err());
+        assert!(snappy(&data, 13).is_err());
+    }
+
+    #[test]
+    fn decodes_rle_runs() {
+        // Five 7s at 3 bits, then one bit-packed group of 1, 0, 1.
+        assert_eq!(rle_hybrid(&[5 << 1, 7, 3, 0b0100_0001, 0, 0], 3, 8).unwrap(), vec![7, 7, 7, 7, 7, 1, 0, 1]);
+        assert!(rle_hybrid(&[5 << 1], 8, 5).is_err());
+    }
+
+    #[test]
+    fn reports_what_it_cant_read() {
+        let plain = two_group_file("data", &[b"a", b"b"]);
+        assert_eq!(read(&plain, "audio").unwrap_err(), "no \"audio\" column in the Parquet file; it has \"data\"");
+        assert_eq!(read(b"{\"data\":\"\"}\n", "data").unwrap_err(), "not a Parquet file");
+
+        let group = Group {
+            pages: plain_page(&[b"a"]),
+            values: 1,
+            dictionary: None,
+        };
+        let gzip = file("data", false, 2, &[group]);
+        assert_eq!(
+            read(&gzip, "data").unwrap_err(),
+            "the column is compressed with gzip; only uncompressed and snappy Parquet columns can be read"
+        );
+
+        let mut truncated = plain.clone();
+        truncated.drain(4..20);
+        assert!(read(&truncated, "data").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::io::{BufWriter, Write};
+use std::sync::mpsc::Receiver;
+use tracing::{debug, error, info};
+
+use crate::input::Chunk;
+use crate::pool::Recycler;
+
+/// Writes every chunk's decoded bytes to `out` as they arrive, for
+/// `--passthrough`. Nothing is decoded as audio, and written buffers go back
+/// to the reader through `recycler`. Returns the number of chunks and bytes
+/// written.
+pub fn forward<W: Write>(rx: Receiver<Chunk>, out: W, recycler: Option<&Recycler>) -> (usize, u64) {
+    let mut out = BufWriter::new(out);
+    let mut chunks = 0;
+    let mut bytes = 0;
+    for chunk in rx {
+        if let Err(e) = out.write_all(&chunk.data) {
+            error!("Failed to forward chunk {}: {}", chunks + 1, e);
+            break;
+        }
+        chunks += 1;
+        bytes += chunk.data.len() as u64;
+        debug!("Forwarded chunk {}, size: {} bytes", chunks, chunk.data.len());
+        if let Some(recycler) = recycler {
+            recycler.give(chunk.data);
+        }
+    }
+    if let Err(e) = out.flush() {
+        error!("Failed to flush forwarded output: {}", e);
+    }
+    info!("Forwarded {} chunks, {} bytes", chunks, bytes);
+    (chunks, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn output_is_the_concatenated_chunks() {
+        let (tx, rx) = mpsc::channel();
+        for data in [b"RIFF".to_vec(), vec![1, 2, 3], vec![0xff; 5]] {
+            tx.send(Chunk { encoded_len: data.len(), data, format: None, gain: None, ts: None, seq: None }).unwrap();
+        }
+        drop(tx);
+
+        let mut out = Vec::new();
+        assert_eq!(forward(rx, &mut out, None), (3, 12));
+        assert_eq!(out, b"RIFF\x01\x02\x03\xff\xff\xff\xff\xff");
+    }
+}
@@ -0,0 +1,236 @@
+use rodio::buffer::SamplesBuffer;
+use rodio::decoder::DecoderError;
+use rodio::Source;
+use std::time::Duration;
+
+use crate::error::ChunkerError;
+use crate::format::Format;
+use crate::wav;
+
+/// A chunk decoded to interleaved 16-bit samples. Chunks are decoded up
+/// front so their length and levels are known before they're queued.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pcm {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+impl Pcm {
+    pub fn decode(format: Format, data: Vec<u8>) -> Result<Pcm, ChunkerError> {
+        if format == Format::Wav {
+            if Format::detect(&data) != Some(Format::Wav) {
+                return Err(ChunkerError::InvalidRiff);
+            }
+            if let Some(pcm) = Pcm::from_float_wav(&data).or_else(|| Pcm::from_rifx(&data)) {
+                return Ok(pcm);
+            }
+        }
+        let source = format.decoder(data).map_err(|e| match e {
+            DecoderError::UnrecognizedFormat => ChunkerError::UnsupportedFormat(format),
+            // rodio's other errors come from symphonia, which only mp3 pulls in.
+            #[cfg(feature = "mp3")]
+            e => ChunkerError::Decode(e),
+        })?;
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        Ok(Pcm {
+            channels,
+            sample_rate,
+            samples: source.collect(),
+        })
+    }
+
+    /// Decodes a whole audio file of any format this build plays, such as
+    /// `--prepend-file`. WAV sizes are fixed up first, since files written
+    /// by streaming tools often leave them unset.
+    pub fn decode_file(data: Vec<u8>) -> Result<Pcm, String> {
+        let format = Format::detect(&data).ok_or("not a WAV, mp3, Ogg or FLAC file")?;
+        format.check_available()?;
+        let data = match wav::extract_wav_header(&data).filter(|_| format == Format::Wav) {
+            Some((header, body)) => wav::reconstruct_wav_file(header, body),
+            None => data,
+        };
+        Pcm::decode(format, data).map_err(|e| e.to_string())
+    }
+
+    /// Converts IEEE float WAV chunks (32-bit, or 16-bit half precision)
+    /// without going through rodio, which can't play all of them. Returns
+    /// `None` for anything else.
+    fn from_float_wav(data: &[u8]) -> Option<Pcm> {
+        let info = wav::parse_wav_info(data)?;
+        if info.format_tag != wav::FORMAT_IEEE_FLOAT || info.channels == 0 {
+            return None;
+        }
+        let (_, body) = wav::extract_wav_header(data)?;
+        let rifx = info.rifx;
+        let samples = match info.bits_per_sample {
+            32 => body
+                .chunks_exact(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    float_to_i16(if rifx { f32::from_be_bytes(b) } else { f32::from_le_bytes(b) })
+                })
+                .collect(),
+            16 => body
+                .chunks_exact(2)
+                .map(|b| {
+                    let b = [b[0], b[1]];
+                    float_to_i16(f16_to_f32(if rifx { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }))
+                })
+                .collect(),
+            _ => return None,
+        };
+        Some(Pcm {
+            channels: info.channels,
+            sample_rate: info.sample_rate,
+            samples,
+        })
+    }
+
+    /// Converts big-endian `RIFX` integer PCM, which rodio doesn't read,
+    /// keeping the top 16 bits of wider samples. Returns `None` for
+    /// anything else.
+    fn from_rifx(data: &[u8]) -> Option<Pcm> {
+        let info = wav::parse_wav_info(data)?;
+        if !info.rifx || info.format_tag != 1 || info.channels == 0 {
+            return None;
+        }
+        let (_, body) = wav::extract_wav_header(data)?;
+        let samples = match info.bits_per_sample {
+            // 8-bit samples are unsigned, with no byte order.
+            8 => body.iter().map(|&b| (i16::from(b) - 128) << 8).collect(),
+            bits @ (16 | 24 | 32) => body
+                .chunks_exact(usize::from(bits / 8))
+                .map(|b| i16::from_be_bytes([b[0], b[1]]))
+                .collect(),
+            _ => return None,
+        };
+        Some(Pcm {
+            channels: info.channels,
+            sample_rate: info.sample_rate,
+            samples,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        if self.sample_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    /// Largest absolute sample value, as a fraction of full scale.
+    pub fn peak(&self) -> f32 {
+        let peak = self.samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+        peak as f32 / 32768.0
+    }
+
+    /// RMS level as a fraction of full scale.
+    pub fn rms(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self.samples.iter().map(|&s| f64::from(s) * f64::from(s)).sum();
+        ((sum / self.samples.len() as f64).sqrt() / 32768.0) as f32
+    }
+
+    /// Reverses the order of the frames, keeping each frame's channels in
+    /// place. A trailing partial frame is dropped.
+    pub fn reverse(&mut self) {
+        let channels = usize::from(self.channels.max(1));
+        self.samples.truncate(self.samples.len() / channels * channels);
+        self.samples.reverse();
+        for frame in self.samples.chunks_exact_mut(channels) {
+            frame.reverse();
+        }
+    }
+
+    pub fn into_source(self) -> SamplesBuffer<i16> {
+        SamplesBuffer::new(self.channels, self.sample_rate, self.samples)
+    }
+}
+
+/// Scales a float sample to 16 bits. Values beyond full scale are clipped
+/// rather than wrapped around; NaN becomes silence.
+fn float_to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1f);
+    let mantissa = f32::from(bits & 0x3ff);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::{rifx_file, wav_file};
+
+    #[test]
+    fn decodes_wav_chunk() {
+        let body: Vec<u8> = [100i16, -16384, 0, 200].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let pcm = Pcm::decode(Format::Wav, wav_file(2, 8000, &body)).unwrap();
+        assert_eq!(pcm.samples, vec![100, -16384, 0, 200]);
+        assert_eq!(pcm.frames(), 2);
+        assert_eq!(pcm.duration(), Duration::from_micros(250));
+        assert_eq!(pcm.peak(), 0.5);
+        assert!((pcm.rms() - 0.25).abs() < 0.001);
+
+        let body: Vec<u8> = [100i16, -16384, 0, 200].iter().flat_map(|s| s.to_be_bytes()).collect();
+        let pcm = Pcm::decode(Format::Wav, rifx_file(2, 8000, &body)).unwrap();
+        assert_eq!(pcm.samples, vec![100, -16384, 0, 200]);
+    }
+
+    #[test]
+    fn reverses_whole_frames() {
+        let mut pcm = Pcm {
+            channels: 2,
+            sample_rate: 8000,
+            samples: vec![1, -1, 2, -2, 3, -3, 9],
+        };
+        pcm.reverse();
+        assert_eq!(pcm.samples, vec![3, -3, 2, -2, 1, -1]);
+    }
+
+    #[test]
+    fn decodes_extensible_pcm() {
+        let file = crate::wav::tests::extensible_file(1, 16, &[1, 0, 2, 0]);
+        assert_eq!(Pcm::decode(Format::Wav, file).unwrap().samples, vec![1, 2]);
+    }
+
+    fn float_wav(bits: u16, body: &[u8]) -> Vec<u8> {
+        let mut file = wav_file(1, 8000, body);
+        file[20..22].copy_from_slice(&wav::FORMAT_IEEE_FLOAT.to_le_bytes());
+        file[28..32].copy_from_slice(&(8000 * u32::from(bits / 8)).to_le_bytes());
+        file[32..34].copy_from_slice(&(bits / 8).to_le_bytes());
+        file[34..36].copy_from_slice(&bits.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn converts_float_samples() {
+        let floats = [0.0f32, 0.5, -1.0, 1.5, -2.0, f32::NAN];
+        let body: Vec<u8> = floats.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let pcm = Pcm::decode(Format::Wav, float_wav(32, &body)).unwrap();
+        assert_eq!(pcm.samples, vec![0, 16384, -32767, 32767, -32767, 0]);
+        assert_eq!(pcm.duration(), Duration::from_micros(750));
+
+        // Half precision: 0.5, -0.25, 65504 (the largest finite value).
+        let halves = [0x3800u16, 0xb400, 0x7bff];
+        let body: Vec<u8> = halves.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let pcm = Pcm::decode(Format::Wav, float_wav(16, &body)).unwrap();
+        assert_eq!(pcm.samples, vec![16384, -8192, 32767]);
+    }
+}
@@ -0,0 +1,254 @@
+use rodio::buffer::SamplesBuffer;
+use rodio::{Sink, Source};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::BoxedSource;
+
+/// Where decoded chunks are played. Implemented by rodio's [`Sink`]; tests
+/// use [`tests::RecordingOutput`] so the consumer runs without a device.
+pub trait AudioOutput {
+    fn append(&mut self, source: BoxedSource);
+
+    /// Blocks until everything appended has played.
+    fn sleep_until_end(&self);
+
+    /// Whether everything appended has played.
+    fn empty(&self) -> bool;
+
+    /// How many appended sources haven't finished playing.
+    fn queued(&self) -> usize;
+
+    /// Polls [`AudioOutput::queued`] until fewer than `limit` sources are
+    /// waiting, for `--sink-queue-limit`. Returns whether it had to wait.
+    fn wait_for_room(&self, limit: usize, poll: Duration) -> bool {
+        let mut waited = false;
+        while self.queued() >= limit {
+            waited = true;
+            thread::sleep(poll);
+        }
+        waited
+    }
+
+    /// Like [`AudioOutput::sleep_until_end`], but polls [`AudioOutput::empty`]
+    /// so it returns as soon as the queue has drained.
+    fn wait_until_empty(&self, poll: Duration) {
+        while !self.empty() {
+            thread::sleep(poll);
+        }
+    }
+
+    /// Like [`AudioOutput::wait_until_empty`], but gives up after `timeout`.
+    /// Returns whether the queue drained.
+    fn wait_until_empty_within(&self, poll: Duration, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.empty() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(poll);
+        }
+        true
+    }
+}
+
+impl AudioOutput for Sink {
+    fn append(&mut self, source: BoxedSource) {
+        Sink::append(self, source);
+    }
+
+    fn sleep_until_end(&self) {
+        Sink::sleep_until_end(self);
+    }
+
+    fn empty(&self) -> bool {
+        Sink::empty(self)
+    }
+
+    fn queued(&self) -> usize {
+        Sink::len(self)
+    }
+}
+
+/// Wraps an output to keep the durations of the sources still queued on
+/// it, for `--max-queued-duration`. The output only reports how many
+/// sources are left, so those are taken to be the most recently appended,
+/// and the one playing counts in full. Sources of unknown length count as
+/// nothing, so the cap needs chunks whose durations are known, as decoded
+/// chunks' are.
+pub struct Tracked<'a, O> {
+    inner: &'a mut O,
+    /// Without one, nothing is kept.
+    cap: Option<Duration>,
+    durations: VecDeque<Duration>,
+}
+
+impl<'a, O: AudioOutput> Tracked<'a, O> {
+    pub fn new(inner: &'a mut O, cap: Option<Duration>) -> Self {
+        Self {
+            inner,
+            cap,
+            durations: VecDeque::new(),
+        }
+    }
+
+    /// Audio appended but not finished playing.
+    pub fn queued_duration(&mut self) -> Duration {
+        let finished = self.durations.len().saturating_sub(self.inner.queued());
+        self.durations.drain(..finished);
+        self.durations.iter().sum()
+    }
+
+    /// Polls [`Tracked::queued_duration`] until it's below the cap. Returns
+    /// whether it had to wait.
+    pub fn wait_for_duration(&mut self, poll: Duration) -> bool {
+        let Some(cap) = self.cap else {
+            return false;
+        };
+        let mut waited = false;
+        while self.queued_duration() >= cap {
+            waited = true;
+            thread::sleep(poll);
+        }
+        waited
+    }
+}
+
+impl<O: AudioOutput> AudioOutput for Tracked<'_, O> {
+    fn append(&mut self, source: BoxedSource) {
+        if self.cap.is_some() {
+            self.durations.push_back(source.total_duration().unwrap_or_default());
+        }
+        self.inner.append(source);
+    }
+
+    fn sleep_until_end(&self) {
+        self.inner.sleep_until_end();
+    }
+
+    fn empty(&self) -> bool {
+        self.inner.empty()
+    }
+
+    fn queued(&self) -> usize {
+        self.inner.queued()
+    }
+}
+
+/// The outputs of every `--device`. With none, chunks are dropped, which is
+/// how `--dry-run` and `--out` run.
+pub struct Outputs<T> {
+    targets: Vec<T>,
+}
+
+impl<T: AudioOutput> Outputs<T> {
+    pub fn new(targets: Vec<T>) -> Self {
+        Self { targets }
+    }
+}
+
+impl<T: AudioOutput> AudioOutput for Outputs<T> {
+    /// Queues `source` on every target. Sources can't be cloned, so with more
+    /// than one target the chunk is decoded into memory once and each target
+    /// gets its own copy of the samples: one extra buffered chunk per device.
+    fn append(&mut self, source: BoxedSource) {
+        match self.targets.as_mut_slice() {
+            [] => {}
+            [target] => target.append(source),
+            targets => {
+                let (channels, sample_rate) = (source.channels(), source.sample_rate());
+                let samples: Vec<i16> = source.collect();
+                for target in targets {
+                    let copy = SamplesBuffer::new(channels, sample_rate, samples.clone());
+                    target.append(Box::new(copy));
+                }
+            }
+        }
+    }
+
+    fn sleep_until_end(&self) {
+        for target in &self.targets {
+            target.sleep_until_end();
+        }
+    }
+
+    fn empty(&self) -> bool {
+        self.targets.iter().all(T::empty)
+    }
+
+    /// The longest queue: appends go to every target, so the slowest one
+    /// sets the pace.
+    fn queued(&self) -> usize {
+        self.targets.iter().map(T::queued).max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Collects the samples of every appended chunk.
+    #[derive(Debug, Default)]
+    pub(crate) struct RecordingOutput {
+        pub(crate) chunks: Vec<Vec<i16>>,
+    }
+
+    impl AudioOutput for RecordingOutput {
+        fn append(&mut self, source: BoxedSource) {
+            self.chunks.push(source.collect());
+        }
+
+        fn sleep_until_end(&self) {}
+
+        fn empty(&self) -> bool {
+            true
+        }
+
+        fn queued(&self) -> usize {
+            0
+        }
+    }
+
+    fn chunk(samples: &[i16]) -> BoxedSource {
+        Box::new(SamplesBuffer::new(1, 8000, samples.to_vec()))
+    }
+
+    #[test]
+    fn every_chunk_reaches_every_target() {
+        let mut outputs = Outputs::new(vec![RecordingOutput::default(), RecordingOutput::default()]);
+        outputs.append(chunk(&[1, 2]));
+        outputs.append(chunk(&[3]));
+
+        for target in &outputs.targets {
+            assert_eq!(target.chunks, vec![vec![1, 2], vec![3]]);
+        }
+    }
+
+    #[test]
+    fn no_targets_drops_chunks() {
+        let mut outputs: Outputs<RecordingOutput> = Outputs::new(Vec::new());
+        outputs.append(chunk(&[1]));
+        assert!(outputs.targets.is_empty());
+    }
+
+    #[test]
+    fn empty_sink_returns_promptly() {
+        let (sink, _queue) = Sink::new_idle();
+        let start = std::time::Instant::now();
+        Outputs::new(vec![sink]).wait_until_empty(Duration::from_secs(5));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn stuck_sink_gives_up_after_timeout() {
+        // An idle sink is never polled by a device, so it never drains.
+        let (sink, _queue) = Sink::new_idle();
+        sink.append(SamplesBuffer::new(1, 8000, vec![0i16; 8000]));
+        let start = std::time::Instant::now();
+        let drained = Outputs::new(vec![sink]).wait_until_empty_within(Duration::from_millis(5), Duration::from_millis(50));
+        assert!(!drained);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
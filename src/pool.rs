@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+
+/// Spare buffers kept in the pool; buffers given back past this are freed.
+const CAPACITY: usize = 64;
+
+/// Buffers handed back by the consumer, for the reader to decode the next
+/// payloads into instead of allocating a fresh `Vec` per chunk.
+#[derive(Debug)]
+pub struct Pool {
+    // `Mutex` only to make the pool `Sync`, so it can sit in `InputOptions`.
+    rx: Mutex<Receiver<Vec<u8>>>,
+    taken: AtomicUsize,
+    reused: AtomicUsize,
+}
+
+/// The consumer's end of a [`Pool`].
+#[derive(Debug, Clone)]
+pub struct Recycler {
+    tx: SyncSender<Vec<u8>>,
+}
+
+pub fn pool() -> (Pool, Recycler) {
+    let (tx, rx) = mpsc::sync_channel(CAPACITY);
+    let pool = Pool {
+        rx: Mutex::new(rx),
+        taken: AtomicUsize::new(0),
+        reused: AtomicUsize::new(0),
+    };
+    (pool, Recycler { tx })
+}
+
+impl Pool {
+    /// An empty buffer, recycled if one has been given back.
+    pub fn take(&self) -> Vec<u8> {
+        self.taken.fetch_add(1, Ordering::Relaxed);
+        match self.rx.lock().unwrap().try_recv() {
+            Ok(mut buf) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                buf.clear();
+                buf
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Buffers taken so far, and how many of those were recycled.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.taken.load(Ordering::Relaxed), self.reused.load(Ordering::Relaxed))
+    }
+}
+
+impl Recycler {
+    /// Returns a buffer the consumer has finished with.
+    pub fn give(&self, buf: Vec<u8>) {
+        let _ = self.tx.try_send(buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_back_buffers_are_reused() {
+        let (pool, recycler) = pool();
+        assert_eq!(pool.take().capacity(), 0);
+
+        let mut buf = pool.take();
+        buf.extend_from_slice(&[1; 100]);
+        let ptr = buf.as_ptr();
+        recycler.give(buf);
+
+        let buf = pool.take();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(pool.counts(), (3, 1));
+
+        // A full pool frees what it can't hold.
+        for _ in 0..CAPACITY + 1 {
+            recycler.give(Vec::new());
+        }
+        assert_eq!(pool.rx.lock().unwrap().try_iter().count(), CAPACITY);
+    }
+}
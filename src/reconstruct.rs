@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::format::Format;
+use crate::wav;
+
+/// How a WAV stream splits into chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavFraming {
+    /// The first chunk carries the header; later chunks are bare PCM.
+    HeaderOnce,
+    /// Every chunk is a complete WAV file.
+    Files,
+}
+
+/// Turns every WAV chunk into a complete file before it's decoded or
+/// written. Unless forced with `--reconstruct`, the framing is decided on
+/// the second chunk: one that starts with its own `RIFF`/`WAVE` header
+/// means every chunk is a file, otherwise the first chunk's header is put
+/// in front of each.
+#[derive(Debug, Clone, Default)]
+pub struct Reconstructor {
+    framing: Option<WavFraming>,
+    /// The most recent header seen.
+    header: Option<Vec<u8>>,
+    chunks: usize,
+    /// `--header-cache`: where each new header is saved, so a restarted
+    /// player can `--resume` without the stream's header chunk.
+    cache: Option<PathBuf>,
+}
+
+impl Reconstructor {
+    /// `framing` forces the convention instead of detecting it.
+    pub fn new(framing: Option<WavFraming>) -> Self {
+        Self {
+            framing,
+            ..Self::default()
+        }
+    }
+
+    /// Saves every new header to `path`.
+    pub fn with_cache(mut self, path: &Path) -> Self {
+        self.cache = Some(path.to_path_buf());
+        self
+    }
+
+    /// Continues a stream whose header chunk was `header`, as if it had
+    /// just been received; the next chunk decides the framing.
+    pub fn resume(mut self, header: Vec<u8>) -> Self {
+        self.header = Some(header);
+        self.chunks = 1;
+        self
+    }
+
+    pub fn complete(&mut self, index: usize, data: Vec<u8>) -> Vec<u8> {
+        self.chunks += 1;
+        let is_file = Format::detect(&data) == Some(Format::Wav);
+        if self.chunks == 2 && self.framing.is_none() {
+            let framing = if is_file {
+                info!("Audio chunk {} has its own WAV header; treating every chunk as a complete file", index);
+                WavFraming::Files
+            } else {
+                info!("Audio chunk {} has no WAV header; reusing the first chunk's header for the rest", index);
+                WavFraming::HeaderOnce
+            };
+            self.framing = Some(framing);
+        }
+
+        if is_file {
+            // A header re-sent mid-stream replaces the one reused for bare
+            // chunks after it.
+            if let Some((header, _)) = wav::extract_wav_header(&data) {
+                if self.header.as_deref() != Some(header) {
+                    self.save(header);
+                    self.header = Some(header.to_vec());
+                }
+            }
+            return data;
+        }
+        match (self.framing, &self.header) {
+            (Some(WavFraming::HeaderOnce), Some(header)) => wav::reconstruct_wav_file(header, &data),
+            _ => data,
+        }
+    }
+
+    fn save(&self, header: &[u8]) {
+        let Some(path) = &self.cache else {
+            return;
+        };
+        match fs::write(path, header) {
+            Ok(()) => info!("Cached the WAV header in {}", path.display()),
+            Err(e) => warn!("Failed to cache the WAV header in {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// `--align-frames`: carries the trailing partial frame of each WAV chunk
+/// over to the start of the next, for producers that split chunks
+/// mid-frame, so only whole frames are decoded and none are lost at the
+/// boundaries. Frames are `block_align` bytes, from each chunk's header.
+#[derive(Debug, Clone, Default)]
+pub struct FrameAligner {
+    remainder: Vec<u8>,
+    block_align: u16,
+}
+
+impl FrameAligner {
+    pub fn align(&mut self, index: usize, data: Vec<u8>) -> Vec<u8> {
+        let (Some(info), Some((header, body))) = (wav::parse_wav_info(&data), wav::extract_wav_header(&data)) else {
+            return data;
+        };
+        let block = usize::from(info.block_align);
+        if block == 0 {
+            return data;
+        }
+        if info.block_align != self.block_align && !self.remainder.is_empty() {
+            debug!(
+                "Audio chunk {} changes the frame size; dropping {} bytes carried from the previous chunk",
+                index,
+                self.remainder.len()
+            );
+            self.remainder.clear();
+        }
+        self.block_align = info.block_align;
+        if self.remainder.is_empty() && body.len() % block == 0 {
+            return data;
+        }
+
+        let mut aligned = std::mem::take(&mut self.remainder);
+        aligned.extend_from_slice(body);
+        self.remainder = aligned.split_off(aligned.len() / block * block);
+        if !self.remainder.is_empty() {
+            debug!("Carrying {} bytes of a partial frame from audio chunk {} into the next", self.remainder.len(), index);
+        }
+        wav::reconstruct_wav_file(header, &aligned)
+    }
+}
+
+/// Reads a header saved with `--header-cache`, checking it still parses as
+/// a WAV header.
+pub fn load_header(path: &Path) -> Result<Vec<u8>, String> {
+    let header = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    match wav::extract_wav_header(&header) {
+        Some((valid, _)) if Format::detect(&header) == Some(Format::Wav) => Ok(valid.to_vec()),
+        _ => Err(format!("{} doesn't hold a WAV header", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::wav_file;
+
+    #[test]
+    fn reuses_the_first_header_for_bare_chunks() {
+        let mut reconstructor = Reconstructor::new(None);
+        let first = wav_file(1, 8000, &[1, 0, 2, 0]);
+        assert_eq!(reconstructor.complete(1, first.clone()), first);
+        assert_eq!(reconstructor.complete(2, vec![3, 0]), wav_file(1, 8000, &[3, 0]));
+        assert_eq!(reconstructor.framing, Some(WavFraming::HeaderOnce));
+
+        // A re-sent header is used from then on.
+        let resent = wav_file(2, 8000, &[]);
+        assert_eq!(reconstructor.complete(3, resent.clone()), resent);
+        assert_eq!(reconstructor.complete(4, vec![4, 0, 5, 0]), wav_file(2, 8000, &[4, 0, 5, 0]));
+    }
+
+    #[test]
+    fn leaves_complete_files_alone() {
+        let mut reconstructor = Reconstructor::new(None);
+        let chunks = [wav_file(1, 8000, &[1, 0]), wav_file(1, 8000, &[2, 0])];
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(reconstructor.complete(index + 1, chunk.clone()), *chunk);
+        }
+        assert_eq!(reconstructor.framing, Some(WavFraming::Files));
+        // Once decided, bare chunks aren't given a header.
+        assert_eq!(reconstructor.complete(3, vec![3, 0]), vec![3, 0]);
+
+        // Forcing header-once reconstructs even after a full second file.
+        let mut forced = Reconstructor::new(Some(WavFraming::HeaderOnce));
+        forced.complete(1, chunks[0].clone());
+        forced.complete(2, chunks[1].clone());
+        assert_eq!(forced.complete(3, vec![3, 0]), wav_file(1, 8000, &[3, 0]));
+    }
+
+    #[test]
+    fn carries_partial_frames_into_the_next_chunk() {
+        // Stereo 16-bit frames (1, 2), (3, 4), (5, 6), split after 5 and
+        // 7 more bytes.
+        let body: Vec<u8> = [1i16, 2, 3, 4, 5, 6].iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        let mut aligner = FrameAligner::default();
+        let first = aligner.align(1, wav_file(2, 8000, &body[..5]));
+        let second = aligner.align(2, wav_file(2, 8000, &body[5..]));
+
+        let samples = |data| crate::pcm::Pcm::decode(Format::Wav, data).unwrap().samples;
+        assert_eq!(samples(first), vec![1, 2]);
+        assert_eq!(samples(second), vec![3, 4, 5, 6]);
+        assert!(aligner.remainder.is_empty());
+    }
+
+    #[test]
+    fn resumes_from_a_cached_header() {
+        let path = std::env::temp_dir().join(format!("header-cache-{}", std::process::id()));
+        let mut reconstructor = Reconstructor::new(None).with_cache(&path);
+        reconstructor.complete(1, wav_file(2, 8000, &[1, 0, 2, 0]));
+        let header = load_header(&path);
+        std::fs::write(&path, b"not a header").unwrap();
+        let corrupt = load_header(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(corrupt.is_err());
+
+        // A bare chunk straight after a restart gets the cached header.
+        let mut resumed = Reconstructor::new(None).resume(header.unwrap());
+        assert_eq!(resumed.complete(1, vec![3, 0, 4, 0]), wav_file(2, 8000, &[3, 0, 4, 0]));
+    }
+}
@@ -0,0 +1,134 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use rodio::buffer::SamplesBuffer;
+use rodio::Source;
+use std::io::{Seek, Write};
+use tracing::{error, warn};
+
+use crate::playback::AudioOutput;
+use crate::BoxedSource;
+
+/// Writes played samples to a 16-bit PCM WAV file.
+///
+/// The layout is fixed by the first chunk. The header sizes are rewritten
+/// after every chunk, so the file stays playable if the process is killed.
+pub struct WavRecorder<W: Write + Seek> {
+    /// Until the first chunk, the file to write to.
+    out: Option<W>,
+    writer: Option<WavWriter<W>>,
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Some(out),
+            writer: None,
+        }
+    }
+
+    /// Appends one chunk's samples. Returns `false`, writing nothing, when
+    /// its layout differs from the first chunk's.
+    pub fn record(&mut self, channels: u16, sample_rate: u32, samples: &[i16]) -> hound::Result<bool> {
+        let writer = match (self.writer.as_mut(), self.out.take()) {
+            (Some(writer), _) => writer,
+            (None, Some(out)) => {
+                let spec = WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample: 16,
+                    sample_format: SampleFormat::Int,
+                };
+                self.writer.insert(WavWriter::new(out, spec)?)
+            }
+            (None, None) => return Ok(false),
+        };
+        let spec = writer.spec();
+        if (spec.channels, spec.sample_rate) != (channels, sample_rate) {
+            return Ok(false);
+        }
+
+        let mut samples_writer = writer.get_i16_writer(samples.len() as u32);
+        for &sample in samples {
+            samples_writer.write_sample(sample);
+        }
+        samples_writer.flush()?;
+        writer.flush()?;
+        Ok(true)
+    }
+}
+
+/// An [`AudioOutput`] that records everything appended to it before passing
+/// it on, for `--record`. Each chunk is decoded into memory first, since a
+/// source can only be consumed once.
+pub struct Tee<O, W: Write + Seek> {
+    inner: O,
+    recorder: Option<WavRecorder<W>>,
+}
+
+impl<O: AudioOutput, W: Write + Seek> Tee<O, W> {
+    pub fn new(inner: O, recorder: Option<WavRecorder<W>>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<O: AudioOutput, W: Write + Seek> AudioOutput for Tee<O, W> {
+    fn append(&mut self, source: BoxedSource) {
+        let Some(recorder) = self.recorder.as_mut() else {
+            self.inner.append(source);
+            return;
+        };
+
+        let (channels, sample_rate) = (source.channels(), source.sample_rate());
+        let samples: Vec<i16> = source.collect();
+        match recorder.record(channels, sample_rate, &samples) {
+            Ok(true) => {}
+            Ok(false) => warn!(
+                "Not recording a {} channel {} Hz chunk: the recording's layout was set by the first chunk",
+                channels, sample_rate
+            ),
+            Err(e) => {
+                error!("Failed to write recording, stopping it: {}", e);
+                self.recorder = None;
+            }
+        }
+        self.inner.append(Box::new(SamplesBuffer::new(channels, sample_rate, samples)));
+    }
+
+    fn sleep_until_end(&self) {
+        self.inner.sleep_until_end();
+    }
+
+    fn empty(&self) -> bool {
+        self.inner.empty()
+    }
+
+    fn queued(&self) -> usize {
+        self.inner.queued()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playback::tests::RecordingOutput;
+    use crate::wav::tests::wav_file;
+
+    #[test]
+    fn recording_matches_played_audio() {
+        let chunks = [wav_file(1, 8000, &[1, 0, 2, 0]), wav_file(1, 8000, &[3, 0])];
+        let path = std::env::temp_dir().join(format!("jsonl_player-record-test-{}.wav", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut tee = Tee::new(RecordingOutput::default(), Some(WavRecorder::new(file)));
+        for chunk in &chunks {
+            tee.append(Box::new(crate::format::Format::Wav.decoder(chunk.clone()).unwrap()));
+        }
+        tee.append(Box::new(SamplesBuffer::new(2, 8000, vec![9i16; 4])));
+
+        assert_eq!(tee.inner.chunks, vec![vec![1, 2], vec![3], vec![9; 4]]);
+        // Complete as of the last chunk, while still being written.
+        let recorded = std::fs::read(&path).unwrap();
+        drop(tee);
+        std::fs::remove_file(&path).unwrap();
+        // The stereo chunk is played but not recorded.
+        assert_eq!(recorded, wav_file(1, 8000, &[1, 0, 2, 0, 3, 0]));
+    }
+}
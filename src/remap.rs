@@ -0,0 +1,135 @@
+use rodio::Source;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Output channel order for `--remap`: output channel `i` plays input
+/// channel `order[i]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelRemap {
+    order: Vec<u16>,
+}
+
+impl ChannelRemap {
+    /// Checks the order against a chunk's channel count, as read from its
+    /// fmt header.
+    pub fn check(&self, channels: u16) -> Result<(), String> {
+        if self.order.len() != usize::from(channels) {
+            return Err(format!(
+                "--remap lists {} channels but the chunk has {}",
+                self.order.len(),
+                channels
+            ));
+        }
+        match self.order.iter().find(|&&index| index >= channels) {
+            Some(index) => Err(format!("--remap index {} is past the chunk's {} channels", index, channels)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for ChannelRemap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let order = s
+            .split(',')
+            .map(|index| {
+                index
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a channel index", index.trim()))
+            })
+            .collect::<Result<Vec<u16>, _>>()?;
+        Ok(Self { order })
+    }
+}
+
+/// Reorders the channels of each frame, deinterleaving by the source's
+/// channel count. The order must have been [checked](ChannelRemap::check)
+/// against that count.
+pub struct Remap<S> {
+    input: S,
+    order: Vec<u16>,
+    frame: Vec<i16>,
+    index: usize,
+}
+
+impl<S> Remap<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(input: S, remap: &ChannelRemap) -> Result<Self, String> {
+        remap.check(input.channels())?;
+        Ok(Self {
+            input,
+            order: remap.order.clone(),
+            frame: Vec::with_capacity(remap.order.len()),
+            index: 0,
+        })
+    }
+}
+
+impl<S> Iterator for Remap<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.index == self.frame.len() {
+            self.frame.clear();
+            self.frame.extend(self.input.by_ref().take(self.order.len()));
+            self.index = 0;
+            // A trailing partial frame has nothing to map into; drop it.
+            if self.frame.len() < self.order.len() {
+                return None;
+            }
+        }
+        let sample = self.frame[usize::from(self.order[self.index])];
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for Remap<S>
+where
+    S: Source<Item = i16>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn swaps_left_and_right() {
+        let remap: ChannelRemap = "1,0".parse().unwrap();
+        let source = SamplesBuffer::new(2, 8000, vec![100, -200, 300, -400]);
+        let swapped: Vec<i16> = Remap::new(source, &remap).unwrap().collect();
+        assert_eq!(swapped, vec![-200, 100, -400, 300]);
+    }
+
+    #[test]
+    fn rejects_orders_that_dont_fit_the_chunk() {
+        let remap: ChannelRemap = "1,0".parse().unwrap();
+        assert!(Remap::new(SamplesBuffer::new(1, 8000, vec![0i16]), &remap).is_err());
+        assert!("0,2".parse::<ChannelRemap>().unwrap().check(2).is_err());
+        assert!("0,x".parse::<ChannelRemap>().is_err());
+    }
+}
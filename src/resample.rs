@@ -0,0 +1,277 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use tracing::debug;
+
+use crate::pcm::Pcm;
+
+/// `--resample-quality`: how chunks are converted to the device's rate.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    /// Linear interpolation, as rodio does.
+    Fast,
+    /// Cubic (Catmull-Rom) interpolation.
+    #[default]
+    Medium,
+    /// Windowed sinc, low-passed when downsampling so it doesn't alias.
+    High,
+}
+
+impl Quality {
+    pub const NAMES: [&'static str; 3] = ["fast", "medium", "high"];
+
+    fn method(self) -> &'static str {
+        match self {
+            Quality::Fast => "linear",
+            Quality::Medium => "cubic",
+            Quality::High => "windowed sinc",
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Quality::Fast),
+            "medium" => Ok(Quality::Medium),
+            "high" => Ok(Quality::High),
+            other => Err(format!("unknown resample quality `{}`", other)),
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", Quality::NAMES[*self as usize], self.method())
+    }
+}
+
+/// Taps on each side of the sinc kernel.
+const SINC_HALF_WIDTH: i64 = 16;
+
+/// Converts decoded chunks to the output device's sample rate before
+/// they're queued, so rodio doesn't fall back to its linear conversion.
+/// Chunks are converted as one stream: the position between input frames
+/// and the frames the kernel still reaches back to carry over from one
+/// chunk to the next, and the last few frames of each chunk are held back
+/// until the next one arrives or [`flush`](Self::flush) is called. A
+/// stream split into chunks comes out as if it had been converted whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resampler {
+    pub rate: u32,
+    pub quality: Quality,
+    /// Channels and rate of the stream being converted.
+    layout: Option<(u16, u32)>,
+    /// Interleaved input not yet done with, from frame `base` of the stream.
+    held: Vec<i16>,
+    base: u64,
+    /// Frames written so far.
+    produced: u64,
+}
+
+impl Resampler {
+    pub fn new(rate: u32, quality: Quality) -> Self {
+        Self {
+            rate,
+            quality,
+            layout: None,
+            held: Vec::new(),
+            base: 0,
+            produced: 0,
+        }
+    }
+
+    /// `pcm` at the target rate, as far as it can be converted before the
+    /// next chunk is seen. Chunks already at the rate are returned as is. A
+    /// chunk in another layout than the last starts a new stream, dropping
+    /// what was held back of the old one.
+    pub fn apply(&mut self, pcm: Pcm) -> Pcm {
+        if pcm.sample_rate == self.rate || pcm.sample_rate == 0 || pcm.channels == 0 {
+            self.reset(None);
+            return pcm;
+        }
+        let layout = (pcm.channels, pcm.sample_rate);
+        if self.layout != Some(layout) {
+            if !self.held.is_empty() {
+                debug!("Chunk layout changed, dropping the {} samples held back for resampling", self.held.len());
+            }
+            self.reset(Some(layout));
+        }
+        self.held.extend(pcm.samples);
+        self.convert(false)
+    }
+
+    /// The end of the stream held back by [`apply`](Self::apply), if any.
+    pub fn flush(&mut self) -> Option<Pcm> {
+        self.layout?;
+        let pcm = self.convert(true);
+        self.reset(None);
+        (!pcm.samples.is_empty()).then_some(pcm)
+    }
+
+    fn reset(&mut self, layout: Option<(u16, u32)>) {
+        self.layout = layout;
+        self.held.clear();
+        self.base = 0;
+        self.produced = 0;
+    }
+
+    /// Writes every frame whose kernel the held input covers, or with
+    /// `end`, the rest of the stream with its last frame held.
+    fn convert(&mut self, end: bool) -> Pcm {
+        let (channels, sample_rate) = self.layout.unwrap();
+        let (width, rate) = (usize::from(channels), u64::from(sample_rate));
+        let received = self.base + (self.held.len() / width) as u64;
+        let total = (received * u64::from(self.rate) + rate / 2) / rate;
+        let step = f64::from(sample_rate) / f64::from(self.rate);
+        // Below 1 when downsampling: the sinc's cut-off, as a fraction of
+        // the input's Nyquist frequency.
+        let cutoff = (f64::from(self.rate) / f64::from(sample_rate)).min(1.0);
+        // Frames the kernel reads before and after the one at or below
+        // the position.
+        let (before, after) = match self.quality {
+            Quality::Fast => (0, 1),
+            Quality::Medium => (1, 2),
+            Quality::High => (SINC_HALF_WIDTH as u64 - 1, SINC_HALF_WIDTH as u64),
+        };
+
+        let mut samples = Vec::new();
+        while self.produced < total {
+            let position = self.produced as f64 * step;
+            if !end && position.floor() as u64 + after >= received {
+                break;
+            }
+            for channel in 0..width {
+                let at = |index: i64| {
+                    let index = (index.clamp(0, received as i64 - 1) as u64 - self.base) as usize;
+                    f64::from(self.held[index * width + channel])
+                };
+                let value = match self.quality {
+                    Quality::Fast => linear(at, position),
+                    Quality::Medium => cubic(at, position),
+                    Quality::High => sinc(at, position, cutoff),
+                };
+                samples.push(value.round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16);
+            }
+            self.produced += 1;
+        }
+
+        // Keep only what the next frame's kernel reaches back to.
+        let next = (self.produced as f64 * step).floor() as u64;
+        let keep_from = next.saturating_sub(before).min(received);
+        if keep_from > self.base {
+            self.held.drain(..(keep_from - self.base) as usize * width);
+            self.base = keep_from;
+        }
+        Pcm {
+            channels,
+            sample_rate: self.rate,
+            samples,
+        }
+    }
+}
+
+fn linear(at: impl Fn(i64) -> f64, position: f64) -> f64 {
+    let index = position.floor() as i64;
+    let frac = position - position.floor();
+    at(index) + (at(index + 1) - at(index)) * frac
+}
+
+fn cubic(at: impl Fn(i64) -> f64, position: f64) -> f64 {
+    let index = position.floor() as i64;
+    let t = position - position.floor();
+    let (p0, p1, p2, p3) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+    p1 + 0.5
+        * t
+        * (p2 - p0 + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3 + t * (3.0 * (p1 - p2) + p3 - p0)))
+}
+
+fn sinc(at: impl Fn(i64) -> f64, position: f64, cutoff: f64) -> f64 {
+    let center = position.floor() as i64;
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for index in center - SINC_HALF_WIDTH + 1..=center + SINC_HALF_WIDTH {
+        let x = position - index as f64;
+        let t = cutoff * x;
+        let sinc = if t.abs() < 1e-9 { 1.0 } else { (PI * t).sin() / (PI * t) };
+        // Blackman window over the kernel's width.
+        let w = 0.5 + 0.5 * x / SINC_HALF_WIDTH as f64;
+        let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+        let weight = cutoff * sinc * window;
+        sum += at(index) * weight;
+        weights += weight;
+    }
+    // Normalised so the kernel passes DC unchanged.
+    if weights.abs() < 1e-9 {
+        0.0
+    } else {
+        sum / weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `pcm` converted as a stream of its own.
+    fn convert(rate: u32, quality: Quality, pcm: Pcm) -> Pcm {
+        let mut resampler = Resampler::new(rate, quality);
+        let mut resampled = resampler.apply(pcm);
+        if let Some(tail) = resampler.flush() {
+            resampled.samples.extend(tail.samples);
+        }
+        resampled
+    }
+
+    #[test]
+    fn every_quality_converts_to_the_same_length() {
+        // 10 ms of stereo 44.1 kHz becomes 10 ms at 48 kHz.
+        let pcm = Pcm {
+            channels: 2,
+            sample_rate: 44100,
+            samples: (0..882).map(|i| ((i / 2) as i16 * 50) - 11000).collect(),
+        };
+        for quality in [Quality::Fast, Quality::Medium, Quality::High] {
+            let resampled = convert(48000, quality, pcm.clone());
+            assert_eq!(resampled.sample_rate, 48000);
+            assert_eq!(resampled.frames(), 480, "{}", quality);
+            // A constant signal stays constant.
+            let flat = Pcm {
+                samples: vec![1000; 882],
+                ..pcm.clone()
+            };
+            let flat = convert(22050, quality, flat);
+            assert_eq!(flat.frames(), 221);
+            assert!(flat.samples.iter().all(|&sample| sample == 1000), "{}", quality);
+        }
+    }
+
+    #[test]
+    fn chunks_convert_like_the_whole_stream() {
+        // 20 ms of a stereo 1 kHz sine at 44.1 kHz.
+        let samples: Vec<i16> = (0..882)
+            .flat_map(|frame| {
+                let value = ((frame as f64 * 2.0 * PI * 1000.0 / 44100.0).sin() * 12000.0) as i16;
+                [value, -value]
+            })
+            .collect();
+        let pcm = |samples: &[i16]| Pcm {
+            channels: 2,
+            sample_rate: 44100,
+            samples: samples.to_vec(),
+        };
+        for quality in [Quality::Fast, Quality::Medium, Quality::High] {
+            let whole = convert(48000, quality, pcm(&samples));
+
+            let mut resampler = Resampler::new(48000, quality);
+            let mut split = Vec::new();
+            for part in [&samples[..450], &samples[450..1000], &samples[1000..]] {
+                split.extend(resampler.apply(pcm(part)).samples);
+            }
+            split.extend(resampler.flush().unwrap().samples);
+            assert_eq!(split, whole.samples, "{}", quality);
+            assert_eq!(resampler.flush(), None);
+        }
+    }
+}
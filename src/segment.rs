@@ -0,0 +1,162 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+use crate::format::Format;
+use crate::output::{AudioWriter, OutputFormat};
+use crate::wav;
+
+type FileWriter = AudioWriter<BufWriter<File>>;
+
+/// Where `--out` goes: one file, or with `--out-segment` a numbered series.
+pub enum Destination {
+    File(FileWriter),
+    Segments(SegmentWriter),
+}
+
+impl Destination {
+    pub fn write_chunk(&mut self, format: Format, data: Vec<u8>) -> Result<(), String> {
+        match self {
+            Destination::File(writer) => writer.write_chunk(format, data),
+            Destination::Segments(segments) => segments.write_chunk(format, data),
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Destination::File(writer) => writer.finish().map(drop),
+            Destination::Segments(segments) => segments.finish(),
+        }
+    }
+}
+
+/// Writes WAV chunks into consecutive files of `length` each, named after
+/// the `--out` path with a `_000`, `_001`, ... suffix. Boundaries come from
+/// the fmt header's byte rate; a chunk straddling one is split between the
+/// two files.
+pub struct SegmentWriter {
+    path: PathBuf,
+    length: Duration,
+    /// Applies the `--out` options to each new file's writer.
+    configure: Box<dyn Fn(FileWriter) -> FileWriter + Send>,
+    current: Option<FileWriter>,
+    /// Segments opened so far.
+    count: usize,
+    /// PCM bytes in the current segment.
+    written: u64,
+}
+
+impl SegmentWriter {
+    pub fn new(path: &Path, length: Duration, configure: impl Fn(FileWriter) -> FileWriter + Send + 'static) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            length,
+            configure: Box::new(configure),
+            current: None,
+            count: 0,
+            written: 0,
+        }
+    }
+
+    pub fn write_chunk(&mut self, format: Format, data: Vec<u8>) -> Result<(), String> {
+        let (Some(info), Some((header, body))) = (wav::parse_wav_info(&data), wav::extract_wav_header(&data)) else {
+            return Err("--out-segment needs WAV chunks".to_string());
+        };
+        let frame = u64::from(info.channels) * u64::from(info.bits_per_sample / 8);
+        let frames = (self.length.as_secs_f64() * f64::from(info.byte_rate)) as u64 / frame.max(1);
+        let segment_bytes = (frames * frame).max(frame).max(1);
+
+        let len = body.len() as u64;
+        if self.written + len <= segment_bytes {
+            self.writer()?.write_chunk(format, data)?;
+            self.written += len;
+        } else {
+            let mut rest = body;
+            while !rest.is_empty() {
+                let room = usize::try_from(segment_bytes - self.written).unwrap_or(usize::MAX);
+                let (part, tail) = rest.split_at(room.min(rest.len()));
+                self.writer()?.write_chunk(format, wav::reconstruct_wav_file(header, part))?;
+                self.written += part.len() as u64;
+                rest = tail;
+                if self.written == segment_bytes {
+                    self.close().map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        if self.written == segment_bytes {
+            self.close().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    /// The current segment's writer, opening the next file if there is none.
+    fn writer(&mut self) -> Result<&mut FileWriter, String> {
+        if self.current.is_none() {
+            let path = segment_path(&self.path, self.count);
+            let file = File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            info!("Writing segment {} to {}", self.count, path.display());
+            self.current = Some((self.configure)(AudioWriter::new(BufWriter::new(file), OutputFormat::Wav)));
+            self.count += 1;
+        }
+        Ok(self.current.as_mut().unwrap())
+    }
+
+    /// Patches the current segment's sizes and closes it.
+    fn close(&mut self) -> io::Result<()> {
+        self.written = 0;
+        match self.current.take() {
+            Some(writer) => writer.finish().map(drop),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `out.wav` becomes `out_000.wav`, `out_001.wav`, ...
+fn segment_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:03}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}_{:03}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::wav_file;
+
+    #[test]
+    fn names_segments_after_the_out_path() {
+        assert_eq!(segment_path(Path::new("rec/out.wav"), 0), Path::new("rec/out_000.wav"));
+        assert_eq!(segment_path(Path::new("out"), 12), Path::new("out_012"));
+    }
+
+    #[test]
+    fn splits_a_stream_into_segments() {
+        let dir = std::env::temp_dir().join(format!("out-segment-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.wav");
+
+        // 4 Hz mono 16-bit is 8 bytes a second; the second chunk straddles
+        // the first boundary.
+        let mut segments = SegmentWriter::new(&path, Duration::from_secs(1), |writer| writer);
+        segments.write_chunk(Format::Wav, wav_file(1, 4, &[1, 0, 2, 0, 3, 0])).unwrap();
+        segments.write_chunk(Format::Wav, wav_file(1, 4, &[4, 0, 5, 0, 6, 0])).unwrap();
+        segments.finish().unwrap();
+
+        let first = std::fs::read(dir.join("out_000.wav")).unwrap();
+        let second = std::fs::read(dir.join("out_001.wav")).unwrap();
+        assert!(!dir.join("out_002.wav").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first, wav_file(1, 4, &[1, 0, 2, 0, 3, 0, 4, 0]));
+        assert_eq!(second, wav_file(1, 4, &[5, 0, 6, 0]));
+    }
+}
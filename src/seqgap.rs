@@ -0,0 +1,78 @@
+use std::time::Duration;
+use tracing::warn;
+
+/// Tracks the records' `seq` fields for `--conceal-gaps`, estimating how
+/// much audio went missing when the sequence skips ahead. Missing chunks
+/// are assumed to be as long as the average chunk received so far.
+#[derive(Debug, Default)]
+pub struct GapConcealer {
+    /// Highest sequence number seen.
+    last_seq: Option<u64>,
+    /// Total duration and count of the chunks decoded so far.
+    received: Duration,
+    chunks: u32,
+}
+
+impl GapConcealer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many chunks are missing before chunk `index` numbered `seq`.
+    /// Chunks without a `seq`, or numbered at or below one already seen,
+    /// don't count as gaps.
+    pub fn missing(&mut self, index: usize, seq: Option<u64>) -> u64 {
+        let Some(seq) = seq else {
+            return 0;
+        };
+        let missing = match self.last_seq {
+            Some(last) if seq > last => seq - last - 1,
+            Some(last) => {
+                warn!("Audio chunk {} has seq {}, not after the previous {}", index, seq, last);
+                return 0;
+            }
+            None => 0,
+        };
+        self.last_seq = Some(seq);
+        missing
+    }
+
+    /// Adds a decoded chunk to the average.
+    pub fn record(&mut self, duration: Duration) {
+        self.received += duration;
+        self.chunks += 1;
+    }
+
+    /// The silence standing in for `missing` chunks, or `None` if there is
+    /// nothing missing or no chunk to estimate their length from yet.
+    pub fn silence_for(&self, missing: u64) -> Option<Duration> {
+        if missing == 0 || self.chunks == 0 {
+            return None;
+        }
+        let average = self.received / self.chunks;
+        Some(average.saturating_mul(u32::try_from(missing).unwrap_or(u32::MAX)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_missing_audio_from_the_average_chunk() {
+        let mut gaps = GapConcealer::new();
+        assert_eq!(gaps.missing(1, Some(4)), 0);
+        gaps.record(Duration::from_millis(100));
+        assert_eq!(gaps.missing(2, Some(5)), 0);
+        gaps.record(Duration::from_millis(300));
+        assert_eq!(gaps.missing(3, Some(8)), 2);
+        assert_eq!(gaps.silence_for(2), Some(Duration::from_millis(400)));
+
+        // Unnumbered, repeated and reordered chunks aren't gaps.
+        assert_eq!(gaps.missing(4, None), 0);
+        assert_eq!(gaps.missing(5, Some(8)), 0);
+        assert_eq!(gaps.missing(6, Some(6)), 0);
+        assert_eq!(gaps.missing(7, Some(9)), 0);
+        assert_eq!(gaps.silence_for(0), None);
+    }
+}
@@ -0,0 +1,250 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Mutex, TryLockError};
+use std::thread;
+use tracing::{info, warn};
+
+use crate::consumer::Consumer;
+use crate::input::{self, InputOptions};
+use crate::playback::AudioOutput;
+
+/// Longest request line or header accepted.
+const MAX_HEADER_LINE: usize = 8192;
+
+/// Serves `--serve`: each POSTed body is read as input and played through
+/// `output` with the settings of `consumer`, and answered with the run's
+/// stats as JSON once it has finished playing. Only one body plays at a
+/// time; requests arriving meanwhile get a 409.
+pub fn run<A, O>(addr: A, consumer: Consumer, output: &mut O, options: &InputOptions) -> io::Result<()>
+where
+    A: ToSocketAddrs,
+    O: AudioOutput + Send,
+{
+    let listener = TcpListener::bind(addr)?;
+    info!("Accepting POSTed input on http://{}", listener.local_addr()?);
+    serve(listener, consumer, output, options);
+    Ok(())
+}
+
+fn serve<O: AudioOutput + Send>(listener: TcpListener, consumer: Consumer, output: &mut O, options: &InputOptions) {
+    let player = Mutex::new((consumer, output));
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let player = &player;
+                    scope.spawn(move || {
+                        if let Err(e) = handle(stream, player, options) {
+                            warn!("Failed to answer a --serve request: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept a --serve connection: {}", e),
+            }
+        }
+    });
+}
+
+fn handle<O: AudioOutput + Send>(
+    stream: TcpStream,
+    player: &Mutex<(Consumer, &mut O)>,
+    options: &InputOptions,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let request = read_line(&mut reader)?;
+    let method = request.split(' ').next().unwrap_or_default().to_string();
+
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut expect_continue = false;
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return respond(&mut writer, "400 Bad Request", &error_body("malformed header"));
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => match value.parse::<u64>() {
+                Ok(length) => content_length = Some(length),
+                Err(_) => return respond(&mut writer, "400 Bad Request", &error_body("malformed Content-Length")),
+            },
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+
+    if method != "POST" {
+        return respond(&mut writer, "405 Method Not Allowed", &error_body("POST the input to play"));
+    }
+    if content_length.is_none() && !chunked {
+        return respond(&mut writer, "411 Length Required", &error_body("send a Content-Length or a chunked body"));
+    }
+    let mut guard = match player.try_lock() {
+        Ok(guard) => guard,
+        Err(TryLockError::WouldBlock) => {
+            return respond(&mut writer, "409 Conflict", &error_body("another request is already playing"));
+        }
+        Err(TryLockError::Poisoned(_)) => {
+            return respond(&mut writer, "500 Internal Server Error", &error_body("playback failed earlier"));
+        }
+    };
+    if expect_continue {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+
+    let body: Box<dyn BufRead> = if chunked {
+        Box::new(BufReader::new(Chunked::new(reader)))
+    } else {
+        Box::new(reader.take(content_length.unwrap_or(0)))
+    };
+    info!("Playing a POSTed stream");
+    let (consumer, output) = &mut *guard;
+    let consumer = consumer.for_next_run();
+    let (stats, decodes) = thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        let playing = scope.spawn(move || consumer.run(rx, &mut **output));
+        let stats = input::read_input(body, &tx, options);
+        drop(tx);
+        (stats, playing.join().unwrap_or_default())
+    });
+    info!("POSTed stream played: {} chunks decoded", stats.successful_decode_count);
+
+    let summary = serde_json::json!({ "input": stats, "decodes": decodes });
+    respond(&mut writer, "200 OK", &summary.to_string())
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_HEADER_LINE as u64).read_line(&mut line)?;
+    if !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "request header cut short or too long"));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn respond<W: Write>(writer: &mut W, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+/// Decodes a `Transfer-Encoding: chunked` body. Chunk extensions and
+/// trailers are ignored.
+struct Chunked<R> {
+    inner: R,
+    /// Bytes left in the current chunk.
+    remaining: u64,
+    done: bool,
+}
+
+impl<R: BufRead> Chunked<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    /// Reads the next chunk's size line, and the trailers after the last.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let line = read_line(&mut self.inner)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        self.remaining = u64::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("bad chunk size `{}`", size)))?;
+        if self.remaining == 0 {
+            self.done = true;
+            while !read_line(&mut self.inner)?.is_empty() {}
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for Chunked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            self.next_chunk()?;
+            if self.done {
+                return Ok(0);
+            }
+        }
+        let len = usize::try_from(self.remaining).unwrap_or(usize::MAX).min(buf.len());
+        let read = self.inner.read(&mut buf[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= read as u64;
+        if self.remaining == 0 {
+            // The CRLF closing the chunk.
+            read_line(&mut self.inner)?;
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consumer::tests::consumer;
+    use crate::playback::tests::RecordingOutput;
+    use crate::wav::tests::wav_file;
+    use base64::Engine;
+
+    fn request(addr: std::net::SocketAddr, request: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn plays_posted_streams() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, consumer(), &mut RecordingOutput::default(), &InputOptions::default()));
+
+        let encode = |body: &[u8]| base64::engine::general_purpose::STANDARD.encode(wav_file(1, 8000, body));
+        let body = format!("{{\"data\":\"{}\"}}\n{{\"data\":\"{}\"}}\n", encode(&[1, 0]), encode(&[2, 0]));
+        let response = request(
+            addr,
+            format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}", body.len(), body).as_bytes(),
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        let summary: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(summary["input"]["successful_decode_count"], 2);
+        assert_eq!(summary["decodes"]["attempted"], 2);
+
+        // The same stream, streamed in two HTTP chunks.
+        let (first, second) = body.split_at(10);
+        let chunked = format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            first.len(),
+            first,
+            second.len(),
+            second
+        );
+        let response = request(addr, chunked.as_bytes());
+        assert!(response.contains("\"successful_decode_count\":2"), "{}", response);
+
+        let response = request(addr, b"GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+    }
+}
@@ -0,0 +1,116 @@
+use rodio::buffer::SamplesBuffer;
+use std::time::Duration;
+
+use crate::wav::WavInfo;
+
+/// Layout used for generated silence when nothing better is known yet.
+pub const DEFAULT_CHANNELS: u16 = 2;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// A stretch of generated silence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Silence {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub duration: Duration,
+}
+
+impl Silence {
+    /// Number of frames covering the duration, rounded to the nearest frame.
+    pub fn frames(self) -> usize {
+        (self.duration.as_secs_f64() * self.sample_rate as f64).round() as usize
+    }
+
+    pub fn source(self) -> SamplesBuffer<i16> {
+        let samples = vec![0; self.frames() * self.channels as usize];
+        SamplesBuffer::new(self.channels, self.sample_rate, samples)
+    }
+}
+
+/// Silence standing in for a chunk that failed to decode. A parseable WAV
+/// header gives the exact duration; otherwise `fallback` is used with the
+/// layout of the last chunk that played.
+pub fn replacement_for(
+    wav_info: Option<WavInfo>,
+    last_layout: Option<(u16, u32)>,
+    fallback: Duration,
+) -> Silence {
+    if let Some(info) = wav_info.filter(|info| info.channels > 0 && info.sample_rate > 0) {
+        return Silence {
+            channels: info.channels,
+            sample_rate: info.sample_rate,
+            duration: info.duration(),
+        };
+    }
+    let (channels, sample_rate) = last_layout.unwrap_or((DEFAULT_CHANNELS, DEFAULT_SAMPLE_RATE));
+    Silence {
+        channels,
+        sample_rate,
+        duration: fallback,
+    }
+}
+
+/// Silence queued ahead of the first chunk so a device that's still starting
+/// up clips it instead of the audio. The WAV header supplies the layout when
+/// there is one.
+pub fn warmup(wav_info: Option<WavInfo>, duration: Duration) -> Silence {
+    let (channels, sample_rate) = wav_info
+        .filter(|info| info.channels > 0 && info.sample_rate > 0)
+        .map_or((DEFAULT_CHANNELS, DEFAULT_SAMPLE_RATE), |info| (info.channels, info.sample_rate));
+    Silence {
+        channels,
+        sample_rate,
+        duration,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use crate::wav::{parse_wav_info, tests::wav_file};
+
+    #[test]
+    fn corrupt_wav_chunk_is_replaced_by_equal_silence() {
+        // A header announcing an unsupported codec: parseable, but undecodable.
+        let mut chunk = wav_file(2, 8000, &[0x55; 3200]);
+        chunk[20..22].copy_from_slice(&0x1234u16.to_le_bytes());
+        let info = parse_wav_info(&chunk);
+        assert!(Format::Wav.decoder(chunk).is_err());
+
+        let silence = replacement_for(info, None, Duration::from_millis(250));
+        assert_eq!(
+            silence,
+            Silence {
+                channels: 2,
+                sample_rate: 8000,
+                duration: Duration::from_millis(100),
+            }
+        );
+        let samples: Vec<i16> = silence.source().collect();
+        assert_eq!(samples.len(), 1600);
+        assert!(samples.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn compressed_chunk_uses_fixed_silence() {
+        let silence = replacement_for(None, Some((1, 22050)), Duration::from_millis(250));
+        assert_eq!(silence.channels, 1);
+        assert_eq!(silence.sample_rate, 22050);
+        assert_eq!(silence.duration, Duration::from_millis(250));
+
+        let silence = replacement_for(None, None, Duration::from_millis(250));
+        assert_eq!((silence.channels, silence.sample_rate), (2, 44100));
+    }
+
+    #[test]
+    fn warmup_follows_wav_header() {
+        let info = parse_wav_info(&wav_file(1, 8000, &[0; 16]));
+        let silence = warmup(info, Duration::from_millis(50));
+        assert_eq!((silence.channels, silence.sample_rate), (1, 8000));
+        assert_eq!(silence.source().count(), 400);
+
+        let silence = warmup(None, Duration::from_millis(50));
+        assert_eq!((silence.channels, silence.sample_rate), (2, 44100));
+    }
+}
@@ -0,0 +1,406 @@
+use rodio::Source;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::pcm::Pcm;
+use crate::BoxedSource;
+
+/// Frames read back from a spill file at a time.
+const READ_BLOCK_FRAMES: usize = 4096;
+
+/// Tells the spill files of one process apart.
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The whole stream buffered by `--reverse` and `--loop`: in memory, or
+/// with `--spill-to-disk` in a temp file so a long capture doesn't have
+/// to fit in RAM. Every chunk must share the first one's layout.
+pub enum WholeStream {
+    Memory(Pcm),
+    Disk(Spill),
+}
+
+impl WholeStream {
+    pub fn channels(&self) -> u16 {
+        match self {
+            WholeStream::Memory(pcm) => pcm.channels,
+            WholeStream::Disk(spill) => spill.channels,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        match self {
+            WholeStream::Memory(pcm) => pcm.sample_rate,
+            WholeStream::Disk(spill) => spill.sample_rate,
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        match self {
+            WholeStream::Memory(pcm) => pcm.frames(),
+            WholeStream::Disk(spill) => spill.samples / usize::from(spill.channels.max(1)),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        frames_duration(self.frames(), self.sample_rate())
+    }
+
+    pub fn extend(&mut self, source: BoxedSource) -> io::Result<()> {
+        match self {
+            WholeStream::Memory(pcm) => {
+                pcm.samples.extend(source);
+                Ok(())
+            }
+            WholeStream::Disk(spill) => spill.write(source),
+        }
+    }
+
+    /// The buffer played backwards, frame by frame.
+    pub fn into_reversed(self) -> io::Result<BoxedSource> {
+        match self {
+            WholeStream::Memory(mut pcm) => {
+                pcm.reverse();
+                Ok(Box::new(pcm.into_source()))
+            }
+            WholeStream::Disk(spill) => {
+                let frames = spill.samples / usize::from(spill.channels.max(1));
+                Ok(Box::new(Reversed::new(spill.finish()?, frames)))
+            }
+        }
+    }
+
+    /// Frames before `start` to play once, and frames `start..=end` to play
+    /// on repeat.
+    pub fn into_loop(self, start: usize, end: usize) -> io::Result<(BoxedSource, BoxedSource)> {
+        match self {
+            WholeStream::Memory(pcm) => {
+                let width = usize::from(pcm.channels.max(1));
+                let lead_in = pcm.samples[..start * width].to_vec();
+                let looped = pcm.samples[start * width..(end + 1) * width].to_vec();
+                let (channels, sample_rate) = (pcm.channels, pcm.sample_rate);
+                Ok((
+                    Box::new(Pcm { channels, sample_rate, samples: lead_in }.into_source()),
+                    Box::new(Pcm { channels, sample_rate, samples: looped }.into_source().repeat_infinite()),
+                ))
+            }
+            WholeStream::Disk(spill) => {
+                let file = spill.finish()?;
+                Ok((
+                    Box::new(Forward::new(Arc::clone(&file), 0, start, false)),
+                    Box::new(Forward::new(file, start, end + 1, true)),
+                ))
+            }
+        }
+    }
+}
+
+fn frames_duration(frames: usize, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(frames as f64 / f64::from(sample_rate))
+}
+
+/// A temp file of 16-bit little-endian samples. On Unix it's unlinked as
+/// soon as it's created, so nothing is left behind however the player
+/// exits; elsewhere it's removed once the last reader is dropped.
+struct SpillFile {
+    file: File,
+    path: PathBuf,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => debug!("Removed spill file {}", self.path.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove spill file {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+impl SpillFile {
+    /// Reads samples from `sample` on into `buf`, returning how many were
+    /// read. Reads are positional, so readers don't share a file offset.
+    fn read_samples(&self, sample: usize, buf: &mut [i16]) -> io::Result<usize> {
+        let mut bytes = vec![0u8; buf.len() * 2];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            let read = read_at(&self.file, &mut bytes[filled..], (sample * 2 + filled) as u64)?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        for (sample, pair) in buf.iter_mut().zip(bytes[..filled].chunks_exact(2)) {
+            *sample = i16::from_le_bytes([pair[0], pair[1]]);
+        }
+        Ok(filled / 2)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+/// `--spill-to-disk`: the stream written out as it's decoded.
+pub struct Spill {
+    file: Arc<SpillFile>,
+    writer: BufWriter<File>,
+    channels: u16,
+    sample_rate: u32,
+    samples: usize,
+}
+
+impl Spill {
+    /// Creates a spill file in `dir` for audio in this layout.
+    pub fn create(dir: &Path, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let name = format!(
+            "jsonl_player-spill-{}-{}.pcm",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        let writer = BufWriter::new(file.try_clone()?);
+        debug!("Spilling buffered audio to {}", path.display());
+        let file = SpillFile {
+            file,
+            path,
+            channels,
+            sample_rate,
+        };
+        #[cfg(unix)]
+        fs::remove_file(&file.path)?;
+        Ok(Self {
+            file: Arc::new(file),
+            writer,
+            channels,
+            sample_rate,
+            samples: 0,
+        })
+    }
+
+    /// Where the spill file was created.
+    #[cfg(test)]
+    fn path(&self) -> &Path {
+        &self.file.path
+    }
+
+    /// Bytes written to the file so far.
+    #[cfg(test)]
+    fn len(&mut self) -> io::Result<u64> {
+        self.writer.flush()?;
+        Ok(self.file.file.metadata()?.len())
+    }
+
+    fn write(&mut self, source: BoxedSource) -> io::Result<()> {
+        for sample in source {
+            self.writer.write_all(&sample.to_le_bytes())?;
+            self.samples += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<Arc<SpillFile>> {
+        self.writer.flush()?;
+        Ok(self.file)
+    }
+}
+
+/// Logs a failed read and ends the source, as there's no way to report it.
+fn read_failed(e: io::Error) -> usize {
+    warn!("Failed to read back spilled audio: {}", e);
+    0
+}
+
+/// Frames `start..end` of a spill file, once or on repeat.
+struct Forward {
+    file: Arc<SpillFile>,
+    start: usize,
+    end: usize,
+    repeat: bool,
+    /// The next sample, and a block read ahead of it.
+    pos: usize,
+    block: Vec<i16>,
+    index: usize,
+}
+
+impl Forward {
+    fn new(file: Arc<SpillFile>, start: usize, end: usize, repeat: bool) -> Self {
+        let width = usize::from(file.channels.max(1));
+        Self {
+            file,
+            start: start * width,
+            end: end * width,
+            repeat,
+            pos: start * width,
+            block: Vec::new(),
+            index: 0,
+        }
+    }
+}
+
+impl Iterator for Forward {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.index == self.block.len() {
+            if self.pos == self.end && self.repeat && self.start < self.end {
+                self.pos = self.start;
+            }
+            let len = (self.end - self.pos).min(READ_BLOCK_FRAMES * usize::from(self.file.channels.max(1)));
+            self.block.resize(len, 0);
+            let read = self.file.read_samples(self.pos, &mut self.block).unwrap_or_else(read_failed);
+            self.block.truncate(read);
+            self.pos += read;
+            self.index = 0;
+            if read == 0 {
+                return None;
+            }
+        }
+        self.index += 1;
+        Some(self.block[self.index - 1])
+    }
+}
+
+impl Source for Forward {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.file.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.file.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let width = usize::from(self.file.channels.max(1));
+        (!self.repeat).then(|| frames_duration((self.end - self.start) / width, self.file.sample_rate))
+    }
+}
+
+/// A spill file's frames from last to first, read back a block at a time.
+struct Reversed {
+    file: Arc<SpillFile>,
+    frames: usize,
+    /// Frames not read yet, from the start of the file.
+    remaining: usize,
+    block: Vec<i16>,
+    index: usize,
+}
+
+impl Reversed {
+    fn new(file: Arc<SpillFile>, frames: usize) -> Self {
+        Self {
+            file,
+            frames,
+            remaining: frames,
+            block: Vec::new(),
+            index: 0,
+        }
+    }
+}
+
+impl Iterator for Reversed {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.index == self.block.len() {
+            if self.remaining == 0 {
+                return None;
+            }
+            let width = usize::from(self.file.channels.max(1));
+            let frames = self.remaining.min(READ_BLOCK_FRAMES);
+            self.remaining -= frames;
+            self.block.resize(frames * width, 0);
+            let read = self.file.read_samples(self.remaining * width, &mut self.block).unwrap_or_else(read_failed);
+            if read < self.block.len() {
+                self.remaining = 0;
+                self.block.clear();
+                return None;
+            }
+            self.block.reverse();
+            for frame in self.block.chunks_exact_mut(width) {
+                frame.reverse();
+            }
+            self.index = 0;
+        }
+        self.index += 1;
+        Some(self.block[self.index - 1])
+    }
+}
+
+impl Source for Reversed {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.file.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.file.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(frames_duration(self.frames, self.file.sample_rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(samples: &[i16]) -> BoxedSource {
+        Box::new(rodio::buffer::SamplesBuffer::new(2, 8000, samples.to_vec()))
+    }
+
+    fn spilled(samples: &[i16]) -> (WholeStream, PathBuf) {
+        let mut spill = Spill::create(&std::env::temp_dir(), 2, 8000).unwrap();
+        let path = spill.path().to_path_buf();
+        spill.write(source(samples)).unwrap();
+        assert_eq!(spill.len().unwrap(), samples.len() as u64 * 2);
+        (WholeStream::Disk(spill), path)
+    }
+
+    #[test]
+    fn spilled_stream_plays_back_from_disk() {
+        // Long enough to be read back in several blocks.
+        let samples: Vec<i16> = (0..READ_BLOCK_FRAMES as i16 * 5).collect();
+        let (stream, path) = spilled(&samples);
+        assert_eq!(stream.frames(), samples.len() / 2);
+        let reversed: Vec<i16> = stream.into_reversed().unwrap().collect();
+        let mut expected = Pcm {
+            channels: 2,
+            sample_rate: 8000,
+            samples: samples.clone(),
+        };
+        expected.reverse();
+        assert_eq!(reversed, expected.samples);
+        // Unlinked up front on Unix, otherwise once the readers are gone.
+        assert!(!path.exists());
+
+        let (stream, _) = spilled(&[1, -1, 2, -2, 3, -3, 4, -4]);
+        let (lead_in, looped) = stream.into_loop(1, 2).unwrap();
+        assert_eq!(lead_in.collect::<Vec<_>>(), vec![1, -1]);
+        assert_eq!(looped.take(10).collect::<Vec<_>>(), vec![2, -2, 3, -3, 2, -2, 3, -3, 2, -2]);
+    }
+}
@@ -0,0 +1,88 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::input::Chunk;
+
+/// Presents the payloads of received chunks as one continuous byte stream,
+/// so a single decoder can run across chunk boundaries. Reads block until
+/// the next chunk arrives and hit end of stream once the sender hangs up.
+///
+/// Only seeks that land on the current position are supported; decoders
+/// use those to query where they are.
+pub struct ChunkStream {
+    // `Mutex` only to make the reader `Sync`, which rodio's decoders require.
+    rx: Mutex<Receiver<Chunk>>,
+    buf: Vec<u8>,
+    pos: usize,
+    offset: u64,
+    chunks: usize,
+}
+
+impl ChunkStream {
+    pub fn new(rx: Receiver<Chunk>) -> Self {
+        Self {
+            rx: Mutex::new(rx),
+            buf: Vec::new(),
+            pos: 0,
+            offset: 0,
+            chunks: 0,
+        }
+    }
+}
+
+impl Read for ChunkStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            let Ok(chunk) = self.rx.get_mut().unwrap().recv() else {
+                return Ok(0);
+            };
+            self.chunks += 1;
+            debug!("Streaming audio chunk {}, size: {} bytes", self.chunks, chunk.data.len());
+            self.buf = chunk.data;
+            self.pos = 0;
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for ChunkStream {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match pos {
+            SeekFrom::Start(offset) if offset == self.offset => Ok(self.offset),
+            SeekFrom::Current(0) => Ok(self.offset),
+            _ => Err(io::Error::new(io::ErrorKind::Unsupported, "chunk streams can't seek")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn reads_across_chunks() {
+        let (tx, rx) = mpsc::channel();
+        for data in [vec![1, 2, 3], vec![], vec![4, 5]] {
+            tx.send(Chunk { encoded_len: data.len(), data, format: None, gain: None, ts: None, seq: None }).unwrap();
+        }
+        drop(tx);
+
+        let mut stream = ChunkStream::new(rx);
+        let mut two = [0; 2];
+        stream.read_exact(&mut two).unwrap();
+        assert_eq!(two, [1, 2]);
+        assert_eq!(stream.stream_position().unwrap(), 2);
+        assert!(stream.seek(SeekFrom::Start(0)).is_err());
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [3, 4, 5]);
+    }
+}
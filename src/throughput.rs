@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// How far back `--stats-interval` averages over.
+pub const WINDOW: Duration = Duration::from_secs(5);
+/// Granularity of the counter; traffic within one bucket is summed.
+const BUCKET: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    start: Instant,
+    bytes: u64,
+    chunks: u64,
+}
+
+/// Decoded bytes and chunks sent over the last [`WINDOW`], kept in
+/// one-second buckets.
+#[derive(Debug)]
+pub struct Throughput {
+    started: Instant,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Self::starting_at(Instant::now())
+    }
+
+    fn starting_at(now: Instant) -> Self {
+        Self {
+            started: now,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, bytes: usize) {
+        self.record_at(Instant::now(), bytes);
+    }
+
+    fn record_at(&mut self, now: Instant, bytes: usize) {
+        self.prune(now);
+        match self.buckets.back_mut() {
+            Some(bucket) if now.duration_since(bucket.start) < BUCKET => {
+                bucket.bytes += bytes as u64;
+                bucket.chunks += 1;
+            }
+            _ => self.buckets.push_back(Bucket {
+                start: now,
+                bytes: bytes as u64,
+                chunks: 1,
+            }),
+        }
+    }
+
+    /// Average bytes/sec and chunks/sec over the window, or over the time
+    /// since the counter started if that's shorter.
+    pub fn rates(&mut self) -> (f64, f64) {
+        self.rates_at(Instant::now())
+    }
+
+    fn rates_at(&mut self, now: Instant) -> (f64, f64) {
+        self.prune(now);
+        let span = now.duration_since(self.started).min(WINDOW).max(BUCKET).as_secs_f64();
+        let (bytes, chunks) = self
+            .buckets
+            .iter()
+            .fold((0, 0), |(bytes, chunks), bucket| (bytes + bucket.bytes, chunks + bucket.chunks));
+        (bytes as f64 / span, chunks as f64 / span)
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| now.duration_since(bucket.start) >= WINDOW)
+        {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Logs the rolling rates every `interval` until `throughput` has no other
+/// owners left.
+pub fn spawn_logger(throughput: Arc<Mutex<Throughput>>, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if Arc::strong_count(&throughput) == 1 {
+            break;
+        }
+        let (bytes, chunks) = throughput.lock().unwrap().rates();
+        info!(
+            "Throughput over the last {}s: {:.0} bytes/s ({:.1} kbit/s), {:.1} chunks/s",
+            WINDOW.as_secs(),
+            bytes,
+            bytes * 8.0 / 1000.0,
+            chunks
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_counter_tracks_recent_traffic() {
+        let start = Instant::now();
+        let mut throughput = Throughput::starting_at(start);
+        assert_eq!(throughput.rates_at(start), (0.0, 0.0));
+
+        throughput.record_at(start, 1000);
+        throughput.record_at(start + Duration::from_millis(500), 1000);
+        assert_eq!(throughput.buckets.len(), 1);
+        assert_eq!(throughput.rates_at(start + BUCKET), (2000.0, 2.0));
+
+        throughput.record_at(start + Duration::from_secs(4), 3000);
+        assert_eq!(throughput.rates_at(start + Duration::from_secs(4)), (5000.0 / 4.0, 3.0 / 4.0));
+
+        // The first bucket has aged out of the window.
+        assert_eq!(throughput.rates_at(start + Duration::from_secs(6)), (3000.0 / 5.0, 1.0 / 5.0));
+        assert_eq!(throughput.rates_at(start + Duration::from_secs(10)), (0.0, 0.0));
+    }
+}
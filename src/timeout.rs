@@ -0,0 +1,107 @@
+use std::io::{self, Read};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Wraps a blocking reader so a read that waits longer than `timeout`
+/// fails with `ErrorKind::TimedOut`. The underlying reads happen on a
+/// helper thread, which is left blocked if the timeout fires.
+pub struct IdleTimeout {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    pos: usize,
+    timeout: Duration,
+}
+
+impl IdleTimeout {
+    pub fn spawn<R: Read + Send + 'static>(mut reader: R, timeout: Duration) -> Self {
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::spawn(move || loop {
+            let mut buf = vec![0; 8192];
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf.truncate(n);
+                    if tx.send(Ok(buf)).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    break;
+                }
+            }
+        });
+        Self {
+            rx,
+            buf: Vec::new(),
+            pos: 0,
+            timeout,
+        }
+    }
+}
+
+impl Read for IdleTimeout {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            match self.rx.recv_timeout(self.timeout) {
+                Ok(result) => {
+                    self.buf = result?;
+                    self.pos = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no input for {:?}", self.timeout),
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    /// Delivers `data` once, then hangs like a silent socket.
+    struct Stall(Option<&'static [u8]>);
+
+    impl Read for Stall {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            match self.0.take() {
+                Some(data) => {
+                    out[..data.len()].copy_from_slice(data);
+                    Ok(data.len())
+                }
+                None => {
+                    thread::sleep(Duration::from_secs(60));
+                    Ok(0)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn times_out_after_last_line() {
+        let reader = IdleTimeout::spawn(Stall(Some(b"one\ntwo\n")), Duration::from_millis(50));
+        let mut lines = BufReader::new(reader).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "one");
+        assert_eq!(lines.next().unwrap().unwrap(), "two");
+        assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn eof_is_not_a_timeout() {
+        let reader = IdleTimeout::spawn(&b"one\n"[..], Duration::from_millis(50));
+        let lines: Vec<_> = BufReader::new(reader).lines().collect::<io::Result<_>>().unwrap();
+        assert_eq!(lines, vec!["one"]);
+    }
+}
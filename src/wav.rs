@@ -0,0 +1,791 @@
+use std::time::Duration;
+
+/// Reads a little-endian u16 at `pos`, or `None` when out of bounds.
+pub fn read_u16_le(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Reads a little-endian u32 at `pos`, or `None` when out of bounds.
+pub fn read_u32_le(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads a big-endian u16 at `pos`, or `None` when out of bounds.
+pub fn read_u16_be(data: &[u8], pos: usize) -> Option<u16> {
+    read_u16_le(data, pos).map(u16::swap_bytes)
+}
+
+/// Reads a big-endian u32 at `pos`, or `None` when out of bounds.
+pub fn read_u32_be(data: &[u8], pos: usize) -> Option<u32> {
+    read_u32_le(data, pos).map(u32::swap_bytes)
+}
+
+/// Whether a buffer is a big-endian `RIFX` file rather than a `RIFF` one;
+/// `None` if it's neither.
+pub fn is_rifx(data: &[u8]) -> Option<bool> {
+    if data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    match data.get(0..4)? {
+        b"RIFF" => Some(false),
+        b"RIFX" => Some(true),
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize, rifx: bool) -> Option<u16> {
+    if rifx {
+        read_u16_be(data, pos)
+    } else {
+        read_u16_le(data, pos)
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize, rifx: bool) -> Option<u32> {
+    if rifx {
+        read_u32_be(data, pos)
+    } else {
+        read_u32_le(data, pos)
+    }
+}
+
+fn write_u32(data: &mut [u8], pos: usize, value: u32, rifx: bool) {
+    if rifx {
+        write_u32_be(data, pos, value)
+    } else {
+        write_u32_le(data, pos, value)
+    }
+}
+
+/// The parts of a WAV `fmt ` chunk (and the `data` chunk length) needed for
+/// timing decisions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavInfo {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub byte_rate: u32,
+    /// `fmt ` format code: 1 for integer PCM, [`FORMAT_IEEE_FLOAT`] for floats.
+    /// For [`FORMAT_EXTENSIBLE`] this is the code its sub-format GUID wraps.
+    pub format_tag: u16,
+    pub bits_per_sample: u16,
+    /// Bytes per frame, across all channels.
+    pub block_align: u16,
+    /// A `RIFX` file, whose sizes, fields and samples are big-endian.
+    pub rifx: bool,
+    /// Length of the audio payload actually present in the buffer, which may
+    /// be shorter than what the `data` chunk header claims.
+    pub data_len: usize,
+}
+
+impl WavInfo {
+    /// Playback duration of `bytes` bytes of PCM in this format.
+    pub fn duration_of(&self, bytes: usize) -> Duration {
+        if self.byte_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(bytes as f64 / self.byte_rate as f64)
+    }
+
+    /// Playback duration of the data chunk.
+    pub fn duration(&self) -> Duration {
+        self.duration_of(self.data_len)
+    }
+}
+
+/// Writes a little-endian u32 at `pos`. The caller guarantees the bounds.
+pub fn write_u32_le(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Writes a big-endian u32 at `pos`. The caller guarantees the bounds.
+pub fn write_u32_be(data: &mut [u8], pos: usize, value: u32) {
+    data[pos..pos + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+/// `fmt ` format code of IEEE float samples.
+pub const FORMAT_IEEE_FLOAT: u16 = 3;
+
+/// `fmt ` format code of WAVE_FORMAT_EXTENSIBLE, whose 40-byte `fmt ` chunk
+/// carries the real format as a sub-format GUID.
+pub const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The bytes after the leading format code shared by every sub-format GUID
+/// derived from a plain format code (`0000xxxx-0000-0010-8000-00aa00389b71`).
+const SUBFORMAT_GUID_TAIL: [u8; 14] = [0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0xAA, 0, 0x38, 0x9B, 0x71];
+
+/// The format code of the `fmt ` chunk at `body`, looking through
+/// WAVE_FORMAT_EXTENSIBLE to its sub-format. A GUID that doesn't wrap a
+/// format code leaves the extensible code as is.
+fn format_code(data: &[u8], body: usize, size: usize, rifx: bool) -> Option<u16> {
+    let tag = read_u16(data, body, rifx)?;
+    if tag != FORMAT_EXTENSIBLE || size < 40 {
+        return Some(tag);
+    }
+    let guid = data.get(body + 24..body + 40)?;
+    if guid[2..] == SUBFORMAT_GUID_TAIL {
+        return read_u16_le(guid, 0);
+    }
+    Some(tag)
+}
+
+/// Position of the `data` chunk within a RIFF/WAVE buffer.
+struct DataChunk {
+    /// The `fmt ` chunk, if it precedes `data`. `data_len` is left at 0.
+    format: Option<WavInfo>,
+    /// Offset of the `fmt ` chunk's body, alongside `format`.
+    format_body: usize,
+    /// Offset of the first audio byte, i.e. the header length.
+    body: usize,
+    /// Size claimed by the `data` chunk header.
+    size: usize,
+    rifx: bool,
+}
+
+fn find_data_chunk(data: &[u8]) -> Option<DataChunk> {
+    let rifx = is_rifx(data)?;
+
+    let mut pos = 12;
+    let mut format = None;
+    let mut format_body = 0;
+
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = read_u32(data, pos + 4, rifx)? as usize;
+        let body = pos + 8;
+
+        if id == b"fmt " {
+            format = Some(WavInfo {
+                format_tag: format_code(data, body, size, rifx)?,
+                channels: read_u16(data, body + 2, rifx)?,
+                sample_rate: read_u32(data, body + 4, rifx)?,
+                byte_rate: read_u32(data, body + 8, rifx)?,
+                block_align: read_u16(data, body + 12, rifx)?,
+                bits_per_sample: read_u16(data, body + 14, rifx)?,
+                rifx,
+                data_len: 0,
+            });
+            format_body = body;
+        } else if id == b"data" {
+            return Some(DataChunk {
+                format,
+                format_body,
+                body,
+                size,
+                rifx,
+            });
+        }
+
+        // Chunks are padded to an even size. A chunk before `data` must fit
+        // in the buffer; a size running past it is bogus, not streaming.
+        pos = body.checked_add(size)?.checked_add(size % 2)?;
+        if pos > data.len() {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Walks the RIFF chunks of a complete WAV file and returns its format info.
+/// Returns `None` if the buffer isn't a RIFF/WAVE file or lacks `fmt `/`data`.
+pub fn parse_wav_info(data: &[u8]) -> Option<WavInfo> {
+    let chunk = find_data_chunk(data)?;
+    Some(WavInfo {
+        data_len: chunk.size.min(data.len() - chunk.body),
+        ..chunk.format?
+    })
+}
+
+/// Splits a WAV file into its header (everything up to and including the
+/// `data` chunk id and size) and the audio bytes that follow. A `data` size
+/// beyond the buffer, like the `0xFFFFFFFF` streaming writers use, is cut
+/// to the bytes present.
+pub fn extract_wav_header(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let chunk = find_data_chunk(data)?;
+    let end = chunk.body.checked_add(chunk.size).map_or(data.len(), |end| end.min(data.len()));
+    Some((&data[..chunk.body], &data[chunk.body..end]))
+}
+
+/// Offset of the `data` chunk's size field, found by walking the chunk list
+/// so any chunks (`fact`, `LIST`, ...) may precede it.
+pub fn data_size_offset(header: &[u8]) -> Option<usize> {
+    find_data_chunk(header).map(|chunk| chunk.body - 4)
+}
+
+/// The RIFF and `data` sizes a header claims, which stale producers may
+/// get wrong.
+pub fn declared_sizes(header: &[u8]) -> Option<(u32, u32)> {
+    let rifx = is_rifx(header)?;
+    let riff_size = read_u32(header, 4, rifx)?;
+    let data_size = read_u32(header, data_size_offset(header)?, rifx)?;
+    Some((riff_size, data_size))
+}
+
+/// Rewrites the RIFF size of a header, in its byte order. The caller
+/// guarantees it's at least 8 bytes.
+pub fn write_riff_size(header: &mut [u8], riff_size: u32) {
+    write_u32(header, 4, riff_size, header.starts_with(b"RIFX"));
+}
+
+/// A sustain loop from a `smpl` chunk, in frames from the start of the
+/// audio. Both ends are played: `end` is the last frame of the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleLoop {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The first loop of a WAV file's `smpl` chunk, for `--loop`. `cue`
+/// chunks only mark single points, so they don't define a loop on their
+/// own and are skipped like any other chunk.
+pub fn sample_loop(data: &[u8]) -> Option<SampleLoop> {
+    let rifx = is_rifx(data)?;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size = read_u32(data, pos + 4, rifx)? as usize;
+        let body = pos + 8;
+        if &data[pos..pos + 4] == b"smpl" {
+            // Nine u32 fields, the seventh the loop count, then 24 bytes
+            // per loop: cue id, type, start, end, fraction and play count.
+            if read_u32(data, body + 28, rifx)? == 0 {
+                return None;
+            }
+            return Some(SampleLoop {
+                start: read_u32(data, body + 44, rifx)?,
+                end: read_u32(data, body + 48, rifx)?,
+            });
+        }
+        pos = body.checked_add(size)?.checked_add(size % 2)?;
+    }
+    None
+}
+
+/// Rewrites the RIFF and `data` sizes of a header produced by
+/// [`extract_wav_header`] for `data_len` bytes of audio following it.
+/// Anything shorter than a RIFF header is left alone.
+pub fn patch_wav_sizes(header: &mut [u8], data_len: u32) {
+    let Some(overhead) = header.len().checked_sub(8) else {
+        return;
+    };
+    let riff_size = (overhead as u32).saturating_add(data_len);
+    write_riff_size(header, riff_size);
+    if let Some(offset) = data_size_offset(header) {
+        write_u32(header, offset, data_len, header.starts_with(b"RIFX"));
+    }
+}
+
+/// Rewrites the sample width in a header's `fmt ` chunk, along with the
+/// block align and byte rate that follow from it. Returns `false`, leaving
+/// the header alone, when it has no `fmt ` chunk or the block align or byte
+/// rate don't fit their fields.
+pub fn set_bits_per_sample(header: &mut [u8], bits_per_sample: u16) -> bool {
+    let Some(DataChunk {
+        format: Some(info),
+        format_body: body,
+        rifx,
+        ..
+    }) = find_data_chunk(header)
+    else {
+        return false;
+    };
+    let u16_bytes = |value: u16| if rifx { value.to_be_bytes() } else { value.to_le_bytes() };
+    let Some((block_align, byte_rate)) = frame_sizes(info.channels, info.sample_rate, bits_per_sample) else {
+        return false;
+    };
+    write_u32(header, body + 8, byte_rate, rifx);
+    header[body + 12..body + 14].copy_from_slice(&u16_bytes(block_align));
+    header[body + 14..body + 16].copy_from_slice(&u16_bytes(bits_per_sample));
+    if read_u16(header, body, rifx) == Some(FORMAT_EXTENSIBLE) && header.len() >= body + 20 {
+        // wValidBitsPerSample would otherwise still claim the old width.
+        header[body + 18..body + 20].copy_from_slice(&u16_bytes(bits_per_sample));
+    }
+    true
+}
+
+/// Checks a WAV chunk against the spec for `--wav-validate`, returning
+/// every problem found. Unlike the parsers above, which accept whatever
+/// they can make sense of, this flags anything a strict reader would reject.
+pub fn validate(data: &[u8]) -> Vec<String> {
+    let Some(rifx) = is_rifx(data) else {
+        return vec!["not a RIFF/WAVE file".to_string()];
+    };
+
+    let mut problems = Vec::new();
+    let riff_size = read_u32(data, 4, rifx).unwrap() as usize;
+    if riff_size.checked_add(8) != Some(data.len()) {
+        problems.push(format!(
+            "RIFF size {} doesn't match the {} bytes after the RIFF header",
+            riff_size,
+            data.len() - 8
+        ));
+    }
+
+    let mut fmt = None;
+    let mut has_data = false;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let size = read_u32(data, pos + 4, rifx).unwrap() as usize;
+        let body = pos + 8;
+        if id == b"fmt " {
+            fmt = Some((size, body));
+        } else if id == b"data" {
+            has_data = true;
+            if fmt.is_none() {
+                problems.push("data chunk comes before the fmt chunk".to_string());
+            }
+            break;
+        }
+        pos = match body.checked_add(size + size % 2) {
+            Some(next) if next <= data.len() => next,
+            _ => {
+                problems.push(format!(
+                    "{} chunk claims {} bytes, past the end of the file",
+                    String::from_utf8_lossy(id).trim_end(),
+                    size
+                ));
+                break;
+            }
+        };
+    }
+
+    match fmt {
+        None => problems.push("no fmt chunk".to_string()),
+        Some((size, _)) if ![16, 18, 40].contains(&size) => {
+            problems.push(format!("fmt chunk is {} bytes, not 16, 18 or 40", size));
+        }
+        Some((_, body)) => {
+            let field = |offset| read_u16(data, body + offset, rifx).unwrap_or_default();
+            let (channels, block_align, bits) = (field(2), field(12), field(14));
+            if u32::from(block_align) != u32::from(channels) * u32::from(bits) / 8 {
+                problems.push(format!(
+                    "block_align {} isn't channels ({}) x bits per sample ({}) / 8",
+                    block_align, channels, bits
+                ));
+            }
+        }
+    }
+    if !has_data {
+        problems.push("no data chunk".to_string());
+    }
+    problems
+}
+
+/// Builds a complete WAV file from a header and a body. The body replaces
+/// whatever the header's `data` chunk held; chunks after `data` are kept
+/// after the new body.
+pub fn reconstruct_wav_file(header: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut file = Vec::with_capacity(header.len() + body.len() + 1);
+
+    let Some(chunk) = find_data_chunk(header) else {
+        file.extend_from_slice(header);
+        file.extend_from_slice(body);
+        return file;
+    };
+
+    let old_end = chunk.body.saturating_add(chunk.size).min(header.len());
+    let mut trailer = &header[old_end..];
+    if chunk.size % 2 == 1 && !trailer.is_empty() {
+        trailer = &trailer[1..];
+    }
+
+    file.extend_from_slice(&header[..chunk.body]);
+    file.extend_from_slice(body);
+    if body.len() % 2 == 1 && !trailer.is_empty() {
+        file.push(0);
+    }
+    file.extend_from_slice(trailer);
+
+    write_u32(&mut file, chunk.body - 4, body.len() as u32, chunk.rifx);
+    let riff_size = file.len() as u32 - 8;
+    write_u32(&mut file, 4, riff_size, chunk.rifx);
+    file
+}
+
+/// How much of a reconstructed file `--trace-reconstruction` logs: the
+/// canonical header plus the first samples.
+pub const TRACE_BYTES: usize = 64;
+
+/// The first [`TRACE_BYTES`] of `data` as hex, with `...` if there is more.
+pub fn hex_prefix(data: &[u8]) -> String {
+    let mut text = hex::encode(&data[..data.len().min(TRACE_BYTES)]);
+    if data.len() > TRACE_BYTES {
+        text.push_str("...");
+    }
+    text
+}
+
+/// The block align and byte rate of a PCM layout, if they fit the 16 and
+/// 32-bit fields that hold them.
+fn frame_sizes(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Option<(u16, u32)> {
+    let block_align = u16::try_from(u32::from(channels) * u32::from(bits_per_sample) / 8).ok()?;
+    Some((block_align, sample_rate.checked_mul(u32::from(block_align))?))
+}
+
+/// A canonical 44-byte PCM header with zero sizes, to be patched with
+/// [`patch_wav_sizes`]. `None` when the block align or byte rate would
+/// overflow their fields.
+pub fn pcm_header(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Option<Vec<u8>> {
+    let (block_align, byte_rate) = frame_sizes(channels, sample_rate, bits_per_sample)?;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&36u32.to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&0u32.to_le_bytes());
+    Some(header)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds a 16-bit PCM WAV file containing `body`.
+    pub(crate) fn wav_file(channels: u16, sample_rate: u32, body: &[u8]) -> Vec<u8> {
+        reconstruct_wav_file(&pcm_header(channels, sample_rate, 16).unwrap(), body)
+    }
+
+    /// Builds a mono 8 kHz WAVE_FORMAT_EXTENSIBLE file whose sub-format GUID
+    /// wraps `sub_format`.
+    pub(crate) fn extensible_file(sub_format: u16, bits_per_sample: u16, body: &[u8]) -> Vec<u8> {
+        let canonical = pcm_header(1, 8000, bits_per_sample).unwrap();
+        let mut header = canonical[..16].to_vec();
+        header.extend_from_slice(&40u32.to_le_bytes());
+        header.extend_from_slice(&FORMAT_EXTENSIBLE.to_le_bytes());
+        header.extend_from_slice(&canonical[22..36]);
+        header.extend_from_slice(&22u16.to_le_bytes());
+        header.extend_from_slice(&bits_per_sample.to_le_bytes());
+        // Channel mask: front center.
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&sub_format.to_le_bytes());
+        header.extend_from_slice(&SUBFORMAT_GUID_TAIL);
+        header.extend_from_slice(&canonical[36..]);
+        reconstruct_wav_file(&header, body)
+    }
+
+    /// Builds a big-endian `RIFX` version of [`wav_file`]; `body` is
+    /// written as is.
+    pub(crate) fn rifx_file(channels: u16, sample_rate: u32, body: &[u8]) -> Vec<u8> {
+        let mut file = wav_file(channels, sample_rate, body);
+        file[3] = b'X';
+        for pos in [4, 16, 24, 28, 40] {
+            file[pos..pos + 4].reverse();
+        }
+        for pos in [20, 22, 32, 34] {
+            file[pos..pos + 2].reverse();
+        }
+        file
+    }
+
+    /// Builds a mono 8 kHz 16-bit file whose `smpl` chunk, ahead of the
+    /// audio, loops frames `start..=end`.
+    pub(crate) fn looped_file(body: &[u8], start: u32, end: u32) -> Vec<u8> {
+        let mut smpl = vec![0; 28];
+        smpl.extend_from_slice(&1u32.to_le_bytes());
+        smpl.extend_from_slice(&[0; 12]);
+        smpl.extend_from_slice(&start.to_le_bytes());
+        smpl.extend_from_slice(&end.to_le_bytes());
+        smpl.extend_from_slice(&[0; 8]);
+        let header = pcm_header(1, 8000, 16).unwrap();
+        riff(&[(b"fmt ", &header[20..36]), (b"smpl", &smpl), (b"data", body)])
+    }
+
+    /// Builds a RIFF/WAVE file from `(id, body)` chunks, padding odd sizes.
+    fn riff(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut out = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, body) in chunks {
+            out.extend_from_slice(*id);
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                out.push(0);
+            }
+        }
+        let riff_size = out.len() as u32 - 8;
+        write_u32_le(&mut out, 4, riff_size);
+        out
+    }
+
+    /// An IMA ADPCM `fmt ` chunk body with its extension: mono, 8 kHz, 4000 bytes/s.
+    const ADPCM_FMT: [u8; 20] = [
+        0x11, 0x00, 0x01, 0x00, 0x40, 0x1f, 0x00, 0x00, 0xa0, 0x0f, 0x00, 0x00, 0x00, 0x01, 0x04,
+        0x00, 0x02, 0x00, 0xf9, 0x01,
+    ];
+
+    #[test]
+    fn parses_fmt_and_data() {
+        let file = wav_file(2, 8000, &[0; 3200]);
+        let info = parse_wav_info(&file).unwrap();
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.sample_rate, 8000);
+        assert_eq!(info.byte_rate, 32000);
+        assert_eq!(info.data_len, 3200);
+        assert_eq!(info.duration(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn reads_extensible_sub_format() {
+        let file = extensible_file(1, 16, &[1, 0, 2, 0]);
+        let info = parse_wav_info(&file).unwrap();
+        assert_eq!((info.format_tag, info.channels, info.bits_per_sample, info.data_len), (1, 1, 16, 4));
+        assert!(validate(&file).is_empty());
+
+        let float = extensible_file(FORMAT_IEEE_FLOAT, 32, &[0; 4]);
+        assert_eq!(parse_wav_info(&float).unwrap().format_tag, FORMAT_IEEE_FLOAT);
+
+        // A GUID that isn't derived from a format code stays extensible.
+        let mut unknown = file.clone();
+        unknown[47] ^= 0xFF;
+        assert_eq!(parse_wav_info(&unknown).unwrap().format_tag, FORMAT_EXTENSIBLE);
+    }
+
+    #[test]
+    fn rejects_non_wav() {
+        assert_eq!(parse_wav_info(b"ID3\x03\x00\x00\x00\x00\x00\x00\x00\x00"), None);
+        assert_eq!(parse_wav_info(&[]), None);
+    }
+
+    #[test]
+    fn extracts_and_reconstructs() {
+        let file = wav_file(1, 8000, &[1, 2, 3, 4]);
+        let (header, body) = extract_wav_header(&file).unwrap();
+        assert_eq!(header.len(), 44);
+        assert_eq!(body, &[1, 2, 3, 4]);
+
+        let rebuilt = reconstruct_wav_file(header, &[9, 9]);
+        assert_eq!(read_u32_le(&rebuilt, 4), Some(38));
+        assert_eq!(read_u32_le(&rebuilt, 40), Some(2));
+        assert_eq!(&rebuilt[44..], &[9, 9]);
+    }
+
+    #[test]
+    fn fact_chunk_stays_in_header() {
+        let fact = 2000u32.to_le_bytes();
+        let file = riff(&[(b"fmt ", &ADPCM_FMT), (b"fact", &fact), (b"data", &[7; 10])]);
+
+        let (header, body) = extract_wav_header(&file).unwrap();
+        assert_eq!(header.len(), 12 + 8 + ADPCM_FMT.len() + 8 + 4 + 8);
+        assert_eq!(&header[header.len() - 8..header.len() - 4], b"data");
+        assert_eq!(body, &[7; 10]);
+
+        let rebuilt = reconstruct_wav_file(header, &[1, 2, 3, 4]);
+        assert_eq!(
+            rebuilt,
+            riff(&[(b"fmt ", &ADPCM_FMT), (b"fact", &fact), (b"data", &[1, 2, 3, 4])])
+        );
+        assert_eq!(parse_wav_info(&rebuilt).unwrap().data_len, 4);
+        assert_eq!(read_u32_le(&rebuilt, 4), Some(rebuilt.len() as u32 - 8));
+    }
+
+    #[test]
+    fn parses_and_rebuilds_big_endian_rifx() {
+        let file = rifx_file(2, 44100, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(read_u32_be(&file, 40), Some(8));
+        let info = parse_wav_info(&file).unwrap();
+        assert!(info.rifx);
+        assert_eq!((info.channels, info.sample_rate, info.block_align, info.data_len), (2, 44100, 4, 8));
+        let (header, body) = extract_wav_header(&file).unwrap();
+        assert_eq!((header.len(), body.len()), (44, 8));
+        assert_eq!(declared_sizes(header), Some((44, 8)));
+
+        let rebuilt = reconstruct_wav_file(header, &[9; 4]);
+        assert_eq!(rebuilt, rifx_file(2, 44100, &[9; 4]));
+        assert_eq!(read_u32_be(&rebuilt, 4), Some(40));
+        let mut patched = header.to_vec();
+        patch_wav_sizes(&mut patched, 100);
+        assert_eq!(declared_sizes(&patched), Some((136, 100)));
+    }
+
+    #[test]
+    fn reads_the_smpl_loop() {
+        let file = looped_file(&[0; 8], 1, 2);
+        assert_eq!(sample_loop(&file), Some(SampleLoop { start: 1, end: 2 }));
+        assert_eq!(parse_wav_info(&file).unwrap().data_len, 8);
+
+        // After the audio too, past other chunks.
+        let smpl = &file[36..36 + 8 + 60];
+        let mut trailing = wav_file(1, 8000, &[0; 4]);
+        trailing.extend_from_slice(smpl);
+        assert_eq!(sample_loop(&trailing), Some(SampleLoop { start: 1, end: 2 }));
+        assert_eq!(sample_loop(&wav_file(1, 8000, &[0; 4])), None);
+    }
+
+    #[test]
+    fn odd_sized_chunks_before_data_are_skipped() {
+        let file = riff(&[(b"LIST", b"abc"), (b"fmt ", &ADPCM_FMT), (b"data", &[1, 2])]);
+        let (header, body) = extract_wav_header(&file).unwrap();
+        assert_eq!(body, &[1, 2]);
+        assert_eq!(reconstruct_wav_file(header, body), file);
+        assert_eq!(parse_wav_info(&file).unwrap().byte_rate, 4000);
+    }
+
+    #[test]
+    fn data_chunk_before_trailing_chunks() {
+        // A template whose empty `data` chunk is followed by metadata.
+        let header = riff(&[(b"fmt ", &ADPCM_FMT), (b"data", &[]), (b"LIST", b"INFOabcd")]);
+        assert_eq!(data_size_offset(&header), Some(12 + 8 + ADPCM_FMT.len() + 4));
+
+        let rebuilt = reconstruct_wav_file(&header, &[1, 2, 3]);
+        assert_eq!(
+            rebuilt,
+            riff(&[(b"fmt ", &ADPCM_FMT), (b"data", &[1, 2, 3]), (b"LIST", b"INFOabcd")])
+        );
+        assert_eq!(extract_wav_header(&rebuilt).unwrap().1, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn replaces_existing_data_in_header() {
+        let file = riff(&[(b"fmt ", &ADPCM_FMT), (b"data", &[9; 5]), (b"cue ", &[0; 4])]);
+        let rebuilt = reconstruct_wav_file(&file, &[1, 2]);
+        assert_eq!(
+            rebuilt,
+            riff(&[(b"fmt ", &ADPCM_FMT), (b"data", &[1, 2]), (b"cue ", &[0; 4])])
+        );
+    }
+
+    #[test]
+    fn patches_located_size_field() {
+        let mut header = riff(&[(b"fmt ", &ADPCM_FMT), (b"fact", &[0; 4]), (b"data", &[])]);
+        patch_wav_sizes(&mut header, 100);
+        let offset = data_size_offset(&header).unwrap();
+        assert_eq!(read_u32_le(&header, offset), Some(100));
+        assert_eq!(read_u32_le(&header, 4), Some(header.len() as u32 - 8 + 100));
+    }
+
+    #[test]
+    fn huge_chunk_sizes_do_not_panic() {
+        let mut file = riff(&[(b"LIST", &[0; 4]), (b"fmt ", &ADPCM_FMT), (b"data", &[1, 2])]);
+        write_u32_le(&mut file, 16, u32::MAX - 1);
+        assert_eq!(extract_wav_header(&file), None);
+        assert_eq!(parse_wav_info(&file), None);
+
+        // Streaming writers leave the data size at its maximum.
+        let mut streaming = wav_file(1, 8000, &[1, 0, 2, 0]);
+        write_u32_le(&mut streaming, 40, u32::MAX);
+        let (header, body) = extract_wav_header(&streaming).unwrap();
+        assert_eq!((header.len(), body), (44, &[1, 0, 2, 0][..]));
+        assert_eq!(parse_wav_info(&streaming).unwrap().data_len, 4);
+    }
+
+    #[test]
+    fn header_fields_that_overflow_are_refused() {
+        assert_eq!(pcm_header(u16::MAX, 8000, 32), None);
+        assert_eq!(pcm_header(2, u32::MAX, 16), None);
+
+        let original = pcm_header(20000, 8000, 16).unwrap();
+        let mut header = original.clone();
+        // 20000 channels of 32 bits need an 80000-byte block align.
+        assert!(!set_bits_per_sample(&mut header, 32));
+        assert_eq!(header, original);
+        assert!(set_bits_per_sample(&mut header, 24));
+        assert_eq!(parse_wav_info(&header).unwrap().byte_rate, 8000 * 60000);
+    }
+
+    #[test]
+    fn hex_prefix_is_bounded() {
+        let file = wav_file(1, 8000, &[0xab; 1000]);
+        let text = hex_prefix(&file);
+        assert!(text.starts_with("52494646"));
+        assert_eq!(text.len(), TRACE_BYTES * 2 + 3);
+        assert_eq!(hex_prefix(&[1, 2]), "0102");
+    }
+
+    #[test]
+    fn valid_file_passes_validation() {
+        assert!(validate(&wav_file(2, 8000, &[0; 8])).is_empty());
+        assert!(validate(&rifx_file(2, 8000, &[0; 8])).is_empty());
+    }
+
+    #[test]
+    fn validation_reports_each_violation() {
+        let problems = |file: &[u8]| validate(file).join("; ");
+
+        assert_eq!(problems(b"RIFF\0\0\0\0AVI "), "not a RIFF/WAVE file");
+
+        let mut file = rifx_file(1, 8000, &[0; 8]);
+        file[32..34].copy_from_slice(&4u16.to_be_bytes());
+        assert_eq!(problems(&file), "block_align 4 isn't channels (1) x bits per sample (16) / 8");
+
+        let mut file = wav_file(1, 8000, &[0; 8]);
+        write_u32_le(&mut file, 4, 1000);
+        assert_eq!(problems(&file), "RIFF size 1000 doesn't match the 44 bytes after the RIFF header");
+
+        let mut file = wav_file(1, 8000, &[0; 8]);
+        file[32..34].copy_from_slice(&4u16.to_le_bytes());
+        assert_eq!(problems(&file), "block_align 4 isn't channels (1) x bits per sample (16) / 8");
+
+        let mut fmt = pcm_header(1, 8000, 16).unwrap()[20..36].to_vec();
+        fmt.extend_from_slice(&[0; 4]);
+        assert_eq!(
+            problems(&riff(&[(b"fmt ", &fmt), (b"data", &[0; 2])])),
+            "fmt chunk is 20 bytes, not 16, 18 or 40"
+        );
+
+        let fmt = &pcm_header(1, 8000, 16).unwrap()[20..36];
+        assert_eq!(
+            problems(&riff(&[(b"data", &[0; 2]), (b"fmt ", fmt)])),
+            "data chunk comes before the fmt chunk; no fmt chunk"
+        );
+        assert_eq!(problems(&riff(&[(b"fmt ", fmt)])), "no data chunk");
+        assert_eq!(problems(&riff(&[(b"LIST", &[0; 4])])), "no fmt chunk; no data chunk");
+
+        let mut file = riff(&[(b"fmt ", fmt), (b"data", &[0; 2])]);
+        write_u32_le(&mut file, 16, 100);
+        assert!(problems(&file).starts_with("fmt chunk claims 100 bytes, past the end of the file"));
+    }
+
+    /// Runs every parser over `data`; they must not panic, whatever it holds.
+    pub(crate) fn parse_everything(data: &[u8]) {
+        let _ = parse_wav_info(data);
+        let _ = declared_sizes(data);
+        let _ = validate(data);
+        let _ = reconstruct_wav_file(data, &[1, 2, 3]);
+        if let Some((header, body)) = extract_wav_header(data) {
+            let mut header = header.to_vec();
+            patch_wav_sizes(&mut header, body.len() as u32);
+            assert_eq!(extract_wav_header(&reconstruct_wav_file(&header, body)).map(|(_, b)| b), Some(body));
+        }
+        patch_wav_sizes(&mut data.to_vec(), 0);
+    }
+
+    #[test]
+    fn truncated_and_corrupted_input_does_not_panic() {
+        let seeds = [
+            wav_file(2, 8000, &[1, 0, 2, 0, 3]),
+            riff(&[(b"LIST", &[0; 3]), (b"fmt ", &ADPCM_FMT), (b"data", &[7; 10]), (b"id3 ", &[1])]),
+            b"RIFF\0\0\0\0WAVEdata".to_vec(),
+        ];
+        for seed in &seeds {
+            for len in 0..=seed.len() {
+                parse_everything(&seed[..len]);
+            }
+            for pos in 0..seed.len() {
+                for byte in [0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff] {
+                    let mut mutated = seed.clone();
+                    mutated[pos] = byte;
+                    parse_everything(&mutated);
+                }
+            }
+        }
+        for data in fuzz_regressions() {
+            parse_everything(&data);
+        }
+    }
+
+    /// Inputs that once crashed a parser; `fuzz/` finds more.
+    fn fuzz_regressions() -> Vec<Vec<u8>> {
+        vec![
+            // Shorter than a RIFF header: `patch_wav_sizes` underflowed.
+            b"RIFF".to_vec(),
+        ]
+    }
+}